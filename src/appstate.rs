@@ -1,13 +1,219 @@
+use serde::Serialize;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc, PoisonError};
+use std::time::Duration;
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use crate::batch::BatchMonitoringData;
+use crate::canary::CanaryComparison;
 use crate::loadtest::LoadTestMonitoringData;
 use crate::tasks::MonitoringData;
+use crate::utils::error_messages::ErrorMessageCounts;
+use crate::utils::request_history::{HistorySample, RequestHistory};
+use crate::utils::request_metrics::RequestMetrics;
+use crate::utils::response_times::ResponseTimeHistory;
+use crate::websocket::WebSocketMonitoringData;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct AppState {
     /// Monitoring data for load tests, organized by workflow name and then by API URL.
     pub load_test_monitoring_data: Arc<Mutex<HashMap<String, HashMap<String, LoadTestMonitoringData>>>>,
     /// Monitoring data for tasks, organized by workflow name and then by API URL.
     pub task_monitoring_data: Arc<Mutex<HashMap<String, HashMap<String, MonitoringData>>>>,
+    /// Every task's response times across runs, organized like `task_monitoring_data`.
+    pub task_response_times: Arc<Mutex<HashMap<String, HashMap<String, ResponseTimeHistory>>>>,
+    /// Monitoring data for request batches, organized by workflow name and then by batch name.
+    pub batch_monitoring_data: Arc<Mutex<HashMap<String, HashMap<String, BatchMonitoringData>>>>,
+    /// Baseline-versus-canary comparisons, organized by workflow name and then by API name.
+    pub canary_monitoring_data: Arc<Mutex<HashMap<String, HashMap<String, CanaryComparison>>>>,
+    /// Monitoring data for WebSocket load tests, organized by workflow name and then by API name.
+    pub websocket_monitoring_data: Arc<Mutex<HashMap<String, HashMap<String, WebSocketMonitoringData>>>>,
+    /// How often each distinct error message occurred, organized by API URL.
+    pub error_messages: Arc<Mutex<HashMap<String, ErrorMessageCounts>>>,
+    /// Values extracted from responses, organized by workflow name and then by variable name; requests in the
+    /// same workflow refer to them as `${name}`.
+    pub variables: Arc<Mutex<HashMap<String, HashMap<String, String>>>>,
+    /// The most recent requests to each API URL, kept only when `history_size` is set.
+    pub request_history: Arc<Mutex<HashMap<String, RequestHistory>>>,
+    /// Counts of every request sent since the process started, for `/metrics`.
+    pub request_metrics: Arc<Mutex<RequestMetrics>>,
+}
+
+/// A point-in-time copy of every result held in `AppState`, labelled with the run's metadata.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResultsSnapshot {
+    /// Labels describing the run these results belong to.
+    pub run_labels: HashMap<String, String>,
+    pub task_results: HashMap<String, HashMap<String, MonitoringData>>,
+    pub load_test_results: HashMap<String, HashMap<String, LoadTestMonitoringData>>,
+    pub batch_results: HashMap<String, HashMap<String, BatchMonitoringData>>,
+    pub canary_results: HashMap<String, HashMap<String, CanaryComparison>>,
+    pub websocket_results: HashMap<String, HashMap<String, WebSocketMonitoringData>>,
+}
+
+impl AppState {
+    /// Copies the current results out of the state, locking each data set in turn.
+    pub async fn snapshot(&self, run_labels: HashMap<String, String>) -> ResultsSnapshot {
+        ResultsSnapshot {
+            run_labels,
+            task_results: self.task_monitoring_data.lock().await.clone(),
+            load_test_results: self.load_test_monitoring_data.lock().await.clone(),
+            batch_results: self.batch_monitoring_data.lock().await.clone(),
+            canary_results: self.canary_monitoring_data.lock().await.clone(),
+            websocket_results: self.websocket_monitoring_data.lock().await.clone(),
+        }
+    }
+
+    /// A copy of the variables extracted so far in `workflow_name`, for resolving the placeholders of a request.
+    pub async fn variables(&self, workflow_name: &str) -> HashMap<String, String> {
+        self.variables.lock().await.get(workflow_name).cloned().unwrap_or_default()
+    }
+
+    /// Stores values extracted from a response for later requests in `workflow_name`.
+    pub async fn set_variables(&self, workflow_name: &str, variables: impl IntoIterator<Item = (String, String)>) {
+        self.variables.lock().await.entry(workflow_name.to_string()).or_default().extend(variables);
+    }
+
+    /// Appends completed requests to the history of the API at `url`, keeping at most `capacity` of them.
+    pub async fn record_history(&self, url: &str, capacity: usize, samples: impl IntoIterator<Item = HistorySample>) {
+        let mut request_history = self.request_history.lock().await;
+        let history = request_history.entry(url.to_string()).or_default();
+        for sample in samples {
+            history.record(sample, capacity);
+        }
+    }
+
+    /// Counts error messages seen for the API at `url`, across tasks and load tests.
+    pub async fn record_errors<'a>(&self, url: &str, messages: impl IntoIterator<Item = &'a str>) {
+        let mut error_messages = self.error_messages.lock().await;
+        let counts = error_messages.entry(url.to_string()).or_default();
+        for message in messages {
+            counts.record(message);
+        }
+    }
+}
+
+/// What `/health` reports about the runs in progress, and how to stop them, kept apart from `AppState`
+/// so it can be used without waiting on any of its locks.
+#[derive(Debug, Default)]
+pub struct ServerHealth {
+    /// The number of APIs across all workflows; runtime updates replace APIs, so it never changes.
+    pub apis_configured: usize,
+    /// Monitoring runs currently in progress; a triggered run may overlap the scheduled one.
+    active_runs: AtomicUsize,
+    /// Set once a shutdown signal arrives; no new runs are started after it.
+    shutting_down: AtomicBool,
+    /// Cancelled to stop the runs in progress, then replaced so later runs start afresh.
+    stop_runs: std::sync::Mutex<CancellationToken>,
+}
+
+impl ServerHealth {
+    pub fn new(apis_configured: usize) -> Self {
+        ServerHealth { apis_configured, active_runs: AtomicUsize::new(0), shutting_down: AtomicBool::new(false), stop_runs: Default::default() }
+    }
+
+    /// Whether any monitoring run is in progress.
+    pub fn monitoring_active(&self) -> bool {
+        self.active_runs.load(Ordering::SeqCst) > 0
+    }
+
+    /// Counts a monitoring run as in progress until the returned guard is dropped.
+    pub fn begin_run(self: &Arc<Self>) -> ActiveRun {
+        self.active_runs.fetch_add(1, Ordering::SeqCst);
+        ActiveRun(self.clone())
+    }
+
+    /// The token a run starting now should stop on; it is cancelled by the next `stop_runs`.
+    pub fn run_cancellation(&self) -> CancellationToken {
+        self.stop_runs.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Cancels every run in progress, returning how many there were. Runs started afterwards are unaffected.
+    pub fn stop_runs(&self) -> usize {
+        let mut stop_runs = self.stop_runs.lock().unwrap_or_else(PoisonError::into_inner);
+        stop_runs.cancel();
+        *stop_runs = CancellationToken::new();
+        self.active_runs.load(Ordering::SeqCst)
+    }
+
+    /// Marks the server as shutting down, so new runs are refused.
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Waits up to `grace_period` for the runs in progress to finish; returns whether they all did.
+    pub async fn drain(&self, grace_period: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + grace_period;
+        while self.monitoring_active() {
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        true
+    }
+}
+
+/// A monitoring run in progress, counted by `ServerHealth` until dropped.
+pub struct ActiveRun(Arc<ServerHealth>);
+
+impl Drop for ActiveRun {
+    fn drop(&mut self) {
+        self.0.active_runs.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_monitoring_is_active_until_every_run_ends() {
+        let health = Arc::new(ServerHealth::new(3));
+        assert!(!health.monitoring_active());
+
+        let scheduled = health.begin_run();
+        let triggered = health.begin_run();
+        drop(scheduled);
+        assert!(health.monitoring_active());
+        drop(triggered);
+        assert!(!health.monitoring_active());
+    }
+
+    #[test]
+    fn test_stop_runs_only_cancels_runs_already_started() {
+        let health = Arc::new(ServerHealth::new(1));
+        let _run = health.begin_run();
+        let started = health.run_cancellation();
+
+        assert_eq!(health.stop_runs(), 1);
+        assert!(started.is_cancelled());
+        assert!(!health.run_cancellation().is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_runs_within_the_grace_period() {
+        let health = Arc::new(ServerHealth::new(1));
+        let run = health.begin_run();
+        assert!(!health.drain(Duration::from_millis(150)).await);
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            drop(run);
+        });
+        assert!(health.drain(Duration::from_secs(5)).await);
+    }
+
+    #[tokio::test]
+    async fn test_variables_are_scoped_to_their_workflow() {
+        let app_state = AppState::default();
+        app_state.set_variables("Shop", [("token".to_string(), "abc123".to_string())]).await;
+
+        assert_eq!(app_state.variables("Shop").await["token"], "abc123");
+        assert!(app_state.variables("Admin").await.is_empty());
+    }
 }
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+use futures::future::join_all;
+use log::{info, error};
+use reqwest::Client;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use std::time::Instant;
+
+use crate::{appstate::AppState, config::{ApiConfig, HttpMethod}, factory::{create_request_builder, ApiMonitor}, utils::{http_client::with_redirect_limit, request_limit}};
+
+
+/// Timing and outcome of a single request issued as part of a batch.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchRequestData {
+    /// The name of the sub-request.
+    pub name: String,
+    /// The URL the sub-request was sent to.
+    pub api_url: String,
+    /// The HTTP method used for the sub-request.
+    pub method: HttpMethod,
+    /// The status of the sub-request, e.g., "OK" or "ERROR".
+    pub status: String,
+    /// The response time of the sub-request, in milliseconds.
+    pub response_time: u64,
+    /// The HTTP status code returned, if a response was received.
+    pub status_code: Option<u16>,
+}
+
+/// Represents the data collected for one iteration of a batch of concurrent requests.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchMonitoringData {
+    /// "OK" when every sub-request succeeded, otherwise "ERROR".
+    pub status: String,
+    /// The slowest sub-request's response time in milliseconds, i.e. how long the batch took.
+    pub max_response_time: u64,
+    /// The sum of all sub-request response times in milliseconds.
+    pub total_response_time: u64,
+    /// The per-request results, in configuration order.
+    pub requests: Vec<BatchRequestData>,
+}
+
+
+/// Issues all of an API's `batch` sub-requests concurrently and measures them as a unit.
+pub struct BatchTask {
+    /// Configuration whose `batch` entries are sent together.
+    pub api_config: Arc<ApiConfig>,
+    /// A reference to the shared application state for recording monitoring data.
+    pub app_state: Arc<Mutex<AppState>>,
+}
+
+#[async_trait::async_trait]
+impl ApiMonitor for BatchTask {
+
+    // A batch is a single round of requests, so once started it is left to finish.
+    async fn execute(&self, client: &Client, workflow_name: &str, _cancel: &CancellationToken) -> Result<(), String> {
+        let sub_requests = self.api_config.batch.as_deref().unwrap_or_default();
+        let variables = self.app_state.lock().await.variables(workflow_name).await;
+        let variables = &variables;
+
+        let futures = sub_requests.iter().map(|sub_config| async move {
+            let _permit = request_limit::acquire().await;
+            let start = Instant::now();
+            let response = match create_request_builder(client, sub_config, variables) {
+                Ok(request_builder) => with_redirect_limit(sub_config.max_redirects(), request_builder.send()).await.map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            };
+            let duration = start.elapsed().as_millis() as u64;
+
+            let (status, status_code) = match response {
+                Ok(resp) if resp.status().is_success() => ("OK", Some(resp.status().as_u16())),
+                Ok(resp) => {
+                    error!("Batch request '{}' responded with HTTP status {}", sub_config.name, resp.status().as_u16());
+                    ("ERROR", Some(resp.status().as_u16()))
+                },
+                Err(e) => {
+                    error!("Batch request '{}' failed: {}", sub_config.name, e);
+                    ("ERROR", None)
+                },
+            };
+
+            BatchRequestData {
+                name: sub_config.name.clone(),
+                api_url: sub_config.url.clone(),
+                method: sub_config.method.clone(),
+                status: status.to_string(),
+                response_time: duration,
+                status_code,
+            }
+        });
+
+        let requests = join_all(futures).await;
+
+        let failed = requests.iter().filter(|request| request.status != "OK").count();
+        let batch_data = BatchMonitoringData {
+            status: if failed == 0 { "OK" } else { "ERROR" }.to_string(),
+            max_response_time: requests.iter().map(|request| request.response_time).max().unwrap_or(0),
+            total_response_time: requests.iter().map(|request| request.response_time).sum(),
+            requests,
+        };
+
+        info!(
+            "Batch '{}' completed {} requests in {}ms (sum {}ms)",
+            self.api_config.name, batch_data.requests.len(), batch_data.max_response_time, batch_data.total_response_time
+        );
+        update_batch_app_state(&self.app_state, workflow_name, &self.api_config.name, batch_data).await;
+
+        if failed > 0 {
+            Err(format!("{} of the batch requests in '{}' failed", failed, self.api_config.name))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("Batch for {}", self.api_config.name)
+    }
+
+    fn response_time_threshold(&self) -> Option<u64> {
+        None
+    }
+
+    fn get_task_order(&self) -> usize {
+        self.api_config.task_order.unwrap_or(usize::MAX)
+    }
+}
+
+
+/// Updates the shared application state with the results of a batch.
+async fn update_batch_app_state(
+    app_state: &Arc<Mutex<AppState>>,
+    workflow_name: &str,
+    task_name: &str,
+    batch_data: BatchMonitoringData
+) {
+    let state = app_state.lock().await;
+    let batch_monitoring_data = &mut *state.batch_monitoring_data.lock().await;
+
+    batch_monitoring_data
+        .entry(workflow_name.to_string())
+        .or_default()
+        .insert(task_name.to_string(), batch_data);
+
+    log::info!("Updated batch data for {} in workflow {}", task_name, workflow_name);
+}
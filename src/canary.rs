@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use log::{info, warn};
+use reqwest::{Client, Url};
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use std::time::Instant;
+
+use crate::{appstate::AppState, config::{ApiConfig, CanaryConfig, HttpMethod}, factory::{create_request_builder, ApiMonitor}, utils::{http_client::with_redirect_limit, request_limit}};
+
+
+/// What one side of a canary comparison returned.
+#[derive(Debug, Clone, Serialize)]
+pub struct CanarySideData {
+    /// The URL the request was sent to.
+    pub url: String,
+    /// The HTTP status code returned, if a response was received.
+    pub status_code: Option<u16>,
+    /// The response time in milliseconds.
+    pub response_time: u64,
+    /// The error that prevented a response, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The comparison of a request sent to both the baseline and the canary deployment.
+#[derive(Debug, Clone, Serialize)]
+pub struct CanaryComparison {
+    /// The HTTP method used for both requests.
+    pub method: HttpMethod,
+    pub baseline: CanarySideData,
+    pub canary: CanarySideData,
+    /// Canary response time minus baseline response time, in milliseconds.
+    pub latency_delta_ms: i64,
+    /// Whether the two deployments returned different status codes.
+    pub status_mismatch: bool,
+    /// Whether the two response bodies were byte-for-byte identical.
+    pub body_matches: bool,
+    /// Where the bodies first diverged, when they differ.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_diff: Option<String>,
+}
+
+
+/// Sends each request to both the baseline and canary hosts and compares the responses.
+pub struct CanaryTask {
+    /// Configuration of the request to send to both deployments.
+    pub api_config: Arc<ApiConfig>,
+    /// A reference to the shared application state for recording comparisons.
+    pub app_state: Arc<Mutex<AppState>>,
+    /// The baseline and canary hosts the request's URL is rewritten to.
+    pub canary_config: CanaryConfig,
+}
+
+#[async_trait::async_trait]
+impl ApiMonitor for CanaryTask {
+
+    // A comparison is one request to each host, so once started it is left to finish.
+    async fn execute(&self, client: &Client, workflow_name: &str, _cancel: &CancellationToken) -> Result<(), String> {
+        let baseline_config = with_host(&self.api_config, &self.canary_config.baseline_host)?;
+        let canary_config = with_host(&self.api_config, &self.canary_config.canary_host)?;
+        let variables = self.app_state.lock().await.variables(workflow_name).await;
+
+        // Both deployments receive the request at the same time so they see identical conditions.
+        let ((baseline, baseline_body), (canary, canary_body)) = tokio::join!(
+            send(client, &baseline_config, &variables),
+            send(client, &canary_config, &variables),
+        );
+
+        let body_diff = describe_body_diff(baseline_body.as_deref().unwrap_or_default(), canary_body.as_deref().unwrap_or_default());
+        let comparison = CanaryComparison {
+            method: self.api_config.method.clone(),
+            latency_delta_ms: canary.response_time as i64 - baseline.response_time as i64,
+            status_mismatch: baseline.status_code != canary.status_code,
+            body_matches: body_diff.is_none(),
+            body_diff,
+            baseline,
+            canary,
+        };
+
+        info!(
+            "Canary comparison for '{}': baseline {:?} in {}ms, canary {:?} in {}ms",
+            self.api_config.name, comparison.baseline.status_code, comparison.baseline.response_time,
+            comparison.canary.status_code, comparison.canary.response_time
+        );
+        if !comparison.body_matches {
+            warn!("Canary body for '{}' diverged: {}", self.api_config.name, comparison.body_diff.as_deref().unwrap_or_default());
+        }
+
+        let status_mismatch = comparison.status_mismatch.then(|| format!(
+            "Canary status {:?} differs from baseline status {:?} for '{}'",
+            comparison.canary.status_code, comparison.baseline.status_code, self.api_config.name
+        ));
+        update_canary_app_state(&self.app_state, workflow_name, &self.api_config.name, comparison).await;
+
+        match status_mismatch {
+            Some(error_message) => Err(error_message),
+            None => Ok(()),
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("Canary comparison for {}", self.api_config.name)
+    }
+
+    fn response_time_threshold(&self) -> Option<u64> {
+        None
+    }
+
+    fn get_task_order(&self) -> usize {
+        self.api_config.task_order.unwrap_or(usize::MAX)
+    }
+}
+
+/// Copies the API configuration with its URL pointed at `host`, which may include a port.
+fn with_host(api_config: &ApiConfig, host: &str) -> Result<ApiConfig, String> {
+    let mut url = Url::parse(&api_config.url)
+        .map_err(|e| format!("Invalid URL '{}' for '{}': {}", api_config.url, api_config.name, e))?;
+
+    let (hostname, port) = split_host_port(host);
+    url.set_host(Some(&hostname)).map_err(|e| format!("Invalid canary host '{}': {}", host, e))?;
+    if port.is_some() {
+        url.set_port(port).map_err(|_| format!("Cannot set a port on '{}'", api_config.url))?;
+    }
+
+    let mut rewritten = api_config.clone();
+    rewritten.url = url.to_string();
+    Ok(rewritten)
+}
+
+/// Splits `host` into a hostname and an optional port, bracketing IPv6 literals so they can be set on a URL.
+/// Accepts `host`, `host:port`, `[v6]`, `[v6]:port` and a bare `v6` address.
+fn split_host_port(host: &str) -> (String, Option<u16>) {
+    if host.starts_with('[') {
+        if let Some((bracketed, rest)) = host.split_once(']') {
+            let port = rest.strip_prefix(':').and_then(|port| port.parse::<u16>().ok());
+            return (format!("{}]", bracketed), port);
+        }
+    }
+    if host.parse::<std::net::Ipv6Addr>().is_ok() {
+        return (format!("[{}]", host), None);
+    }
+    match host.rsplit_once(':') {
+        Some((hostname, port)) if port.parse::<u16>().is_ok() => (hostname.to_string(), port.parse::<u16>().ok()),
+        _ => (host.to_string(), None),
+    }
+}
+
+/// Sends one side of the comparison, returning its data and the response body if one was received.
+async fn send(client: &Client, api_config: &ApiConfig, variables: &HashMap<String, String>) -> (CanarySideData, Option<String>) {
+    let _permit = request_limit::acquire().await;
+    let start = Instant::now();
+    let response = match create_request_builder(client, api_config, variables) {
+        Ok(request_builder) => with_redirect_limit(api_config.max_redirects(), request_builder.send()).await.map_err(|e| e.to_string()),
+        Err(e) => Err(e),
+    };
+
+    let (status_code, body, error) = match response {
+        Ok(resp) => {
+            let status_code = resp.status().as_u16();
+            let body = resp.text().await.unwrap_or_default();
+            (Some(status_code), Some(body), None)
+        },
+        Err(e) => (None, None, Some(e)),
+    };
+
+    let side = CanarySideData {
+        url: api_config.url.clone(),
+        status_code,
+        response_time: start.elapsed().as_millis() as u64,
+        error,
+    };
+    (side, body)
+}
+
+/// Describes where two bodies first differ, or returns `None` when they are identical.
+fn describe_body_diff(baseline: &str, canary: &str) -> Option<String> {
+    if baseline == canary {
+        return None;
+    }
+
+    let offset = baseline.bytes().zip(canary.bytes())
+        .position(|(baseline_byte, canary_byte)| baseline_byte != canary_byte)
+        .unwrap_or_else(|| baseline.len().min(canary.len()));
+    Some(format!(
+        "bodies differ at byte {} (baseline {} bytes, canary {} bytes)",
+        offset, baseline.len(), canary.len()
+    ))
+}
+
+/// Updates the shared application state with a canary comparison.
+async fn update_canary_app_state(
+    app_state: &Arc<Mutex<AppState>>,
+    workflow_name: &str,
+    task_name: &str,
+    comparison: CanaryComparison
+) {
+    let state = app_state.lock().await;
+    let canary_monitoring_data = &mut *state.canary_monitoring_data.lock().await;
+
+    canary_monitoring_data
+        .entry(workflow_name.to_string())
+        .or_default()
+        .insert(task_name.to_string(), comparison);
+
+    log::info!("Updated canary data for {} in workflow {}", task_name, workflow_name);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_host_rewrites_host_and_port() {
+        let api_config: ApiConfig = serde_yaml::from_str(r#"
+name: "Orders"
+url: "https://api.example.com/v1/orders?page=2"
+method: GET
+headers: {}
+expected_field: ""
+response_time_threshold: 1000
+"#).unwrap();
+
+        assert_eq!(with_host(&api_config, "canary.example.com").unwrap().url, "https://canary.example.com/v1/orders?page=2");
+        assert_eq!(with_host(&api_config, "localhost:8443").unwrap().url, "https://localhost:8443/v1/orders?page=2");
+        assert_eq!(with_host(&api_config, "::1").unwrap().url, "https://[::1]/v1/orders?page=2");
+        assert_eq!(with_host(&api_config, "[::1]:8443").unwrap().url, "https://[::1]:8443/v1/orders?page=2");
+    }
+
+    #[test]
+    fn test_body_diff_reports_first_divergence() {
+        assert_eq!(describe_body_diff("same", "same"), None);
+        assert_eq!(
+            describe_body_diff(r#"{"v":1}"#, r#"{"v":2}"#).as_deref(),
+            Some("bodies differ at byte 5 (baseline 7 bytes, canary 7 bytes)")
+        );
+        assert_eq!(
+            describe_body_diff("abc", "abcdef").as_deref(),
+            Some("bodies differ at byte 3 (baseline 3 bytes, canary 6 bytes)")
+        );
+    }
+}
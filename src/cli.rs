@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 // src/cli.rs
 use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use crate::config::parse_tls_version;
 
 
 pub fn build_cli() -> Command {
@@ -26,6 +27,49 @@ pub fn build_cli() -> Command {
                 .action(ArgAction::Set)
                 .num_args(1),
         )
+        .arg(
+            Arg::new("replay_har")
+                .long("replay-har")
+                .value_name("FILE")
+                .help("Replays the requests recorded in a HAR file as tasks, in recorded order")
+                .action(ArgAction::Set)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("from_openapi")
+                .long("from-openapi")
+                .value_name("SPEC")
+                .help("Generates a task for every operation in an OpenAPI or Swagger spec, with example parameters and bodies")
+                .action(ArgAction::Set)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("openapi_tag")
+                .long("openapi-tag")
+                .value_name("TAG")
+                .help("Only generates tasks for operations with this tag")
+                .requires("from_openapi")
+                .action(ArgAction::Set)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("openapi_path_prefix")
+                .long("openapi-path-prefix")
+                .value_name("PREFIX")
+                .help("Only generates tasks for operations whose path starts with this prefix")
+                .requires("from_openapi")
+                .action(ArgAction::Set)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("openapi_base_url")
+                .long("openapi-base-url")
+                .value_name("URL")
+                .help("Sends generated requests to this base URL instead of the server the spec declares")
+                .requires("from_openapi")
+                .action(ArgAction::Set)
+                .num_args(1),
+        )
         .arg(
             Arg::new("monitoring_interval_seconds")
                 .long("monitoring-interval-seconds")
@@ -42,6 +86,15 @@ pub fn build_cli() -> Command {
                 .action(ArgAction::Set)
                 .num_args(1),
         )
+        .arg(
+            Arg::new("log_format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .help("Writes log lines as human-readable text (the default) or as JSON objects")
+                .value_parser(["text", "json"])
+                .action(ArgAction::Set)
+                .num_args(1),
+        )
         .arg(
             Arg::new("http_timeout_seconds")
                 .long("http-timeout-seconds")
@@ -63,6 +116,244 @@ pub fn build_cli() -> Command {
             .action(ArgAction::Append)
             .num_args(1)
             .value_parser(value_parser!(String)))
+        .arg(Arg::new("wait_for_server_ready")
+            .long("wait-for-server-ready")
+            .help("Delays the first monitoring cycle until the HTTP server is ready")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("record_redirects")
+            .long("record-redirects")
+            .help("Records the chain of redirects followed for each task request")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("cookie_store")
+            .long("cookie-store")
+            .help("Keeps cookies set by responses and sends them with later requests, e.g. a session from a login task")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("request_timeline")
+            .long("request-timeline")
+            .help("Records a timeline of each task request's attempt, redirects, and outcome \
+                   with per-step latency, for debugging slow or failed requests")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("run_label")
+            .long("label")
+            .value_name("KEY=VALUE")
+            .help("Attaches a label to this run's results (can be used multiple times)")
+            .action(ArgAction::Append)
+            .num_args(1)
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("stream_ndjson")
+            .long("stream-ndjson")
+            .help("Streams each completed request to stdout as a JSON line \
+                   (timestamp_ms, url, method, status, status_code, latency_ms)")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("failure_body_snippet_bytes")
+            .long("failure-body-snippet-bytes")
+            .value_name("BYTES")
+            .help("Includes up to BYTES of the response body, with secrets redacted, in assertion failures")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("parallel_groups")
+            .long("parallel-groups")
+            .value_name("ORDERS")
+            .help("Sets of task_order values that may run concurrently, e.g. \"2,3;5,6\"")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("ignore_task_order")
+            .long("ignore-task-order")
+            .help("Runs every task of a workflow concurrently instead of in task_order stages")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("load_test_http_timeout_seconds")
+            .long("load-test-http-timeout-seconds")
+            .value_name("SECONDS")
+            .help("Sets the HTTP timeout in seconds for load tests only")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("load_test_http_proxy_url")
+            .long("load-test-http-proxy-url")
+            .value_name("URL")
+            .help("Sets the HTTP proxy URL for load tests only")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("no_log_per_request")
+            .long("no-log-per-request")
+            .help("Suppresses per-request start and success logs, logging only failures and per-stage summaries")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("time_unit")
+            .long("time-unit")
+            .value_name("UNIT")
+            .help("Sets the unit of the high-resolution task response time")
+            .value_parser(["ms", "us"])
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("dry_run")
+            .long("dry-run")
+            .help("Builds every API's request without sending it, lists each as OK or ERROR, and exits \
+                   (or APP__DRY_RUN=true)")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("no_server")
+            .long("no-server")
+            .help("Runs the workflows once without starting the HTTP server and prints the results as JSON")
+            .conflicts_with("wait_for_server_ready")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("headless")
+            .long("headless")
+            .help("Runs the workflows once without starting the HTTP server, prints a summary as JSON, and exits 1 \
+                   if any task or load test failed or a task exceeded its response_time_threshold")
+            .conflicts_with_all(["wait_for_server_ready", "no_server"])
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("capture_slow_bodies")
+            .long("capture-slow-bodies")
+            .help("Stores a truncated, redacted response body with each of a load test's slowest requests")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("slow_body_max_bytes")
+            .long("slow-body-max-bytes")
+            .value_name("BYTES")
+            .help("Sets the maximum size of each captured slow-request body (default 512)")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("output_dir")
+            .long("output-dir")
+            .value_name("DIRECTORY")
+            .help("Writes this run's file outputs into a new run-<timestamp> folder under DIRECTORY")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("trace_output_path")
+            .long("trace-output")
+            .value_name("FILE")
+            .help("Writes a Chrome trace format file of every request, viewable in chrome://tracing or Perfetto")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("results_output_path")
+            .long("results-output")
+            .value_name("FILE")
+            .help("Writes the final results to FILE when the run ends, as JSON or CSV depending on its extension")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("error_log_rate_limit")
+            .long("error-log-rate-limit")
+            .value_name("LINES_PER_SECOND")
+            .help("Logs at most this many request errors per second and reports how many were suppressed")
+            .value_parser(value_parser!(usize))
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("max_concurrent_requests")
+            .long("max-concurrent-requests")
+            .value_name("N")
+            .help("Keeps at most N requests in flight at once across the whole run")
+            .value_parser(value_parser!(u32).range(1..))
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("history_size")
+            .long("history-size")
+            .value_name("N")
+            .help("Keeps the N most recent requests to each URL, served as a time series at /history")
+            .value_parser(value_parser!(u32).range(1..))
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("wait_until_healthy")
+            .long("wait-until-healthy")
+            .help("Reruns the workflows every monitoring interval and exits 0 once a cycle passes \
+                   with no failures, or 1 after --timeout")
+            .conflicts_with("wait_for_server_ready")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("wait_until_healthy_timeout_seconds")
+            .long("timeout")
+            .value_name("SECONDS")
+            .help("Sets how long --wait-until-healthy keeps trying (default 300)")
+            .requires("wait_until_healthy")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("connect_error_backoff_secs")
+            .long("connect-error-backoff-secs")
+            .value_name("SECONDS")
+            .help("Sets the wait before retrying a load test whose target could not be reached (default 5)")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("http_error_backoff_secs")
+            .long("http-error-backoff-secs")
+            .value_name("SECONDS")
+            .help("Sets the wait before retrying a load test that failed for any other reason (default 5)")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("repeat")
+            .long("repeat")
+            .value_name("N")
+            .help("Runs the workflows N times back to back, then prints each run's summary and the \
+                   mean, min, max, and variance of the key metrics across runs")
+            .value_parser(value_parser!(u32).range(1..))
+            .conflicts_with_all(["wait_until_healthy", "wait_for_server_ready"])
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("min_samples_for_percentiles")
+            .long("min-samples-for-percentiles")
+            .value_name("COUNT")
+            .help("Omits percentiles for endpoints with fewer samples than this and flags them as insufficient")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("ip_version")
+            .long("ip-version")
+            .value_name("VERSION")
+            .help("Restricts outgoing connections to IPv4 or IPv6 on dual-stack hosts (default auto)")
+            .value_parser(["v4", "v6", "auto"])
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("min_tls_version")
+            .long("min-tls-version")
+            .value_name("VERSION")
+            .help("Lowest TLS version to accept: 1.0, 1.1, 1.2, or 1.3")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("max_tls_version")
+            .long("max-tls-version")
+            .value_name("VERSION")
+            .help("Highest TLS version to offer: 1.0, 1.1, 1.2, or 1.3")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("client_cert_path")
+            .long("client-cert")
+            .value_name("FILE")
+            .help("PEM client certificate to present for mutual TLS; may also contain the private key")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("client_key_path")
+            .long("client-key")
+            .value_name("FILE")
+            .help("PEM private key for --client-cert")
+            .requires("client_cert_path")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("ca_cert_path")
+            .long("ca-cert")
+            .value_name("FILE")
+            .help("PEM CA certificate to trust in addition to the system roots")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("server_bind_address")
+            .long("bind-address")
+            .value_name("ADDRESS")
+            .help("Sets the address the HTTP server binds to (default 127.0.0.1, or APP__SERVER_BIND_ADDRESS)")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("server_port")
+            .long("port")
+            .value_name("PORT")
+            .help("Sets the port the HTTP server listens on (default 8080, or APP__SERVER_PORT)")
+            .value_parser(value_parser!(u16))
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("canary_baseline_host")
+            .long("canary-baseline-host")
+            .value_name("HOST")
+            .help("Enables canary mode, sending each task to this baseline host and the canary host")
+            .requires("canary_host")
+            .action(ArgAction::Set)
+            .num_args(1))
+        .arg(Arg::new("canary_host")
+            .long("canary-host")
+            .value_name("HOST")
+            .help("Sets the canary host compared against the baseline host")
+            .requires("canary_baseline_host")
+            .action(ArgAction::Set)
+            .num_args(1))
 }
 
 
@@ -78,4 +369,103 @@ pub fn process_http_default_headers(matches: &ArgMatches) -> Result<HashMap<Stri
             }
         })
         .collect::<Result<HashMap<_, _>, _>>() // Collects into a Result<HashMap, String>, propagating the first Err encountered, if any.
-}
\ No newline at end of file
+}
+
+/// Parses `--label key=value` arguments, requiring keys to be valid Prometheus label names.
+pub fn process_run_labels(matches: &ArgMatches) -> Result<HashMap<String, String>, String> {
+    matches.get_many::<String>("run_label")
+        .unwrap_or_default()
+        .map(|label| {
+            let (key, value) = label.split_once('=')
+                .ok_or_else(|| format!("Invalid label format: {}", label))?;
+            let key = key.trim();
+            if !is_valid_label_name(key) {
+                return Err(format!("Invalid label name '{}': must match [a-zA-Z_][a-zA-Z0-9_]* and not start with '__'", key));
+            }
+            Ok((key.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Parses `--parallel-groups`, where sets are separated by `;` and order values by `,`.
+pub fn process_parallel_groups(matches: &ArgMatches) -> Result<Option<Vec<Vec<usize>>>, String> {
+    let Some(groups) = matches.get_one::<String>("parallel_groups") else {
+        return Ok(None);
+    };
+
+    groups.split(';')
+        .filter(|group| !group.trim().is_empty())
+        .map(|group| {
+            group.split(',')
+                .map(|order| order.trim().parse::<usize>()
+                    .map_err(|_| format!("Invalid task order '{}' in parallel groups", order.trim())))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+/// Validates `--min-tls-version` and `--max-tls-version`, which must parse and must not contradict each other.
+pub fn process_tls_versions(matches: &ArgMatches) -> Result<(Option<String>, Option<String>), String> {
+    let parse = |id: &str| matches.get_one::<String>(id)
+        .map(|version| parse_tls_version(version).map(|parsed| (version.trim().to_string(), parsed)))
+        .transpose()
+        .map_err(|e| e.to_string());
+    let (min, max) = (parse("min_tls_version")?, parse("max_tls_version")?);
+
+    if let (Some((min, min_parsed)), Some((max, max_parsed))) = (&min, &max) {
+        if min_parsed > max_parsed {
+            return Err(format!("Minimum TLS version {} is above the maximum {}", min, max));
+        }
+    }
+    Ok((min.map(|(version, _)| version), max.map(|(version, _)| version)))
+}
+
+fn is_valid_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_well = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_');
+    starts_well && !name.starts_with("__") && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_labels_are_parsed() {
+        let matches = build_cli().get_matches_from(["load_test_tool", "--label", "git_sha=abc123", "--label", "env = staging"]);
+        let labels = process_run_labels(&matches).unwrap();
+
+        assert_eq!(labels.get("git_sha").map(String::as_str), Some("abc123"));
+        assert_eq!(labels.get("env").map(String::as_str), Some("staging"));
+    }
+
+    #[test]
+    fn test_parallel_groups_are_parsed() {
+        let matches = build_cli().get_matches_from(["load_test_tool", "--parallel-groups", "2, 3;5,6;"]);
+        assert_eq!(process_parallel_groups(&matches), Ok(Some(vec![vec![2, 3], vec![5, 6]])));
+
+        let matches = build_cli().get_matches_from(["load_test_tool", "--parallel-groups", "2,x"]);
+        assert!(process_parallel_groups(&matches).is_err());
+    }
+
+    #[test]
+    fn test_tls_versions_are_validated() {
+        let matches = build_cli().get_matches_from(["load_test_tool", "--min-tls-version", "1.2", "--max-tls-version", "1.3"]);
+        assert_eq!(process_tls_versions(&matches), Ok((Some("1.2".to_string()), Some("1.3".to_string()))));
+
+        let matches = build_cli().get_matches_from(["load_test_tool", "--min-tls-version", "TLSv1.2"]);
+        assert!(process_tls_versions(&matches).unwrap_err().contains("Invalid TLS version 'TLSv1.2'"));
+
+        let matches = build_cli().get_matches_from(["load_test_tool", "--min-tls-version", "1.3", "--max-tls-version", "1.2"]);
+        assert!(process_tls_versions(&matches).is_err());
+    }
+
+    #[test]
+    fn test_invalid_run_label_names_are_rejected() {
+        for label in ["1build=7", "build-number=7", "__name__=x", "missing_separator"] {
+            let matches = build_cli().get_matches_from(["load_test_tool", "--label", label]);
+            assert!(process_run_labels(&matches).is_err(), "expected '{}' to be rejected", label);
+        }
+    }
+}
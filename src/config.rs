@@ -1,14 +1,128 @@
 use config::ConfigError;
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, env, path::PathBuf};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::{collections::{BTreeMap, HashMap, HashSet}, env, path::{Path, PathBuf}, str::FromStr, time::Duration};
 use glob::glob;
 use std::fs::File;
-use crate::utils::interpolate::interpolate_config;
+use std::io::Write;
+use crate::utils::{compression, data_feeder, interpolate::{interpolate_api, interpolate_config, interpolate_env, runtime_variables}, script};
 use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
 pub enum HttpMethod {
-    GET, POST, PUT, DELETE, // Add more as needed
+    GET, POST, PUT, PATCH, DELETE, HEAD, OPTIONS,
+}
+
+impl FromStr for HttpMethod {
+    type Err = String;
+
+    /// Parses a method name regardless of case and surrounding whitespace, so `get`, ` Get`, and `GET` are all accepted.
+    fn from_str(method: &str) -> Result<Self, Self::Err> {
+        match method.trim().to_ascii_uppercase().as_str() {
+            "GET" => Ok(HttpMethod::GET),
+            "POST" => Ok(HttpMethod::POST),
+            "PUT" => Ok(HttpMethod::PUT),
+            "PATCH" => Ok(HttpMethod::PATCH),
+            "DELETE" => Ok(HttpMethod::DELETE),
+            "HEAD" => Ok(HttpMethod::HEAD),
+            "OPTIONS" => Ok(HttpMethod::OPTIONS),
+            _ => Err(format!("unknown HTTP method '{}', expected one of GET, POST, PUT, PATCH, DELETE, HEAD, OPTIONS", method)),
+        }
+    }
+}
+
+impl HttpMethod {
+    /// The method's name as sent on the wire.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpMethod::GET => "GET",
+            HttpMethod::POST => "POST",
+            HttpMethod::PUT => "PUT",
+            HttpMethod::PATCH => "PATCH",
+            HttpMethod::DELETE => "DELETE",
+            HttpMethod::HEAD => "HEAD",
+            HttpMethod::OPTIONS => "OPTIONS",
+        }
+    }
+
+    /// Whether requests with this method carry the configured body; GET, DELETE, HEAD, and OPTIONS are sent without one.
+    pub fn sends_body(&self) -> bool {
+        matches!(self, HttpMethod::POST | HttpMethod::PUT | HttpMethod::PATCH)
+    }
+}
+
+impl<'de> Deserialize<'de> for HttpMethod {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let method = String::deserialize(deserializer)?;
+        method.parse().map_err(de::Error::custom)
+    }
+}
+
+/// The unit high-resolution response times are reported in.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum TimeUnit {
+    #[default]
+    #[serde(rename = "ms")]
+    Milliseconds,
+    #[serde(rename = "us")]
+    Microseconds,
+}
+
+impl TimeUnit {
+    /// Expresses a duration in this unit without rounding away sub-unit precision.
+    pub fn measure(self, duration: Duration) -> f64 {
+        let micros = duration.as_micros() as f64;
+        match self {
+            TimeUnit::Milliseconds => micros / 1000.0,
+            TimeUnit::Microseconds => micros,
+        }
+    }
+}
+
+impl FromStr for TimeUnit {
+    type Err = String;
+
+    fn from_str(unit: &str) -> Result<Self, Self::Err> {
+        match unit {
+            "ms" => Ok(TimeUnit::Milliseconds),
+            "us" => Ok(TimeUnit::Microseconds),
+            _ => Err(format!("unknown time unit '{}'", unit)),
+        }
+    }
+}
+
+/// How a load test grows its load from `initial_load` towards `max_load`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RampShape {
+    /// Adds `spawn_rate` users every second.
+    #[default]
+    Linear,
+    /// Doubles the load every second, to find an order-of-magnitude limit quickly.
+    Exponential,
+    /// Adds `spawn_rate` users, then holds that load for `ramp_step_secs` before the next step.
+    Step,
+}
+
+/// One phase of a staged load test, moving the load linearly to `target_load` over `duration_secs`.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct LoadStage {
+    pub duration_secs: u64,
+    /// The load at the end of the stage; each tick sends this many requests at once, 0 pausing the test.
+    pub target_load: usize,
+}
+
+/// Which row of its `data_file` each request of an API uses.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataOrder {
+    /// Takes the rows in turn, starting over after the last.
+    #[default]
+    RoundRobin,
+    /// Takes each row once across every run; load tests stop issuing requests after the last row, and tasks fail
+    /// rather than repeat one.
+    Unique,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -16,8 +130,69 @@ pub struct LoadTestConfig {
     pub initial_load: Option<usize>,
     pub max_load: Option<usize>,
     pub spawn_rate: Option<usize>,
+    /// Times each request is retried after a connection error, timeout, or 5xx response; defaults to 0.
     pub retry_count: Option<usize>,
+    /// How often a load test attempt that failed before finishing, e.g. because connections could not be
+    /// primed, is rerun as a whole; defaults to 0. Independent of `retry_count`.
+    pub rerun_count: Option<usize>,
     pub max_duration_secs: Option<usize>,
+    /// Stops issuing requests once this many have been sent, warmup included; in-flight ones finish.
+    /// Whichever of this, `max_load`, and `max_duration_secs` is reached first ends the test.
+    pub total_requests: Option<usize>,
+    /// Minimum requests per second the load test must achieve to pass.
+    pub min_achieved_rps: Option<f64>,
+    /// Issues requests no faster than this many per second, however many users are running.
+    pub target_rps: Option<usize>,
+    /// Switches to an open model: starts this many requests per second, spaced evenly, whether or not
+    /// earlier ones have completed. The ramp settings and `max_load` are ignored.
+    pub arrival_rate: Option<usize>,
+    /// With `arrival_rate`, the most requests in flight at once; arrivals beyond it are dropped, not queued.
+    pub max_in_flight: Option<usize>,
+    /// Phases such as ramp-up, steady state, spike, and ramp-down, run one after another from `initial_load`;
+    /// the load is interpolated between their targets and capped at `max_load`. The test lasts as long as
+    /// the stages together, and the ramp settings and `max_duration_secs` are ignored.
+    pub stages: Option<Vec<LoadStage>>,
+    /// Number of connections to open before the measured run starts.
+    pub prime_connections: Option<usize>,
+    /// Maximum number of priming connections opened concurrently; defaults to all at once.
+    pub prime_parallelism: Option<usize>,
+    /// Method of the priming requests, which are sent without a body; defaults to HEAD. Set GET or OPTIONS
+    /// for targets that reject HEAD.
+    pub prime_method: Option<HttpMethod>,
+    /// Fails the attempt when fewer than `prime_connections` connections are still open after priming.
+    pub require_primed_connections: Option<bool>,
+    /// Fraction by which a second's achieved RPS may trail its target before it is flagged; defaults to 0.1.
+    pub rps_tolerance: Option<f64>,
+    /// The shape of the ramp towards `max_load`; defaults to linear.
+    pub ramp_shape: Option<RampShape>,
+    /// For the step shape, how many seconds each load level is held; defaults to 5.
+    pub ramp_step_secs: Option<u64>,
+    /// Width of the concurrency bands errors are broken down by; defaults to 10.
+    pub concurrency_band_size: Option<usize>,
+    /// Stops retrying a request once this many seconds have passed since its first attempt, even if `retry_count`
+    /// allows more; the last backoff is cut short to end within it.
+    pub retry_max_elapsed_secs: Option<u64>,
+    /// A separate endpoint probed at a low rate during the test, to tell whether the whole service degrades.
+    pub health_probe: Option<HealthProbeConfig>,
+    /// Number of initial requests treated as warmup and left out of all reported metrics.
+    pub warmup_requests: Option<usize>,
+    /// Seconds from the start of the test during which issued requests are treated as warmup, like `warmup_requests`.
+    pub warmup_secs: Option<u64>,
+    /// Response header advertising the server's concurrency limit, e.g. `X-RateLimit-Limit`; the load is capped at its value.
+    pub auto_concurrency_from_header: Option<String>,
+    /// Stops adding load once more than this fraction of recent requests failed, e.g. 0.5 for half.
+    pub abort_on_error_rate: Option<f64>,
+    /// How many of the most recent measured requests the error rate is taken over; defaults to 20.
+    pub abort_min_samples: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HealthProbeConfig {
+    pub url: String,
+    /// Seconds between probes; defaults to 1.
+    pub interval_secs: Option<u64>,
+    /// Probes slower than this many milliseconds count as degraded.
+    pub threshold_ms: Option<u64>,
 }
 
 impl Default for LoadTestConfig {
@@ -27,7 +202,29 @@ impl Default for LoadTestConfig {
             max_load: Some(10),
             spawn_rate: Some(1),
             retry_count: Some(0),
+            rerun_count: None,
             max_duration_secs: Some(60),
+            total_requests: None,
+            min_achieved_rps: None,
+            target_rps: None,
+            arrival_rate: None,
+            max_in_flight: None,
+            stages: None,
+            prime_connections: None,
+            prime_parallelism: None,
+            prime_method: None,
+            require_primed_connections: None,
+            rps_tolerance: None,
+            ramp_shape: None,
+            ramp_step_secs: None,
+            concurrency_band_size: None,
+            retry_max_elapsed_secs: None,
+            health_probe: None,
+            warmup_requests: None,
+            warmup_secs: None,
+            auto_concurrency_from_header: None,
+            abort_on_error_rate: None,
+            abort_min_samples: None,
         }
     }
 }
@@ -36,39 +233,354 @@ impl Default for LoadTestConfig {
 pub struct ApiConfig {
     pub name: String,
     pub task_order: Option<usize>,
+    /// May be left out when `urls_file` lists the URLs instead.
+    #[serde(default)]
     pub url: String,
+    /// A file listing one URL per line; each becomes its own API, named after this one with its line's index,
+    /// and otherwise configured the same. Blank lines and lines starting with `#` are skipped.
+    pub urls_file: Option<String>,
     pub headers: HashMap<String, String>,
+    /// Credentials sent in the `Authorization` header, so they needn't be encoded into `headers` by hand.
+    pub auth: Option<AuthConfig>,
+    /// JSON field a successful response must contain, as a dotted path like `data.items.0.id` (optionally
+    /// starting with `$.`); an empty string skips the check.
     pub expected_field: String,
+    /// The value `expected_field` must hold; strings are compared as they are and other values as JSON text.
+    pub expected_value: Option<String>,
     pub response_time_threshold: u64,
     pub method: HttpMethod,
     pub body: Option<String>,
     pub body_file: Option<String>,
+    /// Sends a `multipart/form-data` body of text fields and files instead of `body` or `body_file`.
+    pub multipart: Option<MultipartConfig>,
+    /// Compresses the request body with "gzip" or "deflate" and sets the matching `Content-Encoding` header.
+    pub compress_body: Option<String>,
     pub load_test: Option<bool>,
     pub load_test_config: Option<LoadTestConfig>,
+    /// Requests issued concurrently as a single unit, e.g. the resources of a page load.
+    pub batch: Option<Vec<ApiConfig>>,
+    /// Conditions that mark a response as a failure even when its status is successful.
+    pub failure_rules: Option<Vec<FailureRule>>,
+    /// Response header carrying the server's own processing time, e.g. `Server-Timing` or `X-Response-Time`.
+    pub server_timing_header: Option<String>,
+    /// Records a successful response as an error when its body is not valid JSON; off by default.
+    pub require_valid_json: Option<bool>,
+    /// Scores between 0.0 and 1.0 per status code for the health score, e.g. 0.5 for 429; other codes score 1.0 when 2xx and 0.0 otherwise.
+    pub status_weights: Option<HashMap<u16, f64>>,
+    /// Runs this API in its own named workflow, concurrently with the rest of its file; `task_order` applies within that workflow.
+    pub workflow: Option<String>,
+    /// Request timeout for this API, overriding the HTTP client's timeout.
+    pub timeout_seconds: Option<u64>,
+    /// Resends an idempotent request once when the pooled connection it was sent on turns out to be broken; defaults to true.
+    pub retry_broken_connection: Option<bool>,
+    /// Follows redirects to their final response; when false, the redirect response itself is recorded. Defaults to true.
+    pub follow_redirects: Option<bool>,
+    /// The most redirects a request follows before failing; defaults to 10.
+    pub max_redirects: Option<usize>,
+    /// Tunnels `method` through this header (e.g. `X-HTTP-Method-Override`) and sends the request as POST.
+    pub method_override_header: Option<String>,
+    /// A Rhai script run before each request that may rewrite its URL, headers, and body.
+    pub pre_request_script: Option<String>,
+    /// Sends a generated body of exactly this many bytes instead of `body` or `body_file`.
+    pub random_body_size_bytes: Option<usize>,
+    /// Sends a generated body with a size picked from `[min, max]` for each request; overrides `random_body_size_bytes`.
+    pub random_body_size_range: Option<[usize; 2]>,
+    /// Seed for generated bodies, so runs send the same payloads; defaults to 0.
+    pub random_body_seed: Option<u64>,
+    /// A CSV file with a header row, or a JSONL file of objects, whose rows feed successive requests;
+    /// a row's columns are available to the URL, headers, and body as `${data.column}`.
+    pub data_file: Option<String>,
+    /// Whether requests take the rows of `data_file` "round_robin" (the default) or "unique", each only once.
+    pub data_order: Option<DataOrder>,
+    /// Status codes that count as success, e.g. `[202, 304]`; when unset, any 2xx does.
+    pub expected_status_codes: Option<Vec<u16>>,
+    /// Values taken from a successful task's response, which later requests use as `${variable}` or `${vars.variable}`.
+    pub extract: Option<Vec<Extraction>>,
+    /// Mixes this load-test API into one load test with the workflow's other weighted load-test APIs,
+    /// each request going to one of them in proportion to its weight; the first one's `load_test_config` drives the load.
+    pub weight: Option<u32>,
+    /// Times a task is run in sequence each monitoring run, each result recorded; defaults to 1.
+    pub iterations: Option<usize>,
+    /// Milliseconds to wait between a task's iterations, as think time.
+    pub delay_ms: Option<u64>,
+    /// "http" (the default) or "websocket", which load tests the URL's WebSocket endpoint as `websocket` configures.
+    pub protocol: Option<Protocol>,
+    /// Connections and messages for a WebSocket API; defaults to one connection sending nothing.
+    pub websocket: Option<WebSocketConfig>,
+}
+
+impl ApiConfig {
+    /// Whether a response with this status code counts as successful.
+    pub fn is_expected_status(&self, status_code: u16) -> bool {
+        match &self.expected_status_codes {
+            Some(expected_status_codes) => expected_status_codes.contains(&status_code),
+            None => (200..300).contains(&status_code),
+        }
+    }
+
+    /// The redirect limit requests to this API are sent with, if it overrides the client's.
+    ///
+    /// 0 means a redirect response is returned as it is rather than followed.
+    pub fn max_redirects(&self) -> Option<usize> {
+        match self.follow_redirects {
+            Some(false) => Some(0),
+            _ => self.max_redirects,
+        }
+    }
+
+    /// The method actually sent on the wire, which is POST when a method override header is configured.
+    pub fn wire_method(&self) -> HttpMethod {
+        match self.method_override_header {
+            Some(_) => HttpMethod::POST,
+            None => self.method.clone(),
+        }
+    }
+}
+
+/// A labelled condition over a response's status, body, and headers.
+///
+/// Every configured condition must hold for the rule to match.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FailureRule {
+    /// The label recorded when this rule classifies a response as a failure.
+    pub label: String,
+    /// Matches when the response status is one of these codes.
+    pub status_codes: Option<Vec<u16>>,
+    /// Matches when the response body contains this text.
+    pub body_contains: Option<String>,
+    /// Matches when the response carries this header.
+    pub header: Option<String>,
+    /// Together with `header`, matches only when the header has exactly this value.
+    pub header_value: Option<String>,
+}
+
+/// Credentials for the `Authorization` header; values may use `${VAR}` placeholders from the environment or extracted variables.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AuthConfig {
+    Basic { username: String, password: String },
+    Bearer { token: String },
+}
+
+impl AuthConfig {
+    /// The `Authorization` header value, with each credential passed through `resolve` first.
+    ///
+    /// Basic credentials are encoded as UTF-8 before base64, as RFC 7617 recommends, so non-ASCII names work.
+    pub fn header_value(&self, resolve: impl Fn(&str) -> String) -> String {
+        match self {
+            AuthConfig::Basic { username, password } => {
+                format!("Basic {}", STANDARD.encode(format!("{}:{}", resolve(username), resolve(password))))
+            }
+            AuthConfig::Bearer { token } => format!("Bearer {}", resolve(token)),
+        }
+    }
+}
+
+/// The protocol an API is tested over.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    Http,
+    /// Opens WebSocket connections to a `ws://` or `wss://` URL and exchanges messages over them.
+    Websocket,
+}
+
+/// How a WebSocket API is load tested.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct WebSocketConfig {
+    /// Connections opened and held at once; defaults to 1.
+    pub connections: Option<usize>,
+    /// Text messages each connection sends in order, waiting for a reply to each before the next;
+    /// they may use `${variable}` placeholders. Without any, only connecting is measured.
+    pub messages: Option<Vec<String>>,
+    /// Times each connection sends its messages; defaults to 1.
+    pub rounds: Option<usize>,
+    /// How long to wait for a reply before the message counts as unanswered; defaults to 5000.
+    pub reply_timeout_ms: Option<u64>,
+}
+
+/// The parts of a `multipart/form-data` body, each sent in order of its name.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MultipartConfig {
+    /// Text parts by name; their values may use `${variable}` placeholders.
+    pub fields: Option<BTreeMap<String, String>>,
+    /// File parts by name, each read from the given path and sent under its file name.
+    pub files: Option<BTreeMap<String, String>>,
+}
+
+/// A value taken from a response and stored as a variable for later requests.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Extraction {
+    /// Where the value is in the JSON response, as a dotted path such as `data.token` or `items.0.id`.
+    #[serde(default)]
+    pub field: String,
+    /// A regular expression matched against the raw response instead of `field`; the value is its
+    /// first capture group, or the whole match without one.
+    pub regex: Option<String>,
+    /// The name later requests refer to the value by, as in `Bearer ${token}`.
+    pub variable: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Workflow {
     pub name: String, // Add this to identify each workflow
     pub apis: Vec<ApiConfig>,
+    /// Requests sent once after every workflow has finished, e.g. to delete what a load test created.
+    pub teardown: Option<Vec<ApiConfig>>,
+    /// Counts failed teardown requests as failures of the run; by default they are only logged.
+    pub teardown_failures_fail_run: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
     pub monitoring_interval_seconds: u64,
     pub log_level: String,
+    /// "text" for human-readable log lines, the default, or "json" for one JSON object per line.
+    pub log_format: Option<String>,
     pub http_timeout_seconds: u64,
     pub http_proxy_url: Option<String>,
     pub http_default_headers: HashMap<String, String>,
+    /// Delay the first monitoring cycle until the HTTP server has bound successfully.
+    pub wait_for_server_ready: Option<bool>,
+    /// Record the chain of redirects followed for each task request.
+    pub record_redirects: Option<bool>,
+    /// Keep cookies from responses and send them with later requests of the same monitoring cycle.
+    pub cookie_store: Option<bool>,
+    /// Record a timeline of each task request's attempt, redirects, and outcome, for debugging.
+    pub request_timeline: Option<bool>,
+    /// Arbitrary metadata (git sha, environment, build number) attached to the run's results.
+    pub run_labels: Option<HashMap<String, String>>,
+    /// Print one NDJSON line to stdout for every completed request.
+    pub stream_ndjson: Option<bool>,
+    /// Include up to this many bytes of the response body when an assertion fails.
+    pub failure_body_snippet_bytes: Option<usize>,
+    /// Sets of task_order values whose groups may run concurrently with each other.
+    pub parallel_groups: Option<Vec<Vec<usize>>>,
+    /// Whether tasks run in `task_order` stages; defaults to true. When false, every task of a
+    /// workflow starts at once, still bounded by `max_concurrent_requests`.
+    pub respect_task_order: Option<bool>,
+    /// HTTP client settings used by load tests instead of the global ones.
+    pub load_test_http: Option<HttpOverrides>,
+    /// Whether each request is logged individually; defaults to true. Disabling it keeps only
+    /// failures and per-stage summaries, which avoids logging becoming a bottleneck under heavy load.
+    pub log_per_request: Option<bool>,
+    /// Unit of the high-resolution response time recorded for tasks; defaults to milliseconds.
+    pub time_unit: Option<TimeUnit>,
+    /// Runs the workflows once without binding the HTTP server, printing the results to stdout.
+    pub no_server: Option<bool>,
+    /// Runs the workflows once without binding the HTTP server, prints a summary, and exits non-zero unless it passed.
+    pub headless: Option<bool>,
+    /// Builds every API's request without sending it, reports which could not be built, and exits.
+    pub dry_run: Option<bool>,
+    /// Attaches a truncated body snapshot to each request kept in a load test's slowest-requests record.
+    pub capture_slow_bodies: Option<bool>,
+    /// Maximum bytes of each captured slow-request body; defaults to 512.
+    pub slow_body_max_bytes: Option<usize>,
+    /// Base directory under which each run's file outputs are written to their own `run-<timestamp>` folder.
+    pub output_dir: Option<String>,
+    /// Writes each request as a timed event to this Chrome trace format file; relative paths go in the run's output folder.
+    pub trace_output_path: Option<String>,
+    /// Writes the final results to this `.json` or `.csv` file once each run ends; relative paths go in the run's output folder.
+    pub results_output_path: Option<String>,
+    /// Most request error lines logged per second; excess lines are counted and reported as suppressed.
+    pub error_log_rate_limit: Option<usize>,
+    /// Most requests in flight at once across every workflow and load test of the run.
+    pub max_concurrent_requests: Option<usize>,
+    /// Keeps this many of the most recent requests to each URL, with their timings, for `/history`.
+    pub history_size: Option<usize>,
+    /// Repeats the workflows every monitoring interval until a cycle has no failures, then exits.
+    pub wait_until_healthy: Option<bool>,
+    /// How long `wait_until_healthy` keeps trying before exiting with an error; defaults to 300 seconds.
+    pub wait_until_healthy_timeout_seconds: Option<u64>,
+    /// Seconds to wait before retrying a load test that could not connect at all; defaults to 5.
+    pub connect_error_backoff_secs: Option<u64>,
+    /// Seconds to wait before retrying a load test that failed for any other reason; defaults to 5.
+    pub http_error_backoff_secs: Option<u64>,
+    /// Runs the workflows this many times and reports how the key metrics varied between runs.
+    pub repeat: Option<u32>,
+    /// Percentiles are omitted for endpoints with fewer samples than this, since they would be noise.
+    pub min_samples_for_percentiles: Option<usize>,
+    /// Restricts outgoing connections to "v4" or "v6"; defaults to "auto", allowing either.
+    pub ip_version: Option<String>,
+    /// Lowest TLS version connections may negotiate, e.g. "1.2"; defaults to reqwest's minimum.
+    pub min_tls_version: Option<String>,
+    /// Highest TLS version connections may negotiate, e.g. "1.2" to exercise legacy paths.
+    pub max_tls_version: Option<String>,
+    /// PEM client certificate presented to servers that require mutual TLS; may also hold the key.
+    pub client_cert_path: Option<String>,
+    /// PEM private key for `client_cert_path`, when the certificate file does not include it.
+    pub client_key_path: Option<String>,
+    /// PEM CA certificate trusted in addition to the system roots, e.g. for a private CA.
+    pub ca_cert_path: Option<String>,
+    /// Address the HTTP server binds to; defaults to 127.0.0.1.
+    pub server_bind_address: Option<String>,
+    /// Port the HTTP server listens on; defaults to 8080.
+    pub server_port: Option<u16>,
+    /// When set, plain tasks are sent to both the baseline and canary hosts and compared.
+    pub canary: Option<CanaryConfig>,
+}
+
+/// The hosts compared in canary mode; each request's URL host is swapped for both.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CanaryConfig {
+    /// Host (optionally `host:port`) of the baseline deployment.
+    pub baseline_host: String,
+    /// Host (optionally `host:port`) of the canary deployment.
+    pub canary_host: String,
+}
+
+/// Replacements for the global HTTP client settings; unset fields keep the global value.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct HttpOverrides {
+    pub timeout_seconds: Option<u64>,
+    pub proxy_url: Option<String>,
 }
 
 impl Settings {
     pub fn init_logging(&self) {
         env::set_var("RUST_LOG", &self.log_level);
-        env_logger::init();
+        let mut builder = env_logger::Builder::from_default_env();
+        if self.log_format.as_deref() == Some("json") {
+            builder.format(|buf, record| {
+                let timestamp = buf.timestamp_millis().to_string();
+                writeln!(buf, "{}", json_log_line(&timestamp, record))
+            });
+        }
+        builder.init();
+    }
+
+    /// Whether individual requests should be logged, which is the default.
+    pub fn log_per_request(&self) -> bool {
+        self.log_per_request.unwrap_or(true)
+    }
+
+    /// The address and port the HTTP server binds to.
+    pub fn server_address(&self) -> (&str, u16) {
+        (self.server_bind_address.as_deref().unwrap_or("127.0.0.1"), self.server_port.unwrap_or(8080))
     }
 }
 
+/// A log record as a JSON object, for log aggregators that parse each line.
+fn json_log_line(timestamp: &str, record: &log::Record) -> serde_json::Value {
+    serde_json::json!({
+        "timestamp": timestamp,
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    })
+}
+
+/// Parses a TLS protocol version given as "1.0", "1.1", "1.2", or "1.3".
+pub fn parse_tls_version(version: &str) -> Result<reqwest::tls::Version, ConfigError> {
+    match version.trim() {
+        "1.0" => Ok(reqwest::tls::Version::TLS_1_0),
+        "1.1" => Ok(reqwest::tls::Version::TLS_1_1),
+        "1.2" => Ok(reqwest::tls::Version::TLS_1_2),
+        "1.3" => Ok(reqwest::tls::Version::TLS_1_3),
+        other => Err(ConfigError::Message(format!("Invalid TLS version '{}': expected 1.0, 1.1, 1.2, or 1.3.", other))),
+    }
+}
 
 pub async fn load_workflow(config_file: Option<String>, config_dir: Option<String>) -> Result<Vec<Workflow>, Box<dyn std::error::Error>> {
     let mut workflows = Vec::new();
@@ -87,9 +599,10 @@ pub async fn load_workflow(config_file: Option<String>, config_dir: Option<Strin
     for config_path in config_paths {
         let file = File::open(&config_path).with_context(|| format!("Failed to open config file at {:?}", config_path))?;
         let mut workflow: Workflow = serde_yaml::from_reader(file).with_context(|| format!("Failed to parse YAML from {:?}", config_path))?;
-        // Assuming these functions are async and return a Result type
-        interpolate_config(&mut workflow); // Adjust this if necessary
-        validate_settings(&mut workflow)?; // Ensure this is compatible with async context
+        // Interpolated first, so a `urls_file` path may use environment variables too.
+        interpolate_config(&mut workflow);
+        expand_urls_files(&mut workflow).with_context(|| format!("Invalid configuration in {:?}", config_path))?;
+        validate_settings(&mut workflow).with_context(|| format!("Invalid configuration in {:?}", config_path))?;
 
         workflows.push(workflow);
     }
@@ -97,16 +610,482 @@ pub async fn load_workflow(config_file: Option<String>, config_dir: Option<Strin
     Ok(workflows)
 }
 
+/// Replaces each API or teardown request with a `urls_file` by one copy of it per URL in the file, so they are
+/// validated like any other. The workflow has been interpolated already, so environment variables in the listed
+/// URLs are interpolated here.
+fn expand_urls_files(workflow: &mut Workflow) -> Result<()> {
+    let runtime_variables = runtime_variables(workflow);
+    workflow.apis = expand_apis(std::mem::take(&mut workflow.apis), &runtime_variables)?;
+    if let Some(teardown) = workflow.teardown.take() {
+        workflow.teardown = Some(expand_apis(teardown, &runtime_variables)?);
+    }
+    Ok(())
+}
+
+fn expand_apis(apis_to_expand: Vec<ApiConfig>, runtime_variables: &HashSet<String>) -> Result<Vec<ApiConfig>> {
+    let mut apis = Vec::with_capacity(apis_to_expand.len());
+    for api in apis_to_expand {
+        let Some(urls_file) = api.urls_file.clone() else {
+            apis.push(api);
+            continue;
+        };
+        let contents = std::fs::read_to_string(&urls_file)
+            .with_context(|| format!("Failed to read urls_file '{}' for '{}'", urls_file, api.name))?;
+        let urls: Vec<&str> = contents.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+        if urls.is_empty() {
+            anyhow::bail!("urls_file '{}' for '{}' lists no URLs.", urls_file, api.name);
+        }
+        for (index, url) in urls.into_iter().enumerate() {
+            apis.push(ApiConfig {
+                name: format!("{} #{}", api.name, index + 1),
+                url: interpolate_env(url, runtime_variables),
+                urls_file: None,
+                ..api.clone()
+            });
+        }
+    }
+    Ok(apis)
+}
+
+/// Interpolates and validates a single API configuration received at runtime, as if it had been loaded from a file
+/// as part of `workflow`. Variables the workflow's other APIs extract are left for request time.
+pub fn prepare_api(workflow: &Workflow, mut api: ApiConfig) -> Result<ApiConfig, ConfigError> {
+    let mut runtime_variables = runtime_variables(workflow);
+    runtime_variables.extend(api.extract.iter().flatten().map(|extraction| extraction.variable.clone()));
+    interpolate_api(&mut api, &runtime_variables);
+
+    let mut prepared = Workflow { name: workflow.name.clone(), apis: vec![api], teardown: None, teardown_failures_fail_run: workflow.teardown_failures_fail_run };
+    validate_settings(&mut prepared)?;
+    Ok(prepared.apis.remove(0))
+}
+
+/// Checks every API of the workflow, filling in defaults, and reports every problem found at once.
 fn validate_settings(workflow: &mut Workflow) -> Result<(), ConfigError> {
+    let mut problems: Vec<String> = Vec::new();
+
+    // Results are keyed by API name within a workflow, so a duplicate would overwrite the other's results.
+    let mut names = HashSet::new();
+    for api in workflow.apis.iter() {
+        if !names.insert((api.workflow.as_deref(), api.name.as_str())) {
+            problems.push(format!("API name '{}' is used more than once in the same workflow.", api.name));
+        }
+    }
+
     for api in workflow.apis.iter_mut() {
+        for rule in api.failure_rules.iter().flatten() {
+            if rule.status_codes.is_none() && rule.body_contains.is_none() && rule.header.is_none() {
+                problems.push(format!("Failure rule '{}' for '{}' has no conditions.", rule.label, api.name));
+            }
+        }
+        if api.expected_value.is_some() && api.expected_field.is_empty() {
+            problems.push(format!("'{}' sets expected_value without an expected_field to compare it with.", api.name));
+        }
+        for extraction in api.extract.iter().flatten() {
+            if extraction.variable.is_empty() || extraction.field.is_empty() == extraction.regex.is_none() {
+                problems.push(format!("Extraction into '{}' for '{}' needs a variable and either a field or a regex.", extraction.variable, api.name));
+            } else if let Some(Err(e)) = extraction.regex.as_deref().map(regex::Regex::new) {
+                problems.push(format!("Extraction regex for '{}' in '{}' is invalid: {}", extraction.variable, api.name, e));
+            }
+        }
+        if api.multipart.is_some() && (api.body.is_some() || api.body_file.is_some() || api.random_body_size_bytes.is_some() || api.random_body_size_range.is_some()) {
+            problems.push(format!("'{}' sets a multipart body alongside another body; only one may be configured.", api.name));
+        }
+        if let Some(compress_body) = &api.compress_body {
+            if !compression::ENCODINGS.contains(&compress_body.as_str()) {
+                problems.push(format!("compress_body for '{}' must be one of {}, not '{}'.", api.name, compression::ENCODINGS.join(", "), compress_body));
+            } else if api.multipart.is_some() {
+                problems.push(format!("'{}' sets compress_body on a multipart body, which is sent uncompressed.", api.name));
+            } else if !api.wire_method().sends_body() {
+                log::warn!("'{}' sends {} requests without a body, so compress_body is ignored.", api.name, api.wire_method().as_str());
+            } else if api.body.is_none() && api.body_file.is_none() && api.random_body_size_bytes.is_none() && api.random_body_size_range.is_none() {
+                log::warn!("'{}' has no body to compress, so compress_body is ignored.", api.name);
+            }
+        }
+        if api.auth.is_some() && api.headers.keys().any(|name| name.eq_ignore_ascii_case("authorization")) {
+            problems.push(format!("'{}' sets both auth and an Authorization header; only one may be configured.", api.name));
+        }
+        if api.weight == Some(0) {
+            problems.push(format!("weight for '{}' must be at least 1.", api.name));
+        }
+        if api.iterations == Some(0) {
+            problems.push(format!("iterations for '{}' must be at least 1.", api.name));
+        }
+        if let Some(pre_request_script) = &api.pre_request_script {
+            if let Err(e) = script::compile(pre_request_script) {
+                problems.push(format!("Pre-request script for '{}' does not compile: {}", api.name, e));
+            }
+        }
+        if let Some(batch) = &api.batch {
+            // A batch is described entirely by its sub-requests, so those are what must be valid.
+            if batch.is_empty() {
+                problems.push(format!("Batch '{}' has no requests configured.", api.name));
+            }
+            for sub_api in batch.iter().filter(|sub_api| sub_api.url.is_empty()) {
+                problems.push(format!("API URL is missing in the configuration for '{}' in batch '{}'.", sub_api.name, api.name));
+            }
+            continue;
+        }
         if api.url.is_empty() {
-            return Err(ConfigError::Message(format!("API URL is missing in the configuration for '{}'.", api.name)));
+            problems.push(format!("API URL is missing in the configuration for '{}'.", api.name));
+        }
+        if api.protocol == Some(Protocol::Websocket) {
+            let websocket_url = ["ws://", "wss://"].iter().any(|scheme| api.url.starts_with(scheme));
+            if !api.url.is_empty() && !websocket_url {
+                problems.push(format!("WebSocket URL for '{}' must start with ws:// or wss://.", api.name));
+            }
+            let websocket = api.websocket.clone().unwrap_or_default();
+            if websocket.connections == Some(0) || websocket.rounds == Some(0) {
+                problems.push(format!("websocket connections and rounds for '{}' must be at least 1.", api.name));
+            }
+            if api.load_test.unwrap_or(false) {
+                log::warn!("'{}' is a WebSocket API, so load_test_config is ignored; websocket sets its load.", api.name);
+            }
+        } else if api.websocket.is_some() {
+            log::warn!("'{}' sets websocket without protocol: websocket; it has no effect.", api.name);
+        }
+        // Files expand into their APIs when a workflow is loaded; an API sent at runtime has no such step.
+        if let Some(urls_file) = &api.urls_file {
+            problems.push(format!("urls_file '{}' for '{}' is only supported in configuration files.", urls_file, api.name));
+        }
+        if api.response_time_threshold == 0 {
+            problems.push(format!("response_time_threshold for '{}' must be at least 1 millisecond.", api.name));
+        }
+        if let Some(body_file) = api.body_file.as_deref().filter(|body_file| !Path::new(body_file).is_file()) {
+            problems.push(format!("body_file '{}' for '{}' does not exist.", body_file, api.name));
+        }
+        if let Some(Err(e)) = api.data_file.as_deref().map(data_feeder::load) {
+            problems.push(format!("{} for '{}'.", e, api.name));
+        }
+        if api.data_order.is_some() && api.data_file.is_none() {
+            log::warn!("'{}' sets data_order without a data_file; it has no effect.", api.name);
         }
         if api.load_test.unwrap_or(false) && api.load_test_config.is_none() {
             log::warn!("Missing load_test_config for '{}'. Using default values.", api.name);
             api.load_test_config = Some(LoadTestConfig::default());
         }
+        if let Some(load_test_config) = &api.load_test_config {
+            if load_test_config.spawn_rate == Some(0) {
+                problems.push(format!("spawn_rate for '{}' must be at least 1.", api.name));
+            }
+            if load_test_config.target_rps == Some(0) {
+                problems.push(format!("target_rps for '{}' must be at least 1.", api.name));
+            }
+            if load_test_config.abort_on_error_rate.is_some_and(|rate| !(0.0..1.0).contains(&rate)) {
+                problems.push(format!("abort_on_error_rate for '{}' must be at least 0 and below 1.", api.name));
+            }
+            if load_test_config.arrival_rate == Some(0) {
+                problems.push(format!("arrival_rate for '{}' must be at least 1.", api.name));
+            }
+            if load_test_config.max_in_flight == Some(0) {
+                problems.push(format!("max_in_flight for '{}' must be at least 1.", api.name));
+            }
+            if load_test_config.arrival_rate.is_some() && load_test_config.target_rps.is_some() {
+                problems.push(format!("'{}' sets both arrival_rate and target_rps; only one may be configured.", api.name));
+            }
+            match &load_test_config.stages {
+                Some(stages) if stages.is_empty() => problems.push(format!("stages for '{}' must list at least one stage.", api.name)),
+                Some(stages) if stages.iter().any(|stage| stage.duration_secs == 0) => {
+                    problems.push(format!("Every stage for '{}' must last at least 1 second.", api.name));
+                },
+                Some(_) if load_test_config.arrival_rate.is_some() => {
+                    problems.push(format!("'{}' sets both stages and arrival_rate; only one may be configured.", api.name));
+                },
+                _ => {},
+            }
+            if load_test_config.max_in_flight.is_some() && load_test_config.arrival_rate.is_none() {
+                log::warn!("'{}' sets max_in_flight without an arrival_rate; it has no effect.", api.name);
+            }
+            if load_test_config.total_requests == Some(0) {
+                problems.push(format!("total_requests for '{}' must be at least 1.", api.name));
+            }
+            if load_test_config.abort_min_samples == Some(0) {
+                problems.push(format!("abort_min_samples for '{}' must be at least 1.", api.name));
+            }
+            if let (Some(initial_load), Some(max_load)) = (load_test_config.initial_load, load_test_config.max_load) {
+                if initial_load > max_load {
+                    problems.push(format!("initial_load of {} for '{}' exceeds its max_load of {}.", initial_load, api.name, max_load));
+                }
+            }
+        }
+    }
+    for api in workflow.teardown.iter().flatten().filter(|api| api.url.is_empty()) {
+        problems.push(format!("API URL is missing in the configuration for teardown request '{}'.", api.name));
+    }
+
+    match problems.len() {
+        0 => Ok(()),
+        1 => Err(ConfigError::Message(problems.remove(0))),
+        count => Err(ConfigError::Message(format!("Found {} configuration problems:\n- {}", count, problems.join("\n- ")))),
     }
-    Ok(())
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::api;
+
+    #[test]
+    fn test_http_method_accepts_mixed_case() {
+        for (input, expected) in [("get", HttpMethod::GET), ("Post", HttpMethod::POST), ("PUT", HttpMethod::PUT), ("dElEtE", HttpMethod::DELETE),
+                                 ("patch", HttpMethod::PATCH), ("Head", HttpMethod::HEAD), ("OPTIONS", HttpMethod::OPTIONS), ("' post '", HttpMethod::POST)] {
+            let method: HttpMethod = serde_yaml::from_str(input).expect("method should parse");
+            assert_eq!(method, expected);
+        }
+    }
+
+    #[test]
+    fn test_http_method_serializes_uppercase() {
+        assert_eq!(serde_json::to_string(&HttpMethod::POST).unwrap(), "\"POST\"");
+    }
+
+    #[test]
+    fn test_json_log_line_has_the_record_fields() {
+        let line = json_log_line("2024-05-01T12:00:00.000Z", &log::Record::builder()
+            .args(format_args!("Request error: \"timeout\""))
+            .level(log::Level::Warn)
+            .target("load_test_tool::tasks")
+            .build());
+        assert_eq!(line.to_string(), r#"{"level":"WARN","message":"Request error: \"timeout\"","target":"load_test_tool::tasks","timestamp":"2024-05-01T12:00:00.000Z"}"#);
+    }
+
+    #[test]
+    fn test_time_unit_keeps_sub_millisecond_precision() {
+        let duration = Duration::from_micros(1250);
+        assert_eq!(TimeUnit::Milliseconds.measure(duration), 1.25);
+        assert_eq!("us".parse::<TimeUnit>().unwrap().measure(duration), 1250.0);
+        assert!("s".parse::<TimeUnit>().is_err());
+    }
+
+    #[test]
+    fn test_prepare_api_validates_runtime_updates() {
+        let api: ApiConfig = serde_json::from_str(r#"{
+            "name": "Orders", "url": "", "method": "GET", "headers": {},
+            "expected_field": "", "response_time_threshold": 1000
+        }"#).unwrap();
+        assert!(prepare_api(&workflow(Vec::new()), api.clone()).is_err());
+
+        let api = prepare_api(&workflow(Vec::new()), ApiConfig { url: "http://localhost/orders".to_string(), load_test: Some(true), ..api }).unwrap();
+        assert!(api.load_test_config.is_some());
+    }
+
+    #[test]
+    fn test_prepare_api_leaves_variables_extracted_by_the_workflow() {
+        let login = ApiConfig { extract: Some(vec![Extraction { field: "token".to_string(), regex: None, variable: "token".to_string() }]), ..api("Login") };
+        let orders = ApiConfig { headers: HashMap::from([("Authorization".to_string(), "Bearer ${token:-anonymous}".to_string())]), ..api("Orders") };
+
+        let prepared = prepare_api(&workflow(vec![login]), orders.clone()).unwrap();
+        assert_eq!(prepared.headers["Authorization"], "Bearer ${token:-anonymous}");
+        let prepared = prepare_api(&workflow(Vec::new()), orders).unwrap();
+        assert_eq!(prepared.headers["Authorization"], "Bearer anonymous");
+    }
+
+    #[test]
+    fn test_load_test_ramp_is_validated() {
+        let api: ApiConfig = serde_json::from_str(r#"{
+            "name": "Orders", "url": "http://localhost/orders", "method": "GET", "headers": {},
+            "expected_field": "", "response_time_threshold": 1000, "load_test": true,
+            "load_test_config": {"initial_load": 20, "max_load": 5, "spawn_rate": 0}
+        }"#).unwrap();
+        let error = prepare_api(&workflow(Vec::new()), api.clone()).unwrap_err().to_string();
+        assert!(error.starts_with("Found 2 configuration problems:\n"), "{}", error);
+        assert!(error.contains("- spawn_rate for 'Orders' must be at least 1."), "{}", error);
+        assert!(error.contains("- initial_load of 20 for 'Orders' exceeds its max_load of 5."), "{}", error);
+
+        let load_test_config = LoadTestConfig { spawn_rate: Some(2), initial_load: Some(5), ..api.load_test_config.clone().unwrap() };
+        let api = prepare_api(&workflow(Vec::new()), ApiConfig { load_test_config: Some(load_test_config), ..api }).unwrap();
+
+        let load_test_config = LoadTestConfig { abort_on_error_rate: Some(1.5), ..api.load_test_config.clone().unwrap() };
+        assert!(prepare_api(&workflow(Vec::new()), ApiConfig { load_test_config: Some(load_test_config), ..api }).is_err());
+    }
+
+    fn workflow(apis: Vec<ApiConfig>) -> Workflow {
+        Workflow { name: "Shop".to_string(), apis, teardown: None, teardown_failures_fail_run: None }
+    }
+
+    fn validate(apis: Vec<ApiConfig>) -> Result<(), String> {
+        validate_settings(&mut workflow(apis)).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_duplicate_api_names_are_rejected_within_a_workflow() {
+        assert_eq!(validate(vec![api("Orders"), api("Orders")]).unwrap_err(), "API name 'Orders' is used more than once in the same workflow.");
+        // APIs that run in their own named workflow keep their results apart.
+        let separate = ApiConfig { workflow: Some("Checkout".to_string()), ..api("Orders") };
+        assert!(validate(vec![api("Orders"), separate]).is_ok());
+    }
+
+    #[test]
+    fn test_zero_response_time_threshold_is_rejected() {
+        let api = ApiConfig { response_time_threshold: 0, ..api("Orders") };
+        assert_eq!(validate(vec![api]).unwrap_err(), "response_time_threshold for 'Orders' must be at least 1 millisecond.");
+    }
+
+    #[test]
+    fn test_urls_file_expands_into_one_api_per_url() {
+        let urls_file = std::env::temp_dir().join(format!("load_test_tool_urls_{}.txt", std::process::id()));
+        std::fs::write(&urls_file, "# Smoke test targets\nhttp://localhost/a\n\n  http://localhost/b  \n").unwrap();
+        let template = ApiConfig { url: String::new(), urls_file: Some(urls_file.to_string_lossy().into_owned()), ..api("Smoke") };
+        let mut workflow = Workflow { name: "Shop".to_string(), apis: vec![api("Orders"), template], teardown: None, teardown_failures_fail_run: None };
+
+        expand_urls_files(&mut workflow).unwrap();
+        let _ = std::fs::remove_file(&urls_file);
+
+        let apis: Vec<(&str, &str)> = workflow.apis.iter().map(|api| (api.name.as_str(), api.url.as_str())).collect();
+        assert_eq!(apis, [("Orders", "http://localhost/orders"), ("Smoke #1", "http://localhost/a"), ("Smoke #2", "http://localhost/b")]);
+        assert!(workflow.apis.iter().all(|api| api.urls_file.is_none() && api.response_time_threshold == 1000));
+        assert!(validate_settings(&mut workflow).is_ok());
+    }
+
+    #[test]
+    fn test_urls_file_paths_are_interpolated_and_teardown_is_expanded() {
+        let urls_file = std::env::temp_dir().join(format!("load_test_tool_teardown_urls_{}.txt", std::process::id()));
+        std::fs::write(&urls_file, "http://${URLS_FILE_TEST_HOST}/a\nhttp://${URLS_FILE_TEST_HOST}/b\n").unwrap();
+        env::set_var("URLS_FILE_TEST_DIR", std::env::temp_dir());
+        env::set_var("URLS_FILE_TEST_HOST", "cleanup.internal");
+        let file_name = urls_file.file_name().unwrap().to_string_lossy();
+        let template = ApiConfig { url: String::new(), urls_file: Some(format!("${{URLS_FILE_TEST_DIR}}/{}", file_name)), ..api("Cleanup") };
+        let mut workflow = Workflow { name: "Shop".to_string(), apis: vec![api("Orders")], teardown: Some(vec![template]), teardown_failures_fail_run: None };
+
+        interpolate_config(&mut workflow);
+        expand_urls_files(&mut workflow).unwrap();
+        let _ = std::fs::remove_file(&urls_file);
+        env::remove_var("URLS_FILE_TEST_DIR");
+        env::remove_var("URLS_FILE_TEST_HOST");
+
+        let teardown: Vec<&str> = workflow.teardown.iter().flatten().map(|api| api.url.as_str()).collect();
+        assert_eq!(teardown, ["http://cleanup.internal/a", "http://cleanup.internal/b"]);
+    }
+
+    #[test]
+    fn test_auth_encodes_credentials_and_excludes_an_authorization_header() {
+        let basic: AuthConfig = serde_json::from_value(serde_json::json!({"type": "basic", "username": "jürgen", "password": "p:ss"})).unwrap();
+        assert_eq!(basic.header_value(str::to_string), "Basic asO8cmdlbjpwOnNz");
+        let bearer: AuthConfig = serde_json::from_value(serde_json::json!({"type": "bearer", "token": "abc"})).unwrap();
+        assert_eq!(bearer.header_value(str::to_uppercase), "Bearer ABC");
+
+        let mut both = ApiConfig { auth: Some(bearer), ..api("Orders") };
+        both.headers.insert("authorization".to_string(), "Bearer abc".to_string());
+        assert_eq!(validate(vec![both]).unwrap_err(), "'Orders' sets both auth and an Authorization header; only one may be configured.");
+    }
+
+    #[test]
+    fn test_missing_body_file_is_rejected() {
+        let missing = ApiConfig { body_file: Some("/nonexistent/order.json".to_string()), ..api("Orders") };
+        assert_eq!(validate(vec![missing]).unwrap_err(), "body_file '/nonexistent/order.json' for 'Orders' does not exist.");
+
+        let body_file = std::env::temp_dir().join(format!("load_test_tool_body_{}.json", std::process::id()));
+        std::fs::write(&body_file, "{}").unwrap();
+        let present = ApiConfig { body_file: Some(body_file.to_string_lossy().into_owned()), ..api("Orders") };
+        let result = validate(vec![present]);
+        let _ = std::fs::remove_file(&body_file);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_every_problem_is_reported_at_once() {
+        let error = validate(vec![
+            ApiConfig { url: String::new(), ..api("Orders") },
+            ApiConfig { iterations: Some(0), response_time_threshold: 0, ..api("Cart") },
+            api("Cart"),
+        ]).unwrap_err();
+        assert_eq!(error, "Found 4 configuration problems:\n\
+            - API name 'Cart' is used more than once in the same workflow.\n\
+            - API URL is missing in the configuration for 'Orders'.\n\
+            - iterations for 'Cart' must be at least 1.\n\
+            - response_time_threshold for 'Cart' must be at least 1 millisecond.");
+    }
+
+    #[test]
+    fn test_unknown_body_compression_is_rejected() {
+        let brotli = ApiConfig { compress_body: Some("br".to_string()), method: HttpMethod::POST, ..api("Orders") };
+        assert_eq!(validate(vec![brotli]).unwrap_err(), "compress_body for 'Orders' must be one of gzip, deflate, not 'br'.");
+        // A request without a body is sent as it is, with only a warning.
+        assert!(validate(vec![ApiConfig { compress_body: Some("gzip".to_string()), ..api("Orders") }]).is_ok());
+    }
+
+    #[test]
+    fn test_expected_value_needs_an_expected_field() {
+        let api = ApiConfig { expected_value: Some("ok".to_string()), ..api("Orders") };
+        assert_eq!(validate(vec![api.clone()]).unwrap_err(), "'Orders' sets expected_value without an expected_field to compare it with.");
+        assert!(validate(vec![ApiConfig { expected_field: "status".to_string(), ..api }]).is_ok());
+    }
+
+    #[test]
+    fn test_extractions_take_a_field_or_a_regex() {
+        let extraction = |field: &str, regex: Option<&str>| Extraction { field: field.to_string(), regex: regex.map(str::to_string), variable: "token".to_string() };
+        let with = |extractions| ApiConfig { extract: Some(extractions), ..api("Login") };
+
+        assert!(validate(vec![with(vec![extraction("token", None), extraction("", Some(r"token=(\w+)"))])]).is_ok());
+        assert_eq!(validate(vec![with(vec![extraction("token", Some("token"))])]).unwrap_err(),
+            "Extraction into 'token' for 'Login' needs a variable and either a field or a regex.");
+        assert!(validate(vec![with(vec![extraction("", Some("(unclosed"))])]).unwrap_err().starts_with("Extraction regex for 'token' in 'Login' is invalid"));
+    }
+
+    #[test]
+    fn test_load_test_stages_are_validated() {
+        let staged = |stages: Vec<LoadStage>| ApiConfig {
+            load_test: Some(true),
+            load_test_config: Some(LoadTestConfig { stages: Some(stages), ..LoadTestConfig::default() }),
+            ..api("Orders")
+        };
+        assert!(validate(vec![staged(vec![LoadStage { duration_secs: 10, target_load: 50 }, LoadStage { duration_secs: 5, target_load: 0 }])]).is_ok());
+        assert_eq!(validate(vec![staged(Vec::new())]).unwrap_err(), "stages for 'Orders' must list at least one stage.");
+        assert_eq!(validate(vec![staged(vec![LoadStage { duration_secs: 0, target_load: 50 }])]).unwrap_err(), "Every stage for 'Orders' must last at least 1 second.");
+    }
+
+    #[test]
+    fn test_websocket_apis_need_a_websocket_url() {
+        let websocket = ApiConfig { protocol: Some(Protocol::Websocket), url: "ws://localhost/chat".to_string(), ..api("Chat") };
+        assert!(validate(vec![websocket.clone()]).is_ok());
+        assert_eq!(validate(vec![ApiConfig { url: "http://localhost/chat".to_string(), ..websocket.clone() }]).unwrap_err(),
+            "WebSocket URL for 'Chat' must start with ws:// or wss://.");
+        let idle = ApiConfig { websocket: Some(WebSocketConfig { connections: Some(0), ..WebSocketConfig::default() }), ..websocket };
+        assert_eq!(validate(vec![idle]).unwrap_err(), "websocket connections and rounds for 'Chat' must be at least 1.");
+    }
+
+    #[test]
+    fn test_unreadable_data_file_is_rejected() {
+        let missing = ApiConfig { data_file: Some("/nonexistent/users.csv".to_string()), ..api("Signup") };
+        assert!(validate(vec![missing]).unwrap_err().starts_with("Error reading data_file '/nonexistent/users.csv'"));
+
+        let header_only = std::env::temp_dir().join(format!("load_test_tool_header_only_{}.csv", std::process::id()));
+        std::fs::write(&header_only, "email,name\n").unwrap();
+        let empty = ApiConfig { data_file: Some(header_only.to_string_lossy().into_owned()), ..api("Signup") };
+        assert!(validate(vec![empty]).unwrap_err().ends_with("has no rows for 'Signup'."));
+        let _ = std::fs::remove_file(&header_only);
+    }
+
+    #[test]
+    fn test_multipart_excludes_other_bodies() {
+        let api: ApiConfig = serde_json::from_str(r#"{
+            "name": "Upload", "url": "http://localhost/upload", "method": "POST", "headers": {},
+            "expected_field": "", "response_time_threshold": 1000, "body": "{}",
+            "multipart": {"fields": {"owner": "alice"}}
+        }"#).unwrap();
+        assert!(prepare_api(&workflow(Vec::new()), api.clone()).is_err());
+        assert!(prepare_api(&workflow(Vec::new()), ApiConfig { body: None, ..api }).is_ok());
+    }
+
+    #[test]
+    fn test_expected_status_codes_replace_the_2xx_range() {
+        let mut api: ApiConfig = serde_json::from_str(r#"{
+            "name": "Orders", "url": "http://localhost/orders", "method": "GET", "headers": {},
+            "expected_field": "", "response_time_threshold": 1000
+        }"#).unwrap();
+        assert!(api.is_expected_status(204) && !api.is_expected_status(304));
+
+        api.expected_status_codes = Some(vec![202, 304]);
+        assert!(api.is_expected_status(304) && api.is_expected_status(202));
+        assert!(!api.is_expected_status(200));
+    }
+
+    #[test]
+    fn test_unknown_http_method_is_rejected() {
+        let error = serde_yaml::from_str::<HttpMethod>("fetch").unwrap_err().to_string();
+        assert!(error.contains("unknown HTTP method 'fetch', expected one of GET, POST"), "{}", error);
+    }
+}
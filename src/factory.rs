@@ -4,77 +4,291 @@ use futures::future::join_all;
 use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
-use crate::config::{Settings, Workflow};
+use tokio_util::sync::CancellationToken;
+use crate::config::{parse_tls_version, HttpOverrides, Settings, Workflow};
 use crate::appstate::AppState;
+use crate::batch::BatchTask;
+use crate::canary::CanaryTask;
 use crate::loadtest::LoadTest;
 use crate::tasks::Task;
+use crate::summary::RunSummary;
 use crate::utils::http_client::{self, HttpClientConfig};
-use std::{fs, str::FromStr};
-use reqwest::{Client, RequestBuilder};
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use crate::config::{ApiConfig, HttpMethod};
+use crate::utils::{compression, data_feeder, error_log, output, results_file, trace};
+use crate::utils::{interpolate::{interpolate_template, resolve_variables, runtime_variables, unresolved_placeholders}, random_body, script::run_pre_request_script};
+use bytes::Bytes;
+use std::{fs, path::Path, str::FromStr};
+use reqwest::{Client, Method, RequestBuilder};
+use reqwest::multipart::{Form, Part};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_ENCODING};
+use crate::config::{ApiConfig, HttpMethod, MultipartConfig, Protocol};
+use crate::websocket::WsLoadTest;
 use reqwest::Client as HttpClient;
 
 
 
 #[async_trait::async_trait]
 pub trait ApiMonitor {
-    async fn execute(&self, client: &reqwest::Client, workflow_name: &str) -> Result<(), String>;
+    /// Runs the monitor, stopping early and recording it as cancelled once `cancel` is cancelled.
+    async fn execute(&self, client: &reqwest::Client, workflow_name: &str, cancel: &CancellationToken) -> Result<(), String>;
     fn describe(&self) -> String;
-    fn response_time_threshold(&self) -> Option<u64>; // Threshold in seconds
+    fn response_time_threshold(&self) -> Option<u64>; // Threshold in milliseconds
     fn get_task_order(&self) -> usize;
+    /// Whether this monitor generates load, and so should use the load-test HTTP client.
+    fn is_load_test(&self) -> bool {
+        false
+    }
+}
+
+/// The HTTP clients shared by all monitors in a run.
+#[derive(Clone)]
+pub struct MonitorClients {
+    /// Client used by tasks and batches.
+    pub default: HttpClient,
+    /// Client used by load tests; the same as `default` unless overrides are configured.
+    pub load_test: HttpClient,
+}
+
+impl MonitorClients {
+    /// Builds the clients the settings call for; fails if, e.g., a certificate file cannot be read.
+    pub fn new(settings: &Settings) -> anyhow::Result<Self> {
+        let default = http_client::get_client(Some(http_client_config(settings, None)))?;
+        // Load tests get their own client only when their settings differ from the global ones.
+        let load_test = match &settings.load_test_http {
+            Some(overrides) => http_client::get_client(Some(http_client_config(settings, Some(overrides))))?,
+            None => default.clone(),
+        };
+        Ok(MonitorClients { default, load_test })
+    }
+
+    /// Picks the client appropriate for the given monitor.
+    fn for_monitor(&self, monitor: &(dyn ApiMonitor + Send + Sync)) -> &HttpClient {
+        if monitor.is_load_test() { &self.load_test } else { &self.default }
+    }
+}
+
+
+pub fn create_request_builder(client: &Client, api_config: &ApiConfig, variables: &HashMap<String, String>) -> Result<RequestBuilder, String> {
+    // Requests built outside of an iteration use the first row of a `data_file` without claiming it.
+    let data_row = api_config.data_file.as_ref().map(|_| 0);
+    create_request_builder_for_iteration(client, api_config, 0, data_row, variables)
 }
 
+/// Builds the request for one iteration of an API, running its pre-request script if it has one.
+///
+/// `${name}` placeholders in the URL, headers, and body are resolved from `variables`, the values
+/// extracted from earlier responses, and `${data.column}` from `data_row`, the row of the `data_file`
+/// claimed for the request with `data_feeder::claim_row`.
+pub fn create_request_builder_for_iteration(client: &Client, api_config: &ApiConfig, iteration: u64, data_row: Option<usize>, variables: &HashMap<String, String>) -> Result<RequestBuilder, String> {
+    let variables = &*data_feeder::with_data_row(api_config, data_row, variables)?;
+    let body_content: Bytes = if let Some(random_body) = random_body::random_body(api_config, iteration) {
+        random_body
+    } else if let Some(body_file_path) = &api_config.body_file {
+        let template = fs::read_to_string(body_file_path)
+            .map_err(|e| format!("Error reading request body from file '{}': {}", body_file_path, e))?;
+        // The file is a template, filled in afresh for every request from extracted values and the environment.
+        interpolate_template(&template, variables).into()
+    } else {
+        api_config.body.clone().unwrap_or_default().into()
+    };
+
+    let scripted = api_config.pre_request_script.as_deref()
+        .map(|script| run_pre_request_script(script, api_config, &String::from_utf8_lossy(&body_content), iteration))
+        .transpose()?;
+    let (url, header_values, body_content) = match &scripted {
+        Some(request) => (&request.url, &request.headers, Bytes::from(request.body.clone())),
+        None => (&api_config.url, &api_config.headers, body_content),
+    };
+    let url = resolve_variables(url, variables);
+    // Generated bodies are arbitrary bytes, so only text bodies can hold placeholders.
+    let body_content = match std::str::from_utf8(&body_content) {
+        Ok(body) if body.contains("${") => Bytes::from(resolve_variables(body, variables).into_owned()),
+        _ => body_content,
+    };
 
-pub fn create_request_builder(client: &Client, api_config: &ApiConfig) -> Result<RequestBuilder, String> {
     let mut headers = HeaderMap::new();
-    for (key, value) in &api_config.headers {
-        match (HeaderName::from_str(key), HeaderValue::from_str(value)) {
+    for (key, value) in header_values {
+        let value = resolve_variables(value, variables);
+        match (HeaderName::from_str(key), HeaderValue::from_str(&value)) {
             (Ok(header_name), Ok(header_value)) => {
                 headers.insert(header_name, header_value);
             },
             _ => return Err(format!("Invalid header: {}: {}", key, value)),
         }
     }
+    if let Some(auth) = &api_config.auth {
+        let mut header_value = HeaderValue::from_str(&auth.header_value(|value| resolve_variables(value, variables).into_owned()))
+            .map_err(|_| format!("Invalid auth credentials for '{}'", api_config.name))?;
+        // Marked sensitive so the credentials are left out of debug output.
+        header_value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, header_value);
+    }
 
-    let body_content = if let Some(body_file_path) = &api_config.body_file {
-        fs::read_to_string(body_file_path)
-            .map_err(|e| format!("Error reading request body from file '{}': {}", body_file_path, e))?
+    // Method overrides tunnel the logical method through a header on a POST request.
+    let (request_builder, sends_body) = if let Some(override_header) = &api_config.method_override_header {
+        let header_name = HeaderName::from_str(override_header)
+            .map_err(|_| format!("Invalid method override header: {}", override_header))?;
+        headers.insert(header_name, HeaderValue::from_static(api_config.method.as_str()));
+        (client.post(url.as_ref()), true)
     } else {
-        api_config.body.clone().unwrap_or_default()
+        match &api_config.method {
+            HttpMethod::POST => (client.post(url.as_ref()), true),
+            HttpMethod::PUT => (client.put(url.as_ref()), true),
+            HttpMethod::PATCH => (client.patch(url.as_ref()), true),
+            HttpMethod::DELETE => (client.delete(url.as_ref()), false),
+            HttpMethod::GET => (client.get(url.as_ref()), false),
+            HttpMethod::HEAD => (client.head(url.as_ref()), false),
+            HttpMethod::OPTIONS => (client.request(Method::OPTIONS, url.as_ref()), false),
+        }
     };
-
-    let request_builder = match &api_config.method {
-        HttpMethod::POST => Ok(client.post(&api_config.url).headers(headers).body(body_content)),
-        HttpMethod::PUT => Ok(client.put(&api_config.url).headers(headers).body(body_content)),
-        HttpMethod::DELETE => Ok(client.delete(&api_config.url).headers(headers)),
-        HttpMethod::GET => Ok(client.get(&api_config.url).headers(headers)),
-        // Extend this match to handle other HTTP methods as needed
+    let request_builder = request_builder.headers(headers);
+    // A multipart form sets its own Content-Type, with the boundary between its parts.
+    let request_builder = match (&api_config.multipart, sends_body) {
+        (_, false) => request_builder,
+        (Some(multipart), true) => request_builder.multipart(multipart_form(multipart, variables)?),
+        // An empty body is sent as it is, without a Content-Encoding that would claim otherwise.
+        (None, true) => match api_config.compress_body.as_deref().filter(|_| !body_content.is_empty()) {
+            Some(encoding) => request_builder.header(CONTENT_ENCODING, encoding).body(compression::compress(encoding, &body_content)?),
+            None => request_builder.body(body_content),
+        },
     };
 
-    request_builder
+    // A per-API timeout replaces the client's for this request only.
+    Ok(match api_config.timeout_seconds {
+        Some(timeout_seconds) => request_builder.timeout(Duration::from_secs(timeout_seconds)),
+        None => request_builder,
+    })
+}
+
+/// Builds a multipart form from its text fields and the files it names, reading each file afresh.
+fn multipart_form(multipart: &MultipartConfig, variables: &HashMap<String, String>) -> Result<Form, String> {
+    let mut form = Form::new();
+    for (name, value) in multipart.fields.iter().flatten() {
+        form = form.text(name.clone(), resolve_variables(value, variables).into_owned());
+    }
+    for (name, path) in multipart.files.iter().flatten() {
+        let contents = fs::read(path)
+            .map_err(|e| format!("Error reading multipart file '{}' for part '{}': {}", path, name, e))?;
+        let file_name = Path::new(path).file_name().map(|file_name| file_name.to_string_lossy().into_owned()).unwrap_or_default();
+        form = form.part(name.clone(), Part::bytes(contents).file_name(file_name));
+    }
+    Ok(form)
+}
+
+/// The outcome of building one API's request without sending it.
+pub struct RequestCheck {
+    pub workflow: String,
+    /// The API's name, prefixed with its batch's name for batch sub-requests.
+    pub api: String,
+    /// Why the request could not be built, if it could not.
+    pub error: Option<String>,
+}
+
+/// Builds the request of every API, batch sub-request, and teardown request without sending any of them.
+///
+/// No values have been extracted yet, so `${name}` placeholders for extracted variables are left as they are.
+/// Any other placeholder still in a request is reported, since nothing will fill it in.
+pub fn check_requests(workflows: &[Arc<Workflow>], client: &HttpClient) -> Vec<RequestCheck> {
+    let mut checks = Vec::new();
+    for workflow in workflows {
+        let runtime_variables = runtime_variables(workflow);
+        let mut check = |api: String, api_config: &ApiConfig| {
+            // A WebSocket API sends no HTTP request of its own; its URL is all that can be checked up front.
+            let error = if api_config.protocol == Some(Protocol::Websocket) {
+                reqwest::Url::parse(&api_config.url).err().map(|e| format!("Invalid WebSocket URL '{}': {}", api_config.url, e))
+            } else {
+                create_request_builder(client, api_config, &HashMap::new())
+                    .and_then(|request_builder| request_builder.build().map_err(|e| e.to_string()))
+                    .and_then(|request| {
+                        // The URL is checked as configured, since building the request percent-encodes its braces.
+                        let body = request.body().and_then(|body| body.as_bytes()).map(String::from_utf8_lossy);
+                        // The configured body is checked too, since methods without one do not send it.
+                        let texts = std::iter::once(api_config.url.as_str())
+                            .chain(api_config.body.as_deref())
+                            .chain(request.headers().values().filter_map(|value| value.to_str().ok()))
+                            .chain(body.as_deref());
+                        let unresolved = unresolved_placeholders(texts, &runtime_variables);
+                        if unresolved.is_empty() {
+                            Ok(())
+                        } else {
+                            Err(format!("Unresolved placeholders: {}", unresolved.into_iter().collect::<Vec<_>>().join(", ")))
+                        }
+                    })
+                    .err()
+            };
+            checks.push(RequestCheck { workflow: workflow.name.clone(), api, error });
+        };
+        for api_config in &workflow.apis {
+            match &api_config.batch {
+                Some(batch) => batch.iter().for_each(|sub_config| check(format!("{} / {}", api_config.name, sub_config.name), sub_config)),
+                None => check(api_config.name.clone(), api_config),
+            }
+        }
+        for api_config in workflow.teardown.iter().flatten() {
+            check(api_config.name.clone(), api_config);
+        }
+    }
+    checks
 }
 
-pub fn create_monitor_tasks(cfg: &Workflow, app_state: Arc<Mutex<AppState>>) -> VecDeque<Box<dyn ApiMonitor + Send + Sync>> {
+pub fn create_monitor_tasks(cfg: &Workflow, app_state: Arc<Mutex<AppState>>, settings: Arc<Settings>) -> VecDeque<Box<dyn ApiMonitor + Send + Sync>> {
     let mut tasks: VecDeque<Box<dyn ApiMonitor + Send + Sync>> = VecDeque::new();
+    let weighted_load_tests: Vec<&ApiConfig> = cfg.apis.iter()
+        .filter(|api_config| api_config.batch.is_none() && api_config.load_test.unwrap_or(false) && api_config.weight.is_some())
+        .collect();
 
     for api_config in cfg.apis.iter() {
         // Use the task's name in logging
-        if api_config.load_test.unwrap_or(false) {
+        if api_config.batch.is_some() {
+            info!("Configuring batch '{}'", api_config.name);
+            tasks.push_back(Box::new(BatchTask {
+                api_config: Arc::new(api_config.clone()),
+                app_state: app_state.clone(),
+            }));
+        } else if api_config.protocol == Some(Protocol::Websocket) {
+            info!("Configuring WebSocket load test '{}'", api_config.name);
+            tasks.push_back(Box::new(WsLoadTest {
+                api_config: Arc::new(api_config.clone()),
+                app_state: app_state.clone(),
+                websocket_config: api_config.websocket.clone().unwrap_or_default(),
+                settings: settings.clone(),
+            }));
+        } else if api_config.load_test.unwrap_or(false) {
+            // Weighted APIs share one load test, configured where the first of them appears.
+            let weighted_apis: Vec<Arc<ApiConfig>> = match api_config.weight {
+                Some(_) if !std::ptr::eq(api_config, weighted_load_tests[0]) => continue,
+                Some(_) => weighted_load_tests.iter().map(|api| Arc::new((*api).clone())).collect(),
+                None => Vec::new(),
+            };
             if let Some(load_test_config) = &api_config.load_test_config {
-                info!("Configuring progressive load test '{}'", api_config.name); // Changed from url to name
+                if weighted_apis.is_empty() {
+                    info!("Configuring progressive load test '{}'", api_config.name); // Changed from url to name
+                } else {
+                    let names: Vec<&str> = weighted_apis.iter().map(|api| api.name.as_str()).collect();
+                    info!("Configuring progressive load test '{}' mixing {}", api_config.name, names.join(", "));
+                }
                 tasks.push_back(Box::new(LoadTest {
                     api_config: Arc::new(api_config.clone()),
                     app_state: app_state.clone(),
                     load_test_config: load_test_config.clone(),
+                    settings: settings.clone(),
+                    weighted_apis,
                 }));
             }
+        } else if let Some(canary_config) = &settings.canary {
+            info!("Configuring canary comparison '{}'", api_config.name);
+            tasks.push_back(Box::new(CanaryTask {
+                api_config: Arc::new(api_config.clone()),
+                app_state: app_state.clone(),
+                canary_config: canary_config.clone(),
+            }));
         } else {
             info!("Configuring task '{}'", api_config.name); // Log task configuration with name
             tasks.push_back(Box::new(Task {
                 api_config: Arc::new(api_config.clone()),
                 app_state: app_state.clone(),
+                settings: settings.clone(),
             }));
         }
     }
@@ -83,56 +297,566 @@ pub fn create_monitor_tasks(cfg: &Workflow, app_state: Arc<Mutex<AppState>>) ->
 }
 
 
-async fn monitor_single_workflow(workflow: Arc<Workflow>, app_state: Arc<Mutex<AppState>>, client: HttpClient) {
+/// Sends a workflow's teardown requests one after another, returning how many failures count against the run.
+///
+/// Failures are always logged but only counted when `teardown_failures_fail_run` is set.
+async fn run_teardown(workflow: &Workflow, client: &HttpClient, variables: &HashMap<String, String>) -> usize {
+    let mut failures = 0;
+    for api_config in workflow.teardown.iter().flatten() {
+        let outcome = match create_request_builder(client, api_config, variables) {
+            Ok(request_builder) => match http_client::with_redirect_limit(api_config.max_redirects(), request_builder.send()).await {
+                Ok(resp) if resp.status().is_success() => Ok(resp.status()),
+                Ok(resp) => Err(format!("HTTP status {}", resp.status().as_u16())),
+                Err(e) => Err(e.to_string()),
+            },
+            Err(e) => Err(e),
+        };
+        match outcome {
+            Ok(status) => info!("Teardown '{}' in workflow '{}' succeeded with status code {}", api_config.name, workflow.name, status.as_u16()),
+            Err(e) => {
+                log::error!("Teardown '{}' in workflow '{}' failed: {}", api_config.name, workflow.name, e);
+                failures += 1;
+            }
+        }
+    }
+
+    if workflow.teardown_failures_fail_run.unwrap_or(false) { failures } else { 0 }
+}
+
+/// Runs every monitor of a workflow stage by stage, returning how many of them failed.
+async fn monitor_single_workflow(workflow: Arc<Workflow>, app_state: Arc<Mutex<AppState>>, settings: Arc<Settings>, clients: MonitorClients, cancel: CancellationToken) -> usize {
     let workflow_name = &workflow.name;
-    let tasks = create_monitor_tasks(&workflow, app_state);
+    let tasks = create_monitor_tasks(&workflow, app_state, settings.clone());
 
     let mut grouped_tasks: HashMap<usize, Vec<Box<dyn ApiMonitor + Send + Sync>>> = HashMap::new();
     for task in tasks {
         let order = task.get_task_order(); // Assume this exists and is correct
-        grouped_tasks.entry(order).or_insert_with(Vec::new).push(task);
+        grouped_tasks.entry(order).or_default().push(task);
     }
 
-    let mut order_keys: Vec<&usize> = grouped_tasks.keys().collect();
+    let mut order_keys: Vec<usize> = grouped_tasks.keys().copied().collect();
     order_keys.sort();
 
-    for order_key in order_keys {
-        if let Some(task_group) = grouped_tasks.get(order_key) {
-            let futures: Vec<_> = task_group.iter().map(|task| {
-                let client_clone = client.clone();
-                async move {
+    // Without ordering, every group joins a single stage.
+    let stages = if settings.respect_task_order.unwrap_or(true) {
+        plan_execution_stages(&order_keys, settings.parallel_groups.as_deref().unwrap_or_default())
+    } else {
+        vec![order_keys]
+    };
+    let log_per_request = settings.log_per_request();
+    let mut failures = 0;
+    for (stage_index, stage) in stages.into_iter().enumerate() {
+        // Stages not yet started when the run is cancelled are skipped entirely.
+        if cancel.is_cancelled() {
+            info!("Workflow '{}' cancelled before stage {}", workflow_name, stage_index + 1);
+            break;
+        }
+        let stage_tasks = stage.iter().filter_map(|order_key| grouped_tasks.get(order_key)).flatten();
+        let futures: Vec<_> = stage_tasks.map(|task| {
+            let client_clone = clients.for_monitor(task.as_ref()).clone();
+            let cancel = &cancel;
+            async move {
+                if log_per_request {
                     info!("Starting '{}'", task.describe());
-                    match task.execute(&client_clone, workflow_name).await {
-                        Ok(_) => info!("Successfully completed '{}'", task.describe()),
-                        Err(e) => log::error!("Task '{}' failed: {}", task.describe(), e),
-                    }
                 }
-            }).collect();
+                match task.execute(&client_clone, workflow_name, cancel).await {
+                    Ok(_) => {
+                        if log_per_request {
+                            info!("Successfully completed '{}'", task.describe());
+                        }
+                        true
+                    },
+                    // A task stopped by cancellation did not fail; it just has no result.
+                    Err(e) if cancel.is_cancelled() => {
+                        info!("Task '{}' stopped: {}", task.describe(), e);
+                        true
+                    },
+                    Err(e) => {
+                        log::error!("Task '{}' failed: {}", task.describe(), e);
+                        false
+                    },
+                }
+            }
+        }).collect();
 
-            join_all(futures).await; // Execute concurrently within the same order group
+        let outcomes = join_all(futures).await; // Execute concurrently within the same stage
+        failures += outcomes.iter().filter(|succeeded| !**succeeded).count();
+        if !log_per_request {
+            let succeeded = outcomes.iter().filter(|succeeded| **succeeded).count();
+            info!(
+                "Workflow '{}' stage {}: {} succeeded, {} failed",
+                workflow_name, stage_index + 1, succeeded, outcomes.len() - succeeded
+            );
         }
     }
+
+    failures
 }
 
+/// Splits off the APIs that name their own `workflow`, so each named sequence runs concurrently with the others.
+///
+/// APIs without a `workflow` stay in the file's workflow. Teardown is left out, since it runs once after all of them.
+fn split_workflow(workflow: &Arc<Workflow>) -> Vec<Arc<Workflow>> {
+    if workflow.apis.iter().all(|api| api.workflow.is_none()) {
+        return vec![workflow.clone()];
+    }
+
+    let mut sequences: Vec<(&str, Vec<ApiConfig>)> = vec![(workflow.name.as_str(), Vec::new())];
+    for api in &workflow.apis {
+        let name = api.workflow.as_deref().unwrap_or(&workflow.name);
+        match sequences.iter_mut().find(|(sequence, _)| *sequence == name) {
+            Some((_, apis)) => apis.push(api.clone()),
+            None => sequences.push((name, vec![api.clone()])),
+        }
+    }
+
+    sequences.into_iter()
+        .filter(|(_, apis)| !apis.is_empty())
+        .map(|(name, apis)| Arc::new(Workflow { name: name.to_string(), apis, teardown: None, teardown_failures_fail_run: None }))
+        .collect()
+}
 
+/// Arranges sorted task-order values into stages that run one after another.
+///
+/// Each order value normally forms its own stage. Values declared together in one of the
+/// `parallel_groups` are independent of each other, so consecutive ones share a stage and run
+/// concurrently. A group never runs ahead of an order value that lies between its members: the
+/// members on either side of it stay in separate stages.
+fn plan_execution_stages(order_keys: &[usize], parallel_groups: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut stages: Vec<Vec<usize>> = Vec::new();
 
-// Updated function signature to accept a vector of workflows
-pub async fn start_monitoring(settings: Arc<Settings>, workflows: Vec<Arc<Workflow>>, app_state: Arc<Mutex<AppState>>) {
-    let http_config = HttpClientConfig {
-        timeout_seconds: settings.http_timeout_seconds,
-        proxy_url: settings.http_proxy_url.clone(),
+    for &order_key in order_keys {
+        let joins_previous_stage = parallel_groups.iter()
+            .find(|group| group.contains(&order_key))
+            .zip(stages.last())
+            .is_some_and(|(group, stage)| stage.iter().all(|key| group.contains(key)));
+
+        match stages.last_mut() {
+            Some(stage) if joins_previous_stage => stage.push(order_key),
+            _ => stages.push(vec![order_key]),
+        }
+    }
+
+    stages
+}
+
+
+
+/// Builds the HTTP client configuration from the global settings, applying any overrides.
+fn http_client_config(settings: &Settings, overrides: Option<&HttpOverrides>) -> HttpClientConfig {
+    let overrides = overrides.cloned().unwrap_or_default();
+    HttpClientConfig {
+        timeout_seconds: overrides.timeout_seconds.unwrap_or(settings.http_timeout_seconds),
+        proxy_url: overrides.proxy_url.or_else(|| settings.http_proxy_url.clone()),
         default_headers: settings.http_default_headers.clone(),
-    };
+        // Request timelines include the redirects, so they need them recorded too.
+        record_redirects: settings.record_redirects.unwrap_or(false) || settings.request_timeline.unwrap_or(false),
+        cookie_store: settings.cookie_store.unwrap_or(false),
+        ip_version: settings.ip_version.clone(),
+        // Already validated at startup.
+        min_tls_version: settings.min_tls_version.as_deref().and_then(|version| parse_tls_version(version).ok()),
+        max_tls_version: settings.max_tls_version.as_deref().and_then(|version| parse_tls_version(version).ok()),
+        client_cert_path: settings.client_cert_path.clone(),
+        client_key_path: settings.client_key_path.clone(),
+        ca_cert_path: settings.ca_cert_path.clone(),
+    }
+}
 
-    let client = http_client::get_client(Some(http_config)).expect("Failed to create HTTP client");
+/// Runs every workflow once, then their teardowns, records the results, and returns a summary of them
+/// with how many monitors failed across them.
+///
+/// Cancelling `cancel` stops the run early: requests in flight finish or are abandoned, nothing new is
+/// started, and teardown still runs.
+pub async fn start_monitoring(settings: Arc<Settings>, workflows: Vec<Arc<Workflow>>, app_state: Arc<Mutex<AppState>>, cancel: CancellationToken) -> RunSummary {
+    // The settings were checked at startup, so building the clients only fails if a certificate file changed since.
+    let clients = MonitorClients::new(&settings).expect("Failed to create HTTP client");
 
-    // Iterate over workflows and spawn a new async task for each
-    let futures: Vec<_> = workflows.into_iter().map(|workflow| {
+    // Iterate over workflows and spawn a new async task for each, after splitting off APIs that name their own workflow
+    let futures: Vec<_> = workflows.iter().flat_map(split_workflow).map(|workflow| {
         let app_state_clone = app_state.clone();
-        let client_clone = client.clone();
-        monitor_single_workflow(workflow, app_state_clone, client_clone)
+        let clients_clone = clients.clone();
+        monitor_single_workflow(workflow, app_state_clone, settings.clone(), clients_clone, cancel.clone())
     }).collect();
 
     // Wait for all spawned tasks to complete
-    join_all(futures).await;
+    let failures: usize = join_all(futures).await.into_iter().sum();
+
+    // Teardown waits for every workflow, since any of them may still be using what it removes. It sees the
+    // variables of its workflow and of any workflows its APIs were split off into.
+    let mut teardown_variables = Vec::new();
+    for workflow in &workflows {
+        let mut variables = HashMap::new();
+        for split in split_workflow(workflow) {
+            variables.extend(app_state.lock().await.variables(&split.name).await);
+        }
+        teardown_variables.push(variables);
+    }
+    let teardown_failures: usize = join_all(workflows.iter().zip(&teardown_variables).map(|(workflow, variables)| run_teardown(workflow, &clients.default, variables)))
+        .await.into_iter().sum();
+    let failures = failures + teardown_failures;
+
+    let snapshot = app_state.lock().await.snapshot(settings.run_labels.clone().unwrap_or_default()).await;
+    // Keep a copy of the results with the run's other file outputs.
+    if output::run_dir().is_some() {
+        output::write_json("results.json", &snapshot);
+    }
+    if let Some(results_output_path) = &settings.results_output_path {
+        results_file::write(&output::resolve(results_output_path), &snapshot);
+    }
+    trace::write();
+    error_log::report_suppressed();
+
+    let summary = RunSummary { failed_monitors: Some(failures), ..RunSummary::from_snapshot(&snapshot) };
+    info!(
+        "Run summary: {} results, {} succeeded, {} failed ({:.1}% errors), response time min/mean/max {:.1}/{:.1}/{:.1}ms",
+        summary.total_results, summary.success_count, summary.error_count, summary.error_rate * 100.0,
+        summary.min_response_time_ms, summary.mean_response_time_ms, summary.max_response_time_ms
+    );
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{api, settings};
+    use crate::config::{AuthConfig, Extraction};
+    use std::collections::BTreeMap;
+    use std::time::Instant;
+
+    fn workflow(apis: Vec<ApiConfig>) -> Workflow {
+        Workflow { name: "Shop".to_string(), apis, teardown: None, teardown_failures_fail_run: None }
+    }
+
+    #[test]
+    fn test_auth_sets_a_sensitive_authorization_header() {
+        let api_config = ApiConfig { auth: Some(AuthConfig::Bearer { token: "${token}".to_string() }), ..api("Orders") };
+        let variables = HashMap::from([("token".to_string(), "abc123".to_string())]);
+
+        let request = create_request_builder(&Client::new(), &api_config, &variables).unwrap().build().unwrap();
+        let authorization = &request.headers()[AUTHORIZATION];
+        assert_eq!(authorization, "Bearer abc123");
+        assert!(authorization.is_sensitive());
+    }
+
+    #[test]
+    fn test_method_override_header_sends_post() {
+        let mut api_config = ApiConfig { method: HttpMethod::DELETE, ..api("Delete order") };
+        let client = Client::new();
+
+        let request = create_request_builder(&client, &api_config, &HashMap::new()).unwrap().build().unwrap();
+        assert_eq!(request.method(), reqwest::Method::DELETE);
+
+        api_config.method_override_header = Some("X-HTTP-Method-Override".to_string());
+        let request = create_request_builder(&client, &api_config, &HashMap::new()).unwrap().build().unwrap();
+        assert_eq!(request.method(), reqwest::Method::POST);
+        assert_eq!(request.headers()["x-http-method-override"], "DELETE");
+        assert_eq!(api_config.wire_method(), HttpMethod::POST);
+    }
+
+    #[test]
+    fn test_patch_sends_a_body_while_head_and_options_do_not() {
+        let mut api_config = ApiConfig { method: HttpMethod::PATCH, body: Some(r#"{"status": "shipped"}"#.to_string()), ..api("Update order") };
+        let client = Client::new();
+
+        let request = create_request_builder(&client, &api_config, &HashMap::new()).unwrap().build().unwrap();
+        assert_eq!(request.method(), Method::PATCH);
+        assert!(request.body().is_some());
+
+        for (method, expected) in [(HttpMethod::HEAD, Method::HEAD), (HttpMethod::OPTIONS, Method::OPTIONS)] {
+            api_config.method = method;
+            let request = create_request_builder(&client, &api_config, &HashMap::new()).unwrap().build().unwrap();
+            assert_eq!(request.method(), expected);
+            assert!(request.body().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multipart_sends_fields_and_files() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let upload = std::env::temp_dir().join(format!("load_test_tool_upload_{}.txt", std::process::id()));
+        fs::write(&upload, "file contents").unwrap();
+        let multipart = MultipartConfig {
+            fields: Some(BTreeMap::from([("owner".to_string(), "${user}".to_string())])),
+            files: Some(BTreeMap::from([("attachment".to_string(), upload.display().to_string())])),
+        };
+        let mut api_config = ApiConfig { method: HttpMethod::POST, multipart: Some(multipart), ..api("Upload") };
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        api_config.url = format!("http://{}/upload", listener.local_addr().unwrap());
+        let received = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            while !String::from_utf8_lossy(&request).trim_end().ends_with("--") {
+                let read = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n").await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let variables = HashMap::from([("user".to_string(), "alice".to_string())]);
+        create_request_builder(&Client::new(), &api_config, &variables).unwrap().send().await.unwrap();
+        let request = received.await.unwrap();
+        assert!(request.contains("content-type: multipart/form-data; boundary="), "{}", request);
+        assert!(request.contains("name=\"owner\"\r\n\r\nalice\r\n"), "{}", request);
+        let file_name = upload.file_name().unwrap().to_string_lossy();
+        assert!(request.contains(&format!("name=\"attachment\"; filename=\"{}\"", file_name)), "{}", request);
+        assert!(request.contains("file contents"));
+
+        api_config.multipart.as_mut().unwrap().files.as_mut().unwrap().insert("missing".to_string(), "/nonexistent/upload.bin".to_string());
+        let error = create_request_builder(&Client::new(), &api_config, &variables).unwrap_err();
+        let _ = fs::remove_file(&upload);
+        assert!(error.contains("'/nonexistent/upload.bin' for part 'missing'"), "{}", error);
+    }
+
+    #[test]
+    fn test_body_file_is_filled_in_for_each_request() {
+        std::env::set_var("FACTORY_TEST_USER_ID", "42");
+        let body_file = std::env::temp_dir().join(format!("load_test_tool_template_{}.json", std::process::id()));
+        fs::write(&body_file, r#"{"user": "${FACTORY_TEST_USER_ID}", "order": "${order_id}", "sent": ${__now__}}"#).unwrap();
+        let api_config = ApiConfig { method: HttpMethod::POST, body_file: Some(body_file.display().to_string()), ..api("Create order") };
+
+        let variables = HashMap::from([("order_id".to_string(), "A-7".to_string())]);
+        let request = create_request_builder(&Client::new(), &api_config, &variables).unwrap().build().unwrap();
+        let _ = fs::remove_file(&body_file);
+        std::env::remove_var("FACTORY_TEST_USER_ID");
+
+        let body: serde_json::Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!((&body["user"], &body["order"]), (&serde_json::json!("42"), &serde_json::json!("A-7")));
+        assert!(body["sent"].as_u64().is_some_and(|sent| sent > 1_700_000_000), "{}", body);
+    }
+
+    #[test]
+    fn test_compressed_bodies_carry_their_content_encoding() {
+        let mut api_config = ApiConfig {
+            method: HttpMethod::POST, body: Some(r#"{"order": "A-7"}"#.to_string()), compress_body: Some("gzip".to_string()), ..api("Create order")
+        };
+        let client = Client::new();
+
+        let request = create_request_builder(&client, &api_config, &HashMap::new()).unwrap().build().unwrap();
+        assert_eq!(request.headers()[CONTENT_ENCODING], "gzip");
+        assert_eq!(request.body().unwrap().as_bytes().unwrap(), &compression::compress("gzip", br#"{"order": "A-7"}"#).unwrap()[..]);
+
+        api_config.body = Some(String::new());
+        let request = create_request_builder(&client, &api_config, &HashMap::new()).unwrap().build().unwrap();
+        assert!(!request.headers().contains_key(CONTENT_ENCODING));
+    }
+
+    #[test]
+    fn test_per_api_timeout_overrides_the_client_timeout() {
+        let mut api_config = api("Report");
+        let client = Client::new();
+
+        assert_eq!(create_request_builder(&client, &api_config, &HashMap::new()).unwrap().build().unwrap().timeout(), None);
+        api_config.timeout_seconds = Some(30);
+        let request = create_request_builder(&client, &api_config, &HashMap::new()).unwrap().build().unwrap();
+        assert_eq!(request.timeout(), Some(&Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_check_requests_reports_each_request_that_cannot_be_built() {
+        let workflow = workflow(vec![
+            api("Orders"),
+            ApiConfig { headers: HashMap::from([("Bad Header".to_string(), "x".to_string())]), ..api("Broken header") },
+            ApiConfig { url: String::new(), batch: Some(vec![ApiConfig { url: "not a url".to_string(), ..api("Script") }]), ..api("Page") },
+        ]);
+
+        let checks = check_requests(&[Arc::new(workflow)], &Client::new());
+        let outcomes: Vec<(&str, bool)> = checks.iter().map(|check| (check.api.as_str(), check.error.is_none())).collect();
+        assert_eq!(outcomes, [("Orders", true), ("Broken header", false), ("Page / Script", false)]);
+        assert_eq!(checks[1].error.as_deref(), Some("Invalid header: Bad Header: x"));
+    }
+
+    #[test]
+    fn test_check_requests_reports_placeholders_nothing_fills_in() {
+        let extract = vec![Extraction { field: "token".to_string(), regex: None, variable: "token".to_string() }];
+        let workflow = workflow(vec![
+            ApiConfig { extract: Some(extract), ..api("Login") },
+            ApiConfig {
+                url: "http://localhost/${API_VERSION}/orders".to_string(),
+                headers: HashMap::from([("Authorization".to_string(), "Bearer ${token}".to_string())]),
+                body: Some(r#"{"at": "${__now__}", "region": "${REGION}"}"#.to_string()),
+                ..api("Orders")
+            },
+        ]);
+
+        let checks = check_requests(&[Arc::new(workflow)], &Client::new());
+        assert_eq!(checks[0].error, None);
+        assert_eq!(checks[1].error.as_deref(), Some("Unresolved placeholders: ${API_VERSION}, ${REGION}"));
+    }
+
+    #[tokio::test]
+    async fn test_teardown_failures_only_count_when_configured() {
+        let teardown = ApiConfig { url: "http://127.0.0.1:1/orders".to_string(), method: HttpMethod::DELETE, ..api("Delete orders") };
+        let mut workflow = Workflow { teardown: Some(vec![teardown]), ..workflow(Vec::new()) };
+        let client = Client::new();
+
+        assert_eq!(run_teardown(&workflow, &client, &HashMap::new()).await, 0);
+        workflow.teardown_failures_fail_run = Some(true);
+        assert_eq!(run_teardown(&workflow, &client, &HashMap::new()).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_extracted_token_authorizes_later_tasks() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Issues a token at /login and only accepts other requests that carry it.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let read = socket.read(&mut request).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&request[..read]).to_ascii_lowercase();
+                let response: &[u8] = if request.starts_with("post /login") {
+                    b"HTTP/1.1 200 OK\r\ncontent-length: 29\r\nconnection: close\r\n\r\n{\"auth\": {\"token\": \"abc123\"}}"
+                } else if request.contains("authorization: bearer abc123") {
+                    b"HTTP/1.1 204 No Content\r\nconnection: close\r\n\r\n"
+                } else {
+                    b"HTTP/1.1 401 Unauthorized\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                };
+                let _ = socket.write_all(response).await;
+            }
+        });
+        let extract = vec![Extraction { field: "auth.token".to_string(), regex: None, variable: "token".to_string() }];
+        let workflow = workflow(vec![
+            ApiConfig { task_order: Some(1), url: format!("{}/login", url), method: HttpMethod::POST, extract: Some(extract), ..api("Login") },
+            ApiConfig {
+                task_order: Some(2), url: format!("{}/orders", url),
+                headers: HashMap::from([("Authorization".to_string(), "Bearer ${token}".to_string())]), ..api("Orders")
+            },
+        ]);
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+
+        let summary = start_monitoring(Arc::new(settings()), vec![Arc::new(workflow)], app_state.clone(), CancellationToken::new()).await;
+
+        assert_eq!(summary.failed_monitors, Some(0));
+        assert_eq!((summary.total_results, summary.success_count, summary.error_count), (2, 2, 0));
+        assert!(summary.min_response_time_ms <= summary.mean_response_time_ms && summary.mean_response_time_ms <= summary.max_response_time_ms);
+        assert_eq!(app_state.lock().await.variables("Shop").await["token"], "abc123");
+    }
+
+    /// Serves every request after `delay`, handling connections concurrently.
+    async fn serve_slowly(delay: Duration) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = [0; 1024];
+                    let _ = socket.read(&mut request).await;
+                    tokio::time::sleep(delay).await;
+                    let _ = socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").await;
+                });
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_tasks_do_not_fail_the_run() {
+        let url = serve_slowly(Duration::from_secs(5)).await;
+        let workflow = workflow(vec![ApiConfig { task_order: Some(1), url, ..api("Slow") }]);
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let cancel = CancellationToken::new();
+        let cancel_soon = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cancel_soon.cancel();
+        });
+
+        let summary = start_monitoring(Arc::new(settings()), vec![Arc::new(workflow)], app_state.clone(), cancel).await;
+
+        assert_eq!(app_state.lock().await.task_monitoring_data.lock().await["Shop"]["Slow"].status, "CANCELLED");
+        assert_eq!((summary.tasks, summary.task_failures, summary.total_results, summary.error_count), (0, 0, 0, 0));
+        assert!(summary.passed());
+    }
+
+    #[tokio::test]
+    async fn test_named_workflows_run_concurrently_in_order() {
+        let url = serve_slowly(Duration::from_millis(200)).await;
+        let task = |name: &str, workflow: &str, task_order: usize| ApiConfig {
+            workflow: Some(workflow.to_string()), task_order: Some(task_order), url: url.clone(), ..api(name)
+        };
+        let workflow = workflow(vec![task("Browse", "browse", 1), task("Search", "browse", 2), task("Add to cart", "checkout", 1), task("Pay", "checkout", 2)]);
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+
+        let started = Instant::now();
+        let summary = start_monitoring(Arc::new(settings()), vec![Arc::new(workflow)], app_state.clone(), CancellationToken::new()).await;
+
+        // Each workflow takes two sequential requests; run one after the other they would take four.
+        assert_eq!(summary.failed_monitors, Some(0));
+        assert!(started.elapsed() < Duration::from_millis(700), "took {:?}", started.elapsed());
+        let results = app_state.lock().await.task_monitoring_data.lock().await.clone();
+        let mut workflows: Vec<_> = results.keys().cloned().collect();
+        workflows.sort();
+        assert_eq!(workflows, ["browse", "checkout"]);
+        assert_eq!(results["checkout"].len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_task_order_can_be_ignored() {
+        let url = serve_slowly(Duration::from_millis(200)).await;
+        let task = |name: &str, task_order: usize| ApiConfig { task_order: Some(task_order), url: url.clone(), ..api(name) };
+        let workflow = workflow(vec![task("Browse", 1), task("Search", 2), task("Pay", 3)]);
+        let settings = Settings { respect_task_order: Some(false), ..settings() };
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+
+        let started = Instant::now();
+        let summary = start_monitoring(Arc::new(settings), vec![Arc::new(workflow)], app_state.clone(), CancellationToken::new()).await;
+
+        // In task_order stages the three requests would take 600ms.
+        assert_eq!(summary.failed_monitors, Some(0));
+        assert!(started.elapsed() < Duration::from_millis(500), "took {:?}", started.elapsed());
+        assert_eq!(app_state.lock().await.task_monitoring_data.lock().await["Shop"].len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_task_iterations_run_in_sequence_and_are_all_recorded() {
+        let url = serve_slowly(Duration::ZERO).await;
+        let workflow = workflow(vec![ApiConfig { url, iterations: Some(3), delay_ms: Some(100), ..api("Browse") }]);
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+
+        let started = Instant::now();
+        let summary = start_monitoring(Arc::new(settings()), vec![Arc::new(workflow)], app_state.clone(), CancellationToken::new()).await;
+
+        assert_eq!(summary.failed_monitors, Some(0));
+        assert!(started.elapsed() >= Duration::from_millis(200), "took {:?}", started.elapsed());
+        let results = app_state.lock().await.task_monitoring_data.lock().await.clone();
+        assert_eq!(results["Shop"]["Browse"].response_time_stats.as_ref().unwrap().count, 3);
+    }
+
+    #[test]
+    fn test_apis_without_a_workflow_stay_in_the_file_workflow() {
+        let workflow = workflow(vec![api("Health"), ApiConfig { workflow: Some("checkout".to_string()), method: HttpMethod::POST, ..api("Pay") }]);
+
+        let split: Vec<(String, usize)> = split_workflow(&Arc::new(workflow)).iter()
+            .map(|workflow| (workflow.name.clone(), workflow.apis.len()))
+            .collect();
+        assert_eq!(split, [("Shop".to_string(), 1), ("checkout".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_stages_are_sequential_by_default() {
+        assert_eq!(plan_execution_stages(&[1, 2, 3], &[]), vec![vec![1], vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn test_parallel_groups_share_a_stage() {
+        let parallel_groups = vec![vec![2, 3], vec![5, 7]];
+        assert_eq!(
+            plan_execution_stages(&[1, 2, 3, 4, 5, 7], &parallel_groups),
+            vec![vec![1], vec![2, 3], vec![4], vec![5, 7]]
+        );
+    }
+
+    #[test]
+    fn test_parallel_group_members_keep_the_order_values_between_them() {
+        assert_eq!(plan_execution_stages(&[1, 2, 3], &[vec![1, 3]]), vec![vec![1], vec![2], vec![3]]);
+        assert_eq!(plan_execution_stages(&[1, 2, 3, 4], &[vec![1, 2, 4]]), vec![vec![1, 2], vec![3], vec![4]]);
+    }
 }
@@ -0,0 +1,162 @@
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::config::{prepare_api, ApiConfig, HttpMethod, Workflow};
+
+/// Response time threshold given to replayed requests; HAR files record none.
+const REPLAY_RESPONSE_TIME_THRESHOLD_MS: u64 = 1000;
+
+/// Headers reqwest sets itself, or that only made sense for the recorded connection.
+const SKIPPED_HEADERS: &[&str] = &["host", "content-length", "connection", "transfer-encoding", "accept-encoding"];
+
+#[derive(Debug, Deserialize)]
+struct Har {
+    log: HarLog,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarLog {
+    #[serde(default)]
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarEntry {
+    request: Option<HarRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarRequest {
+    method: Option<String>,
+    url: Option<String>,
+    #[serde(default)]
+    headers: Vec<HarHeader>,
+    post_data: Option<HarPostData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarPostData {
+    text: Option<String>,
+    encoding: Option<String>,
+}
+
+/// Loads a HAR file as a workflow whose APIs replay its requests in recorded order, each as a plain task.
+///
+/// The recording is replayed once per run, as the browsing session it captured; running every entry as its
+/// own load test would instead load each endpoint in turn. Entries that cannot be replayed are skipped with
+/// a warning explaining why.
+pub fn load_har_workflow(path: &str) -> anyhow::Result<Workflow> {
+    let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read HAR file at {}", path))?;
+    let har: Har = serde_json::from_str(&contents).with_context(|| format!("Failed to parse HAR file at {}", path))?;
+
+    let name = Path::new(path).file_stem().map_or_else(|| path.to_string(), |stem| stem.to_string_lossy().into_owned());
+    let mut workflow = Workflow { name, apis: Vec::new(), teardown: None, teardown_failures_fail_run: None };
+    workflow.apis = replay_apis(&workflow, har);
+    if workflow.apis.is_empty() {
+        log::warn!("HAR file {} has no replayable requests", path);
+    }
+    Ok(workflow)
+}
+
+fn replay_apis(workflow: &Workflow, har: Har) -> Vec<ApiConfig> {
+    har.log.entries.into_iter().enumerate()
+        .filter_map(|(index, entry)| match replay_api(workflow, index, entry) {
+            Ok(api) => Some(api),
+            Err(reason) => {
+                log::warn!("Skipping HAR entry {}: {}", index, reason);
+                None
+            }
+        })
+        .collect()
+}
+
+fn replay_api(workflow: &Workflow, index: usize, entry: HarEntry) -> Result<ApiConfig, String> {
+    let request = entry.request.ok_or("it has no request")?;
+    let url = request.url.filter(|url| !url.is_empty()).ok_or("its request has no URL")?;
+    let method: HttpMethod = request.method.as_deref().unwrap_or("GET").parse()?;
+
+    let headers: HashMap<String, String> = request.headers.into_iter()
+        // HTTP/2 pseudo-headers such as `:authority` are not real headers.
+        .filter(|header| !header.name.starts_with(':') && !SKIPPED_HEADERS.contains(&header.name.to_ascii_lowercase().as_str()))
+        .map(|header| (header.name, header.value))
+        .collect();
+
+    let body = match request.post_data {
+        Some(HarPostData { text: Some(text), encoding: Some(encoding) }) if encoding.eq_ignore_ascii_case("base64") => {
+            let bytes = STANDARD.decode(text.trim()).map_err(|e| format!("its base64 body does not decode: {}", e))?;
+            Some(String::from_utf8(bytes).map_err(|_| "its body is binary, which cannot be replayed")?)
+        },
+        Some(post_data) => post_data.text,
+        None => None,
+    };
+
+    let api: ApiConfig = serde_json::from_value(serde_json::json!({
+        "name": format!("{} {} #{}", method.as_str(), url, index),
+        "url": url,
+        "method": method.as_str(),
+        "headers": headers,
+        "expected_field": "",
+        "response_time_threshold": REPLAY_RESPONSE_TIME_THRESHOLD_MS,
+        "body": body,
+        "task_order": index,
+    })).map_err(|e| e.to_string())?;
+    prepare_api(workflow, api).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workflow() -> Workflow {
+        Workflow { name: "session".to_string(), apis: Vec::new(), teardown: None, teardown_failures_fail_run: None }
+    }
+
+    fn har(entries: &str) -> Har {
+        serde_json::from_str(&format!(r#"{{"log": {{"entries": {}}}}}"#, entries)).unwrap()
+    }
+
+    #[test]
+    fn test_entries_become_tasks_in_recorded_order() {
+        let apis = replay_apis(&workflow(), har(r#"[
+            {"request": {"method": "GET", "url": "http://localhost/a", "headers": [
+                {"name": ":authority", "value": "localhost"},
+                {"name": "Accept", "value": "application/json"},
+                {"name": "Content-Length", "value": "0"}
+            ]}},
+            {"request": {"method": "post", "url": "http://localhost/b",
+                "postData": {"mimeType": "application/json", "text": "eyJpZCI6IDF9", "encoding": "base64"}}}
+        ]"#));
+
+        assert_eq!(apis.len(), 2);
+        assert_eq!(apis[0].headers, HashMap::from([("Accept".to_string(), "application/json".to_string())]));
+        assert_eq!(apis[0].task_order, Some(0));
+        assert!(apis.iter().all(|api| !api.load_test.unwrap_or(false)));
+        assert_eq!(apis[1].method, HttpMethod::POST);
+        assert_eq!(apis[1].body.as_deref(), Some(r#"{"id": 1}"#));
+    }
+
+    #[test]
+    fn test_unreplayable_entries_are_skipped() {
+        let apis = replay_apis(&workflow(), har(r#"[
+            {"response": {}},
+            {"request": {"method": "GET"}},
+            {"request": {"method": "CONNECT", "url": "http://localhost:443"}},
+            {"request": {"method": "PUT", "url": "http://localhost/blob", "postData": {"text": "/w==", "encoding": "base64"}}},
+            {"request": {"url": "http://localhost/ok"}}
+        ]"#));
+
+        assert_eq!(apis.len(), 1);
+        assert_eq!(apis[0].url, "http://localhost/ok");
+        assert_eq!(apis[0].method, HttpMethod::GET);
+    }
+}
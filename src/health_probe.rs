@@ -0,0 +1,116 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::{sync::Mutex, task::JoinHandle, time::Instant};
+
+use crate::config::HealthProbeConfig;
+
+/// Interval between probes when not configured.
+const DEFAULT_INTERVAL_SECS: u64 = 1;
+
+/// One request sent to the health probe endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthProbeSample {
+    /// When the probe was sent, in milliseconds from the start of the load test.
+    pub offset_ms: u64,
+    /// The probe's response time in milliseconds.
+    pub response_time_ms: u64,
+    /// The HTTP status code returned, if a response was received.
+    pub status_code: Option<u16>,
+    /// Whether the probe failed or exceeded the configured threshold.
+    pub degraded: bool,
+}
+
+/// The health probe series recorded alongside a load test.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthProbeReport {
+    pub url: String,
+    /// Probes that received no response or a non-2xx status.
+    pub failures: usize,
+    /// Probes that failed or were slower than the threshold.
+    pub degraded: usize,
+    pub average_response_time_ms: u64,
+    pub samples: Vec<HealthProbeSample>,
+}
+
+/// A probe running in the background for the duration of a load test.
+pub struct HealthProbe {
+    url: String,
+    handle: JoinHandle<()>,
+    samples: Arc<Mutex<Vec<HealthProbeSample>>>,
+}
+
+impl HealthProbe {
+    /// Starts probing `config.url` every `interval_secs`, timing offsets from `started`.
+    pub fn spawn(client: Client, config: &HealthProbeConfig, started: Instant) -> Self {
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let interval = Duration::from_secs(config.interval_secs.unwrap_or(DEFAULT_INTERVAL_SECS).max(1));
+        let threshold_ms = config.threshold_ms;
+        let url = config.url.clone();
+
+        let samples_clone = samples.clone();
+        let url_clone = url.clone();
+        let handle = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let sent = Instant::now();
+                let status_code = client.get(&url_clone).send().await.ok().map(|resp| resp.status().as_u16());
+                let response_time_ms = sent.elapsed().as_millis() as u64;
+
+                let failed = !status_code.is_some_and(|status| (200..300).contains(&status));
+                let slow = threshold_ms.is_some_and(|threshold| response_time_ms > threshold);
+                samples_clone.lock().await.push(HealthProbeSample {
+                    offset_ms: sent.duration_since(started).as_millis() as u64,
+                    response_time_ms,
+                    status_code,
+                    degraded: failed || slow,
+                });
+            }
+        });
+
+        HealthProbe { url, handle, samples }
+    }
+
+    /// Stops probing and summarizes what was observed.
+    pub async fn finish(self) -> HealthProbeReport {
+        self.handle.abort();
+        let samples = std::mem::take(&mut *self.samples.lock().await);
+        summarize(self.url, samples)
+    }
+}
+
+fn summarize(url: String, samples: Vec<HealthProbeSample>) -> HealthProbeReport {
+    let failures = samples.iter()
+        .filter(|sample| !sample.status_code.is_some_and(|status| (200..300).contains(&status)))
+        .count();
+    let degraded = samples.iter().filter(|sample| sample.degraded).count();
+    let average_response_time_ms = if samples.is_empty() {
+        0
+    } else {
+        samples.iter().map(|sample| sample.response_time_ms).sum::<u64>() / samples.len() as u64
+    };
+
+    HealthProbeReport { url, failures, degraded, average_response_time_ms, samples }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_separates_failures_from_slow_probes() {
+        let sample = |response_time_ms, status_code, degraded| HealthProbeSample { offset_ms: 0, response_time_ms, status_code, degraded };
+        let report = summarize("http://localhost/health".to_string(), vec![
+            sample(10, Some(200), false),
+            sample(90, Some(200), true),
+            sample(20, Some(503), true),
+            sample(40, None, true),
+        ]);
+
+        assert_eq!(report.failures, 2);
+        assert_eq!(report.degraded, 3);
+        assert_eq!(report.average_response_time_ms, 40);
+    }
+}
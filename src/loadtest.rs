@@ -1,14 +1,16 @@
 
 use serde::{Serialize, Deserialize};
-use futures::future::join_all;
+use futures::{future::{self, join_all}, stream, StreamExt};
 use async_trait::async_trait;
-use reqwest::{Client, StatusCode};
-use std::{collections::HashMap, sync::Arc};
+use reqwest::{header::{HeaderMap, CONNECTION}, Client, Method, Response, StatusCode, Version};
+use std::{collections::{BTreeMap, HashMap}, sync::{atomic::{AtomicUsize, Ordering}, Arc, PoisonError}};
 use tokio::sync::{Mutex, Semaphore};
-use std::time::Duration;
-use tokio::time::Instant;
+use tokio::task::JoinError;
+use tokio_util::sync::CancellationToken;
+use std::time::{Duration, SystemTime};
+use tokio::time::{Instant, MissedTickBehavior};
 
-use crate::{appstate::AppState, config::{ApiConfig, HttpMethod, LoadTestConfig}, factory::{create_request_builder, ApiMonitor}};
+use crate::{appstate::AppState, health_probe::{HealthProbe, HealthProbeReport}, config::{ApiConfig, HttpMethod, LoadStage, LoadTestConfig, RampShape, Settings}, factory::{create_request_builder_for_iteration, ApiMonitor}, utils::{data_feeder, error_kind::{self, ErrorKind}, error_log, failure_rules::classify_failure, health_score::health_score, http_client::{send_retrying_broken_connection, with_redirect_limit}, ndjson::{self, NdjsonRecord}, request_history::HistorySample, request_limit, response_times::ResponseTimeStats, slowest::{SlowRequest, SlowestRequests}, snippet::body_snippet, trace, weighted::WeightedPicker}};
 
 
 /// Monitors and executes load tests for a specific API endpoint.
@@ -24,6 +26,11 @@ pub struct LoadTest {
     pub app_state: Arc<Mutex<AppState>>,
     /// Configuration specifying the parameters of the load test.
     pub load_test_config: LoadTestConfig,
+    /// Global settings, consulted for logging behavior.
+    pub settings: Arc<Settings>,
+    /// The APIs requests are spread across in proportion to their weights, `api_config` among them;
+    /// empty when every request goes to `api_config`.
+    pub weighted_apis: Vec<Arc<ApiConfig>>,
 }
 
 /// Represents the aggregated results of a load test.
@@ -36,6 +43,11 @@ pub struct LoadTestMonitoringData {
     pub api_url: String,
     /// The total number of requests made during the load test.
     pub total_requests: usize,
+    /// Every request that completed, including failed and warmup ones; at most `total_requests_cap`.
+    pub requests_completed: usize,
+    /// The configured cap on requests sent, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_requests_cap: Option<usize>,
     /// The number of successful requests.
     pub success_count: usize,
     /// The number of failed requests.
@@ -50,16 +62,223 @@ pub struct LoadTestMonitoringData {
     pub max_response_time_ms: u128,
     /// A distribution of response status codes received.
     pub status_code_distribution: HashMap<u16, usize>,
-    /// The 95th percentile response time in milliseconds.
-    pub percentile_95th_response_time_ms: u128,
+    /// The 95th percentile response time in milliseconds, omitted when there were too few samples.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percentile_95th_response_time_ms: Option<u128>,
+    /// Latency percentiles, mean, and spread over every measured response, omitted when none arrived.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<ResponseTimeStats>,
+    /// Whether fewer requests than `min_samples_for_percentiles` completed, so percentiles are omitted.
+    pub insufficient_samples: bool,
     /// The rate of requests per second.
     pub requests_per_second: f64,
+    /// Completed requests divided by the measured wall-clock duration of the test.
+    pub achieved_rps: f64,
+    /// The configured minimum requests per second, if any.
+    pub min_achieved_rps: Option<f64>,
+    /// The rate requests were paced to, if any; compare with `achieved_rps` to confirm the pacing held.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_rps: Option<usize>,
+    /// The rate requests were started at in an open model, if one was configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arrival_rate: Option<usize>,
+    /// In an open model, arrivals dropped because `max_in_flight` requests were already in flight.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dropped_arrivals: Option<usize>,
+    /// Whether `achieved_rps` met `min_achieved_rps`; `None` when no minimum is configured.
+    pub throughput_passed: Option<bool>,
     /// The average size of the response in bytes (placeholder for actual data collection).
     pub average_bytes_per_response: u128,
+    /// Total bytes received in responses that passed, excluding error responses.
+    pub successful_bytes: u64,
+    /// Bytes per second of passing responses over the measured duration of the test.
+    pub goodput_bytes_per_sec: f64,
     /// The HTTP method used in the load test.
     pub method: HttpMethod,
+    /// The shape of the ramp the load followed.
+    pub ramp_shape: RampShape,
+    /// Whether the test ended by reaching its maximum load, running out of time, or failing too often.
+    pub end_reason: EndReason,
+    /// Whether the test was stopped early because its error rate exceeded `abort_on_error_rate`.
+    pub aborted: bool,
+    /// The error rate over the most recent requests when the test was stopped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abort_error_rate: Option<f64>,
+    /// How many priming requests succeeded before measurement began, when priming is configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primed_connections: Option<usize>,
+    /// How many primed connections the server left open, judged by the `Connection` header of each priming response.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verified_primed_connections: Option<usize>,
+    /// How long priming the connection pool took, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priming_duration_ms: Option<u64>,
+    /// Scheduled versus achieved requests for each second of the test.
+    pub rps_timeline: Vec<RpsBucket>,
+    /// The number of users after each tick of the ramp.
+    pub ramp_timeline: Vec<RampStep>,
+    /// The most measured requests that were in flight at once.
+    pub peak_concurrency: usize,
+    /// Warmup requests sent before measurement began, by count or by time, which are excluded from every other metric.
+    pub warmup_requests: usize,
+    /// The slowest responses of the test, slowest first.
+    pub slowest_requests: Vec<SlowRequest>,
+    /// Time from the start of the first attempt to the end of this one, including retries and backoff, in milliseconds.
+    pub retry_elapsed_ms: u64,
+    /// The backoffs applied before this attempt, one per earlier failed attempt.
+    pub retry_backoffs: Vec<RetryBackoff>,
+    /// Retries of individual requests after a connection error or 5xx response.
+    pub retries_used: usize,
+    /// Time spent on failed attempts and backoffs before each request's final attempt, summed over requests, in milliseconds.
+    pub request_retry_elapsed_ms: u64,
+    /// Requests that passed only after being retried.
+    pub recovered_by_retry: usize,
+    /// Idempotent requests resent once after a stale connection broke under them; these are not counted as retries or failures.
+    pub connection_retries: usize,
+    /// Requests and errors grouped by how many requests were in flight when each completed.
+    pub errors_by_concurrency: Vec<ConcurrencyBand>,
+    /// The health probe series recorded during the test, when a probe is configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health_probe: Option<HealthProbeReport>,
+    /// The mean outcome score of the measured requests, weighted by the API's `status_weights`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health_score: Option<f64>,
+    /// The concurrency limit the server advertised in `auto_concurrency_from_header`, if it sent one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub advertised_concurrency_limit: Option<usize>,
+    /// Measured requests sent to each weighted API, by name, when the test mixes several.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub requests_by_api: BTreeMap<String, usize>,
 }
 
+/// Requests that completed while the number in flight was within a range.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConcurrencyBand {
+    /// Lowest in-flight count covered by this band, inclusive.
+    pub min_concurrency: usize,
+    /// Highest in-flight count covered by this band, inclusive.
+    pub max_concurrency: usize,
+    /// Requests that completed within this band.
+    pub requests: usize,
+    /// Of those, the requests that failed or did not pass.
+    pub errors: usize,
+}
+
+/// The load reached at one tick of a load test's ramp.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RampStep {
+    /// Offset of this tick from the start of the test, in seconds.
+    pub second: u64,
+    /// The total number of users once this tick's users were added.
+    pub users: usize,
+}
+
+/// Requests per second the generator aimed for and actually achieved during one second of a test.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpsBucket {
+    /// Offset of this bucket from the start of the test, in seconds.
+    pub second: u64,
+    /// Requests the generator issued in this second.
+    pub target_rps: f64,
+    /// Requests that completed with a response in this second.
+    pub achieved_rps: f64,
+    /// Whether achieved fell below target by more than the configured tolerance.
+    pub below_target: bool,
+}
+
+/// The outcome of a single load test request.
+struct RequestSample {
+    /// When the request completed, relative to the start of the test.
+    completed_at: Duration,
+    /// Status, duration, and response size, or the error that prevented a response.
+    outcome: Result<(StatusCode, Duration, usize), String>,
+    /// How long the final attempt took, including one that ended in an error.
+    elapsed: Duration,
+    /// Whether the response was successful and matched none of the API's failure rules.
+    passed: bool,
+    /// How many requests were in flight, including this one, when it completed.
+    concurrency: usize,
+    /// Whether the request was one of the configured warmup requests.
+    warmup: bool,
+    /// What kind of failure the request ran into, if it did not pass.
+    error_kind: Option<ErrorKind>,
+    /// How many times the request was retried before its final attempt.
+    retries: usize,
+    /// How many of its attempts were resent after their connection broke.
+    connection_retries: usize,
+    /// How long its failed attempts and the backoffs after them took.
+    retry_elapsed: Duration,
+    /// Which of the weighted APIs the request went to; always 0 without weights.
+    api: usize,
+}
+
+/// Why a load test stopped adding load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndReason {
+    /// The load reached `max_load`, or a concurrency limit advertised by the server.
+    MaxLoad,
+    /// `max_duration_secs` elapsed before the load reached its maximum.
+    MaxDuration,
+    /// The recent error rate exceeded `abort_on_error_rate`.
+    ErrorRate,
+    /// The run was stopped, e.g. through `/stop_load_tests`.
+    Cancelled,
+    /// `total_requests` had been sent.
+    TotalRequests,
+    /// Every one of the configured `stages` ran to its end.
+    StagesComplete,
+    /// Every row of a `data_file` read in unique order had been used.
+    DataExhausted,
+}
+
+/// A wait applied before retrying a failed load test attempt.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryBackoff {
+    /// The attempt that failed, counting from 1.
+    pub attempt: usize,
+    /// How that attempt failed, which decides the backoff applied.
+    pub error_kind: ErrorKind,
+    /// How long the retry waited, in milliseconds.
+    pub delay_ms: u64,
+}
+
+/// Why a load test attempt failed.
+#[derive(Debug)]
+struct AttemptError {
+    error_kind: ErrorKind,
+    message: String,
+    /// Whether another attempt could pass; a test stopped to protect the target, or one that ran to its end, is final.
+    retryable: bool,
+}
+
+impl AttemptError {
+    fn retryable(error_kind: ErrorKind, message: String) -> Self {
+        AttemptError { error_kind, message, retryable: true }
+    }
+
+    fn last(error_kind: ErrorKind, message: String) -> Self {
+        AttemptError { error_kind, message, retryable: false }
+    }
+}
+
+/// Fraction by which achieved RPS may trail the target before a bucket is flagged.
+const DEFAULT_RPS_TOLERANCE: f64 = 0.1;
+/// Number of responses kept in a load test's slowest-requests record.
+const SLOWEST_REQUESTS: usize = 10;
+/// Default size cap for bodies captured with slow requests.
+const DEFAULT_SLOW_BODY_MAX_BYTES: usize = 512;
+/// Seconds each load level is held by the step ramp when not configured.
+const DEFAULT_RAMP_STEP_SECS: u64 = 5;
+/// Wait before retrying a failed attempt when no backoff is configured.
+const DEFAULT_RETRY_BACKOFF_SECS: u64 = 5;
+/// Width of the concurrency bands used when not configured.
+const DEFAULT_CONCURRENCY_BAND_SIZE: usize = 10;
+/// Recent requests the abort error rate is taken over when not configured.
+const DEFAULT_ABORT_MIN_SAMPLES: usize = 20;
+/// Wait before the first retry of a single request; it doubles with each further retry.
+const REQUEST_RETRY_BACKOFF_MS: u64 = 100;
+
 
 #[async_trait]
 impl ApiMonitor for LoadTest {
@@ -72,19 +291,38 @@ impl ApiMonitor for LoadTest {
     ///
     /// # Returns
     /// A `Result` indicating the success or failure of the load test execution.
-    async fn execute(&self, client: &Client, workflow_name: &str) -> Result<(), String> {
+    async fn execute(&self, client: &Client, workflow_name: &str, cancel: &CancellationToken) -> Result<(), String> {
         let mut attempt = 0;
-        let max_attempts = self.load_test_config.retry_count.unwrap_or(0) as usize; // Provide a default value if `retry_count` is None and cast to usize for comparison
+        let max_attempts = self.load_test_config.rerun_count.unwrap_or(0);
+        let retries_started = Instant::now();
+        let mut retry_backoffs: Vec<RetryBackoff> = Vec::new();
+        // Requests issued so far; a rerun continues from here, so `total_requests` caps every attempt together.
+        let mut issued: u64 = 0;
+        let total_requests_cap = self.load_test_config.total_requests.map(|cap| cap as u64);
 
         while attempt <= max_attempts {
-            match self.run_load_test(client, workflow_name).await {
+            match self.run_load_test(client, workflow_name, &mut issued, retries_started, &retry_backoffs, cancel).await {
                 Ok(_) => return Ok(()),
-                Err(e) if attempt < max_attempts => { // Correct comparison with unwrapped and converted retry_count
-                    log::warn!("Load test attempt {} failed: {}. Retrying...", attempt + 1, e);
+                // A cancelled test is not retried.
+                Err(AttemptError { message: e, .. }) if cancel.is_cancelled() => return Err(e),
+                // Nor is one that ended on its own terms; rerunning an aborted test would load the failing target all over again.
+                Err(AttemptError { message: e, retryable: false, .. }) => return Err(e),
+                Err(AttemptError { error_kind, message: e, .. }) if attempt < max_attempts && total_requests_cap.is_none_or(|cap| issued < cap) => {
+                    // A target that cannot be reached at all may warrant a longer wait than one returning errors.
+                    let backoff_secs = match error_kind {
+                        ErrorKind::Connect => self.settings.connect_error_backoff_secs,
+                        _ => self.settings.http_error_backoff_secs,
+                    }.unwrap_or(DEFAULT_RETRY_BACKOFF_SECS);
+                    log::warn!("Load test attempt {} failed ({:?}): {}. Retrying in {}s...", attempt + 1, error_kind, e, backoff_secs);
                     attempt += 1;
-                    tokio::time::sleep(Duration::from_secs(5)).await; // Backoff before retry
+                    retry_backoffs.push(RetryBackoff { attempt, error_kind, delay_ms: backoff_secs * 1000 });
+                    // Backoff before retry, unless the run is cancelled meanwhile.
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_secs(backoff_secs)) => {},
+                        _ = cancel.cancelled() => return Err(format!("Load test cancelled before attempt {}: {}", attempt + 1, e)),
+                    }
                 },
-                Err(e) => return Err(format!("Load test failed after {} attempts: {}", attempt + 1, e)),
+                Err(AttemptError { message: e, .. }) => return Err(format!("Load test failed after {} attempts: {}", attempt + 1, e)),
             }
         }
 
@@ -115,6 +353,11 @@ impl ApiMonitor for LoadTest {
     fn get_task_order(&self) -> usize {
         self.api_config.task_order.unwrap_or(usize::MAX)
     }
+
+    /// Load tests run on the load-test HTTP client, which may have its own settings.
+    fn is_load_test(&self) -> bool {
+        true
+    }
 }
 
 impl LoadTest {
@@ -127,125 +370,459 @@ impl LoadTest {
     ///
     /// # Parameters
     /// - `client`: The HTTP client used to send requests to the API.
+    /// - `issued`: Requests issued by earlier attempts, which count towards `total_requests`; updated as requests are issued.
+    /// - `retries_started`: When the first attempt of this load test began.
+    /// - `retry_backoffs`: The backoffs applied after earlier failed attempts.
+    /// - `cancel`: Stops the test from adding load once cancelled; requests already sent are allowed to finish.
     ///
     /// # Returns
-    /// A `Result<(), String>` indicating the success or failure of the load test.
-    /// On success, it returns `Ok(())`. On failure, it returns an `Err` with an error message.
-    async fn run_load_test(&self, client: &Client, workflow_name: &str) -> Result<(), String> {
+    /// A `Result` indicating the success or failure of the load test.
+    /// On success, it returns `Ok(())`. On failure, it returns an `Err` with the kind of failure, an error message,
+    /// and whether another attempt is worth making.
+    async fn run_load_test(&self, client: &Client, workflow_name: &str, issued: &mut u64, retries_started: Instant, retry_backoffs: &[RetryBackoff], cancel: &CancellationToken) -> Result<(), AttemptError> {
+        // Warms up the connection pool before anything is measured, if configured.
+        let priming = match self.load_test_config.prime_connections {
+            Some(count) if count > 0 => Some(self.prime_connections(client, count).await),
+            _ => None,
+        };
+        // Connections the server closed right away would add handshake latency to the measured run.
+        if let Some(priming) = &priming {
+            let expected = self.load_test_config.prime_connections.unwrap_or_default();
+            if priming.verified < expected {
+                let message = format!("Only {}/{} primed connections for '{}' were left open by the server", priming.verified, expected, self.api_config.name);
+                if self.load_test_config.require_primed_connections.unwrap_or(false) {
+                    return Err(AttemptError::retryable(ErrorKind::Connect, message));
+                }
+                log::warn!("{}", message);
+            }
+        }
+
         // Records the start time of the load test to calculate the total duration later.
         let start_time = Instant::now();
+        let started_at = SystemTime::now();
+        // Probes a separate endpoint alongside the load, to see whether the rest of the service degrades.
+        let health_probe = self.load_test_config.health_probe.as_ref()
+            .map(|probe_config| HealthProbe::spawn(client.clone(), probe_config, start_time));
 
         // Initializes a vector to store results of each load test step.
-        let mut all_results = Vec::new();
+        let mut all_results: Vec<RequestSample> = Vec::new();
+        // Tracks how many requests were issued at each second offset of the test.
+        let mut scheduled_per_second: Vec<(u64, usize)> = Vec::new();
 
         // Sets a sensible default for max_duration if not specified, here assumed as 1 second for simplicity.
         let sensible_max_duration_secs: u64 = 1;
-        // Retrieves max_duration from the test configuration, using the sensible default if not specified.
-        let max_duration_secs = self.load_test_config.max_duration_secs
-                                    .map(|secs| secs as u64)
-                                    .unwrap_or(sensible_max_duration_secs);
+        // Staged tests last as long as their stages; others take max_duration from the configuration, using the sensible default if not specified.
+        let stages = self.load_test_config.stages.as_deref();
+        let max_duration_secs = match stages {
+            Some(stages) => stages.iter().map(|stage| stage.duration_secs).sum(),
+            None => self.load_test_config.max_duration_secs
+                        .map(|secs| secs as u64)
+                        .unwrap_or(sensible_max_duration_secs),
+        };
         // Converts the duration from seconds to a Duration object for easier comparison.
         let max_duration = Duration::from_secs(max_duration_secs);
 
-        // Initializes the current load based on the test configuration or defaults to 0.
-        let mut current_load = self.load_test_config.initial_load.unwrap_or_default();
+        // No users run until the first tick, which starts `initial_load` of them at once.
+        let mut current_load = 0;
+        let initial_load = self.load_test_config.initial_load.unwrap_or_default();
         // Retrieves the maximum load from the configuration or uses the maximum usize value if not specified.
-        let max_load = self.load_test_config.max_load.unwrap_or(usize::MAX);
+        let mut max_load = self.load_test_config.max_load.unwrap_or(usize::MAX);
+        // The total number of users after each tick, offset by seconds from the start.
+        let mut ramp_timeline: Vec<RampStep> = Vec::new();
+        // The concurrency limit the server advertises, if configured; 0 until a response carries it.
+        let concurrency_header: Option<Arc<str>> = self.load_test_config.auto_concurrency_from_header.as_deref().map(Arc::from);
+        let advertised_limit = Arc::new(AtomicUsize::new(0));
         // Retrieves the spawn rate (users per second) from the configuration, defaulting to 1 if not specified.
         let spawn_rate = self.load_test_config.spawn_rate.unwrap_or(1);
+        let ramp_shape = self.load_test_config.ramp_shape.unwrap_or_default();
+        let ramp_step_secs = self.load_test_config.ramp_step_secs.unwrap_or(DEFAULT_RAMP_STEP_SECS);
+        let mut tick: u64 = 0;
+
+        let log_per_request = self.settings.log_per_request();
+        // The slowest responses across the whole test; bodies are only snapshotted for entries that are kept.
+        let slowest = Arc::new(std::sync::Mutex::new(SlowestRequests::new(SLOWEST_REQUESTS)));
+        let slow_body_max_bytes = self.settings.capture_slow_bodies.unwrap_or(false)
+            .then(|| self.settings.slow_body_max_bytes.unwrap_or(DEFAULT_SLOW_BODY_MAX_BYTES));
+        // Requests currently awaiting a response, sampled as each completes.
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        // Counts requests issued so far, across attempts; pre-request scripts see it as their iteration number.
+        let first_iteration = *issued;
+        let warmup_requests = self.load_test_config.warmup_requests.unwrap_or(0) as u64;
+        let warmup_duration = Duration::from_secs(self.load_test_config.warmup_secs.unwrap_or(0));
+        let retry_count = self.load_test_config.retry_count.unwrap_or(0);
+        let retry_budget = self.load_test_config.retry_max_elapsed_secs.map(Duration::from_secs);
+        // Spaces requests evenly at the target rate across all users; ticks missed while none were waiting are not made up.
+        let pacer = self.load_test_config.target_rps.map(|target_rps| {
+            let mut pacer = tokio::time::interval(Duration::from_secs_f64(1.0 / target_rps as f64));
+            pacer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            Arc::new(Mutex::new(pacer))
+        });
+        // Once enough requests have completed, a target failing more often than this stops the ramp early.
+        let abort_on_error_rate = self.load_test_config.abort_on_error_rate;
+        let abort_min_samples = self.load_test_config.abort_min_samples.unwrap_or(DEFAULT_ABORT_MIN_SAMPLES);
+        let mut abort_error_rate: Option<f64> = None;
+        let mut data_exhausted = false;
+        // Variables extracted by earlier tasks, as they were when the load test started.
+        let variables = Arc::new(self.app_state.lock().await.variables(workflow_name).await);
+        // Weighted APIs are picked per iteration, so the same test sends the same mix each run.
+        let picker = WeightedPicker::new(self.weighted_apis.iter().map(|api| api.weight.unwrap_or_default()));
+        // Requests are issued from this loop alone, and `issued` carries over from earlier attempts, so it is all the
+        // cap needs to be checked against.
+        let total_requests_cap = self.load_test_config.total_requests.map(|cap| cap as u64);
+        // An open model starts requests at a fixed rate whatever the target's latency, bounded only by `max_in_flight`.
+        let arrival_rate = self.load_test_config.arrival_rate;
+        let open_slots = arrival_rate.map(|_| Arc::new(Semaphore::new(self.load_test_config.max_in_flight.unwrap_or(Semaphore::MAX_PERMITS))));
+        let dropped_arrivals = Arc::new(AtomicUsize::new(0));
+        // Requests still in flight from earlier ticks; only an open model moves on without waiting for them.
+        let mut pending = Vec::new();
 
         // Sets up a repeating interval of 1 second to control the spawn rate.
         let mut interval = tokio::time::interval(Duration::from_secs(1));
 
         // Continues to execute the load test until the current load reaches the max load or the max duration is exceeded.
-        while current_load < max_load && start_time.elapsed() < max_duration {
-            // Waits for the next tick of the interval, effectively pausing for 1 second.
-            interval.tick().await;
+        while (arrival_rate.is_some() || stages.is_some() || current_load < max_load) && start_time.elapsed() < max_duration && total_requests_cap.is_none_or(|cap| *issued < cap) {
+            // Waits for the next tick of the interval, effectively pausing for 1 second, unless the test is cancelled first.
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = cancel.cancelled() => break,
+            }
+            // The deadline may pass while waiting; nothing new is spawned after it.
+            if start_time.elapsed() >= max_duration {
+                break;
+            }
 
             // Calculates the number of new users to spawn this tick, without exceeding the max load.
-            let new_users = std::cmp::min(spawn_rate, max_load - current_load);
-            // Updates the current load by adding the new users.
-            current_load += new_users;
+            let new_users = match (arrival_rate, stages, tick) {
+                (Some(arrival_rate), _, _) => arrival_rate,
+                (None, Some(stages), _) => stage_load(stages, initial_load, start_time.elapsed()).min(max_load),
+                (None, None, 0) if initial_load > 0 => initial_load.min(max_load),
+                (None, None, _) => ramp_users(ramp_shape, tick, current_load, spawn_rate, ramp_step_secs).min(max_load - current_load),
+            };
+            // The last tick before the cap only spawns the requests still allowed.
+            let new_users = match total_requests_cap {
+                Some(cap) => new_users.min((cap - *issued) as usize),
+                None => new_users,
+            };
+            // Nor more than the unique data rows left; once they are all used, no more requests are issued.
+            let new_users = match picker.as_ref().map_or_else(|| data_feeder::unique_rows_left(&self.api_config), |_| None) {
+                Some(0) => {
+                    data_exhausted = true;
+                    break;
+                },
+                Some(rows_left) => new_users.min(rows_left),
+                None => new_users,
+            };
+            tick += 1;
+            // Updates the current load by adding the new users; in an open model or a staged test, the load is
+            // set afresh each tick rather than built up.
+            current_load = if arrival_rate.is_some() || stages.is_some() { new_users } else { current_load + new_users };
 
             // Logs the number of new users being spawned and the total current load.
             log::info!("Spawning {} new users, total users: {}", new_users, current_load);
+            // Records how many requests the generator intends to issue in this second.
+            scheduled_per_second.push((start_time.elapsed().as_secs(), new_users));
+            ramp_timeline.push(RampStep { second: start_time.elapsed().as_secs(), users: current_load });
 
             // Creates a semaphore with a number of permits equal to the current load, controlling concurrent access.
-            let semaphore = Arc::new(Semaphore::new(current_load));
+            let semaphore = open_slots.clone().unwrap_or_else(|| Arc::new(Semaphore::new(current_load)));
 
             // Maps each new user to a spawned task, creating a vector of these tasks.
-            let tasks = (0..new_users).map(|_| {
+            let tasks = (0..new_users).map(|user| {
+                let iteration = *issued + user as u64;
+                // Warmup requests are sent like any other, with the same headers and timeout; only their results are set aside.
+                let warmup = iteration - first_iteration < warmup_requests || start_time.elapsed() < warmup_duration;
                 // Clones the client and API configuration for use within the async task.
                 let client_clone = client.clone();
-                let api_config_clone = self.api_config.clone();
+                let api = picker.as_ref().map(|picker| picker.pick(iteration)).unwrap_or_default();
+                let api_config_clone = match picker {
+                    Some(_) => self.weighted_apis[api].clone(),
+                    None => self.api_config.clone(),
+                };
+                // Rows are claimed as requests are issued, so the rows left are known before the next tick.
+                let data_row = data_feeder::claim_row(&api_config_clone, iteration);
                 let semaphore_clone = semaphore.clone();
+                let slowest_clone = slowest.clone();
+                let in_flight_clone = in_flight.clone();
+                let concurrency_header_clone = concurrency_header.clone();
+                let advertised_limit_clone = advertised_limit.clone();
+                let variables_clone = variables.clone();
+                let pacer_clone = pacer.clone();
+                let dropped_clone = dropped_arrivals.clone();
+                let arrival_offset = arrival_rate.map(|arrival_rate| Duration::from_secs_f64(user as f64 / arrival_rate as f64));
 
                 // Spawns an asynchronous task for each user.
                 tokio::spawn(async move {
-                    // Acquires a permit from the semaphore before proceeding, ensuring concurrency control.
-                    let _permit = semaphore_clone.acquire_owned().await.expect("Failed to acquire semaphore permit");
-                    // Records the start time of the request for duration calculation.
-                    let start = Instant::now();
-
-                    // Attempts to create a request builder using the client and API configuration.
-                    let request_result = create_request_builder(&client_clone, &api_config_clone);
-                    match request_result {
-                        // If successful, sends the request and awaits the response.
-                        Ok(request_builder) => {
-                            let response = request_builder.send().await;
+                    // Waits for its turn at the target rate first, so no permit sits idle while it waits.
+                    if let Some(pacer) = &pacer_clone {
+                        pacer.lock().await.tick().await;
+                    }
+                    let _permit = match arrival_offset {
+                        // Arrivals are spread across the second; one finding every slot taken is dropped, since
+                        // waiting for a slot would hold back the offered load.
+                        Some(arrival_offset) => {
+                            tokio::time::sleep(arrival_offset).await;
+                            match semaphore_clone.try_acquire_owned() {
+                                Ok(permit) => permit,
+                                Err(_) => {
+                                    dropped_clone.fetch_add(1, Ordering::SeqCst);
+                                    return None;
+                                },
+                            }
+                        },
+                        // Acquires a permit from the semaphore before proceeding, ensuring concurrency control.
+                        None => semaphore_clone.acquire_owned().await.expect("Failed to acquire semaphore permit"),
+                    };
+                    // Waits for room under the run's request limit, if any, before this request counts as in flight.
+                    let _request_permit = request_limit::acquire().await;
+                    in_flight_clone.fetch_add(1, Ordering::SeqCst);
+                    let lane = trace::is_tracing().then(trace::Lane::acquire);
+                    let mut passed = false;
+                    let error_kind;
+
+                    // Sends the request, retrying transient failures; only the final attempt is measured.
+                    let SentRequest { outcome: request_result, retries, connection_retries, started: start, retry_elapsed } = match data_row {
+                        Ok(data_row) => send_with_retries(&client_clone, &api_config_clone, iteration, data_row, retry_count, retry_budget, &variables_clone).await,
+                        Err(e) => SentRequest::unsent(e),
+                    };
+                    let outcome = match request_result {
+                        // If the request could be built, inspects the response of the final attempt.
+                        Ok(response) => {
                             match response {
                                 // On successful response, extracts the status code, response body, and calculates the duration.
                                 Ok(resp) => {
                                     let status = resp.status();
+                                    // Only the first advertised limit is used, so the cap doesn't move around mid-test.
+                                    if let Some(header_name) = &concurrency_header_clone {
+                                        if let Some(limit) = advertised_concurrency_limit(resp.headers(), header_name) {
+                                            let _ = advertised_limit_clone.compare_exchange(0, limit, Ordering::SeqCst, Ordering::SeqCst);
+                                        }
+                                    }
+                                    // Failure rules may inspect headers, so they are only kept when rules exist.
+                                    let failure_rules = api_config_clone.failure_rules.as_deref().unwrap_or_default();
+                                    let response_headers = (!failure_rules.is_empty()).then(|| resp.headers().clone());
                                     let body = resp.text().await.unwrap_or_default();
                                     let bytes = body.len();
                                     let duration = start.elapsed();
+                                    passed = api_config_clone.is_expected_status(status.as_u16()) && response_headers.is_none_or(|headers| {
+                                        classify_failure(failure_rules, status.as_u16(), &headers, &body).is_none()
+                                    });
+                                    let mut slowest = slowest_clone.lock().unwrap_or_else(PoisonError::into_inner);
+                                    offer_slow_request(&mut slowest, warmup, duration, || SlowRequest {
+                                        completed_at_ms: start_time.elapsed().as_millis() as u64,
+                                        response_time_ms: duration.as_millis() as u64,
+                                        status_code: status.as_u16(),
+                                        bytes,
+                                        body: slow_body_max_bytes.map(|max_bytes| body_snippet(&body, max_bytes)),
+                                    });
+                                    drop(slowest);
+                                    error_kind = (!passed).then_some(ErrorKind::Http);
+                                    if ndjson::is_streaming() {
+                                        let outcome = if passed { "OK" } else { "ERROR" };
+                                        ndjson::emit(NdjsonRecord::now(&api_config_clone.url, &api_config_clone.method, outcome, Some(status.as_u16()), duration.as_millis() as u64));
+                                    }
                                     // Returns the status code, duration, and response size.
                                     Ok((status, duration, bytes))
                                 },
                                 // Logs any errors encountered while sending the request.
                                 Err(e) => {
-                                    log::error!("Request error: {}", e);
-                                    Err(e.to_string())
+                                    if log_per_request && error_log::allow() {
+                                        log::error!("Request error: {}", e);
+                                    }
+                                    error_kind = Some(error_kind::classify(&e));
+                                    if ndjson::is_streaming() {
+                                        ndjson::emit(NdjsonRecord::now(&api_config_clone.url, &api_config_clone.method, "ERROR", None, start.elapsed().as_millis() as u64));
+                                    }
+                                    if e.is_timeout() {
+                                        Err(format!("Request timed out after {:?}", start.elapsed()))
+                                    } else {
+                                        Err(e.to_string())
+                                    }
                                 },
                             }
                         },
                         // Logs any errors encountered while creating the request builder.
                         Err(e) => {
-                            log::error!("Request creation error: {}", e);
+                            if log_per_request && error_log::allow() {
+                                log::error!("Request creation error: {}", e);
+                            }
+                            error_kind = Some(ErrorKind::Other);
                             Err(e)
                         },
+                    };
+
+                    let concurrency = in_flight_clone.fetch_sub(1, Ordering::SeqCst);
+                    if let Some(lane) = &lane {
+                        let status_code = outcome.as_ref().ok().map(|(status, _, _)| status.as_u16());
+                        trace::record(&api_config_clone.name, "load_test", start.into_std(), start.elapsed(), lane, status_code);
                     }
+                    Some(RequestSample { completed_at: start_time.elapsed(), outcome, elapsed: start.elapsed(), passed, concurrency, warmup, error_kind, retries, connection_retries, retry_elapsed, api })
                 })
             }).collect::<Vec<_>>();
+            *issued += new_users as u64;
 
+            // A closed model waits for the step's requests to finish; an open one only collects those that already have.
+            let ready = if arrival_rate.is_some() {
+                pending.extend(tasks);
+                let (finished, unfinished): (Vec<_>, Vec<_>) = std::mem::take(&mut pending).into_iter().partition(|task| task.is_finished());
+                pending = unfinished;
+                finished
+            } else {
+                tasks
+            };
+            let step_results = collect_samples(join_all(ready).await, start_time.elapsed());
 
-            let join_results = join_all(tasks).await;
-            let step_results = join_results.into_iter().map(|join_result| {
-                join_result.unwrap_or_else(|join_error| {
-                    log::error!("Task panicked: {:?}", join_error);
-                    Err("Task panicked".to_string())
-                })
-            }).collect::<Vec<_>>();
+            // Without per-request logs, failures are reported once per step instead.
+            if !log_per_request {
+                let failed = step_results.iter().filter(|sample| sample.outcome.is_err()).count();
+                if failed > 0 && error_log::allow() {
+                    log::error!("{} of {} requests failed in this step", failed, step_results.len());
+                }
+            }
+
+            // Each step is added to the history as it completes, so latency can be followed during the test.
+            self.record_history(started_at, &step_results).await;
 
             all_results.extend(step_results);
 
+            // Continuing to load a target that is falling over only makes the outage worse.
+            if let Some(threshold) = abort_on_error_rate {
+                let recent_failures = all_results.iter().rev().filter(|sample| !sample.warmup).map(|sample| !sample.passed);
+                if let Some(error_rate) = rolling_error_rate(recent_failures, abort_min_samples).filter(|error_rate| *error_rate > threshold) {
+                    log::warn!("{:.0}% of the last {} requests to '{}' failed, above the abort threshold of {:.0}%; stopping the load test with {} users",
+                        error_rate * 100.0, abort_min_samples, self.api_config.name, threshold * 100.0, current_load);
+                    abort_error_rate = Some(error_rate);
+                    break;
+                }
+            }
+
+            // Respect a concurrency limit advertised by the server; later steps ramp no further than it.
+            let limit = advertised_limit.load(Ordering::SeqCst);
+            if arrival_rate.is_none() && limit > 0 && limit < max_load {
+                log::info!("'{}' advertises a concurrency limit of {}; capping the load there", self.api_config.name, limit);
+                max_load = limit;
+            }
+
             if start_time.elapsed() >= max_duration {
-                log::info!("Max duration reached, ending load test early.");
                 break;
             }
         }
 
+        // An open model's last requests are still in flight when the loop ends; they are allowed to finish.
+        if !pending.is_empty() {
+            let remaining = collect_samples(join_all(pending).await, start_time.elapsed());
+            self.record_history(started_at, &remaining).await;
+            all_results.extend(remaining);
+        }
+
         // Once the load test loop is complete, calculate the total duration
         let total_duration = start_time.elapsed();
+        let end_reason = if cancel.is_cancelled() {
+            EndReason::Cancelled
+        } else if abort_error_rate.is_some() {
+            EndReason::ErrorRate
+        } else if total_requests_cap.is_some_and(|cap| *issued >= cap) {
+            EndReason::TotalRequests
+        } else if data_exhausted {
+            EndReason::DataExhausted
+        } else if stages.is_some() {
+            EndReason::StagesComplete
+        } else if arrival_rate.is_none() && current_load >= max_load {
+            EndReason::MaxLoad
+        } else {
+            EndReason::MaxDuration
+        };
+        if end_reason == EndReason::MaxDuration {
+            log::info!("Max duration of {}s reached with {} users; in-flight requests were allowed to finish.", max_duration_secs, current_load);
+        } else if end_reason == EndReason::Cancelled {
+            log::info!("Load test '{}' cancelled with {} users; in-flight requests were allowed to finish.", self.api_config.name, current_load);
+        } else if end_reason == EndReason::TotalRequests {
+            log::info!("All {} requests sent with {} users; in-flight requests were allowed to finish.", *issued, current_load);
+        } else if end_reason == EndReason::DataExhausted {
+            log::info!("Every unique data row used after {} requests; in-flight requests were allowed to finish.", *issued);
+        }
+        let health_probe = match health_probe {
+            Some(probe) => Some(probe.finish().await),
+            None => None,
+        };
         log::info!("Load test completed. Total duration: {:?}", total_duration);
 
+        // Compare what the generator scheduled each second against what actually completed.
+        let completed_seconds: Vec<u64> = all_results.iter()
+            .filter(|sample| sample.outcome.is_ok())
+            .map(|sample| sample.completed_at.as_secs())
+            .collect();
+        let rps_tolerance = self.load_test_config.rps_tolerance.unwrap_or(DEFAULT_RPS_TOLERANCE);
+        let rps_timeline = rps_timeline(&scheduled_per_second, &completed_seconds, rps_tolerance);
+
+        let requests_completed = all_results.len();
+        // Warmup requests are naturally slow, so metrics below only reflect steady-state behavior.
+        let warmup_count = all_results.iter().filter(|sample| sample.warmup).count();
+        all_results.retain(|sample| !sample.warmup);
+
+        // Tally why measured requests failed, so repeated causes stand out from varied ones.
+        let error_messages: Vec<String> = all_results.iter()
+            .filter(|sample| !sample.passed)
+            .map(|sample| match &sample.outcome {
+                Err(e) => e.clone(),
+                Ok((status, _, _)) if self.api_config.is_expected_status(status.as_u16()) => format!("HTTP status {} matched a failure rule", status.as_u16()),
+                Ok((status, _, _)) => format!("HTTP status {}", status.as_u16()),
+            })
+            .collect();
+        if !error_messages.is_empty() {
+            self.app_state.lock().await.record_errors(&self.api_config.url, error_messages.iter().map(String::as_str)).await;
+        }
+
+        {
+            let app_state = self.app_state.lock().await;
+            let mut request_metrics = app_state.request_metrics.lock().await;
+            for sample in &all_results {
+                let api = self.weighted_apis.get(sample.api).unwrap_or(&self.api_config);
+                let status_code = sample.outcome.as_ref().ok().map(|(status, _, _)| status.as_u16());
+                request_metrics.record(&api.name, api.method.as_str(), status_code, sample.elapsed, !sample.passed);
+            }
+        }
+
+        // Goodput only counts bytes from responses that passed, so failing requests don't inflate capacity.
+        let successful_bytes: u64 = all_results.iter()
+            .filter(|sample| sample.passed)
+            .filter_map(|sample| sample.outcome.as_ref().ok())
+            .map(|(_, _, bytes)| *bytes as u64)
+            .sum();
+
+        let concurrency_samples: Vec<(usize, bool)> = all_results.iter()
+            .map(|sample| (sample.concurrency, !sample.passed))
+            .collect();
+        let band_size = self.load_test_config.concurrency_band_size.unwrap_or(DEFAULT_CONCURRENCY_BAND_SIZE);
+        let errors_by_concurrency = concurrency_bands(&concurrency_samples, band_size);
+        let peak_concurrency = concurrency_samples.iter().map(|(concurrency, _)| *concurrency).max().unwrap_or_default();
+
+        // Retries show how flaky the target is, even when every request eventually passed.
+        let retries_used: usize = all_results.iter().map(|sample| sample.retries).sum();
+        let request_retry_elapsed_ms = all_results.iter().map(|sample| sample.retry_elapsed).sum::<Duration>().as_millis() as u64;
+        let recovered_by_retry = all_results.iter().filter(|sample| sample.retries > 0 && sample.passed).count();
+        let connection_retries: usize = all_results.iter().map(|sample| sample.connection_retries).sum();
+
+        // Counts per weighted API show whether the mix sent matched the configured weights.
+        let mut requests_by_api = BTreeMap::new();
+        if picker.is_some() {
+            for sample in &all_results {
+                *requests_by_api.entry(self.weighted_apis[sample.api].name.clone()).or_insert(0) += 1;
+            }
+        }
+
+        let run_error_kind = error_kind::classify_run(all_results.iter().map(|sample| sample.error_kind.as_ref()));
+
+        // As for tasks, a 2xx response that failed a rule is scored like one that never arrived.
+        let health_score = health_score(self.api_config.status_weights.as_ref(), all_results.iter().map(|sample| {
+            let status_code = sample.outcome.as_ref().ok()
+                .map(|(status, _, _)| status.as_u16())
+                .filter(|status_code| sample.passed || !(200..300).contains(status_code));
+            (status_code, 1)
+        }));
+
         // Filter the results to only include successful requests and calculate statistics.
         let filtered_results: Vec<(StatusCode, Duration, usize)> = all_results.into_iter()
-            .filter_map(|result| match result {
-                Ok((status, duration, bytes)) => Some((status, duration, bytes)),
-                Err(_) => None,
-            })
+            .filter_map(|sample| sample.outcome.ok())
             .collect();
 
         // Analyze the filtered results to compute summary statistics.
@@ -258,12 +835,36 @@ impl LoadTest {
             status_code_distribution,
             percentile_95th_response_time_ms,
             requests_per_second,
-            average_bytes_per_response) = analyze_results(&filtered_results);
+            average_bytes_per_response) = analyze_results(&filtered_results, |status| self.api_config.is_expected_status(status.as_u16()));
+
+        // A percentile over a handful of requests is noise, so it is only reported with enough samples.
+        let insufficient_samples = filtered_results.len() < self.settings.min_samples_for_percentiles.unwrap_or(0);
+        let percentile_95th_response_time_ms = (!insufficient_samples).then_some(percentile_95th_response_time_ms);
+        let latency = ResponseTimeStats::from_times(
+            filtered_results.iter().map(|(_, duration, _)| duration.as_millis() as u64).collect(),
+            self.settings.min_samples_for_percentiles.unwrap_or(0),
+        );
+
+        // Achieved throughput is measured against wall-clock time, not summed response times.
+        let achieved_rps = if total_duration.as_secs_f64() > 0.0 {
+            filtered_results.len() as f64 / total_duration.as_secs_f64()
+        } else {
+            0.0
+        };
+        let goodput_bytes_per_sec = if total_duration.as_secs_f64() > 0.0 {
+            successful_bytes as f64 / total_duration.as_secs_f64()
+        } else {
+            0.0
+        };
+        let min_achieved_rps = self.load_test_config.min_achieved_rps;
+        let throughput_passed = min_achieved_rps.map(|min_rps| achieved_rps >= min_rps);
 
         // Construct LoadTestMonitoringData
         let load_test_data = LoadTestMonitoringData {
             api_url: self.api_config.url.clone(),
             total_requests: filtered_results.len(),
+            requests_completed,
+            total_requests_cap: self.load_test_config.total_requests,
             success_count,
             failure_count,
             median_response_time_ms,
@@ -272,18 +873,137 @@ impl LoadTest {
             max_response_time_ms,
             status_code_distribution,
             percentile_95th_response_time_ms,
+            latency,
+            insufficient_samples,
             requests_per_second,
             average_bytes_per_response,
+            successful_bytes,
+            goodput_bytes_per_sec,
+            achieved_rps,
+            min_achieved_rps,
+            target_rps: self.load_test_config.target_rps,
+            arrival_rate,
+            dropped_arrivals: arrival_rate.map(|_| dropped_arrivals.load(Ordering::SeqCst)),
+            throughput_passed,
             method: self.api_config.method.clone(),
+            ramp_shape,
+            end_reason,
+            aborted: abort_error_rate.is_some(),
+            abort_error_rate,
+            primed_connections: priming.as_ref().map(|priming| priming.primed),
+            verified_primed_connections: priming.as_ref().map(|priming| priming.verified),
+            priming_duration_ms: priming.as_ref().map(|priming| priming.duration.as_millis() as u64),
+            rps_timeline,
+            ramp_timeline,
+            peak_concurrency,
+            errors_by_concurrency,
+            health_probe,
+            health_score,
+            advertised_concurrency_limit: Some(advertised_limit.load(Ordering::SeqCst)).filter(|limit| *limit > 0),
+            warmup_requests: warmup_count,
+            retry_elapsed_ms: retries_started.elapsed().as_millis() as u64,
+            retry_backoffs: retry_backoffs.to_vec(),
+            retries_used,
+            request_retry_elapsed_ms,
+            recovered_by_retry,
+            connection_retries,
+            requests_by_api,
+            slowest_requests: slowest.lock().unwrap_or_else(PoisonError::into_inner).clone().into_sorted(),
         };
 
         // Update application state with load test data
-        update_load_test_app_state(&self.app_state, &workflow_name, &self.api_config.name, load_test_data).await;
+        update_load_test_app_state(&self.app_state, workflow_name, &self.api_config.name, load_test_data).await;
+
+        if end_reason == EndReason::Cancelled {
+            return Err(AttemptError::last(run_error_kind, format!("Load test '{}' was cancelled after {:?}", self.api_config.name, total_duration)));
+        }
+
+        if let Some(error_rate) = abort_error_rate {
+            return Err(AttemptError::last(run_error_kind, format!(
+                "Aborted after {:.0}% of the last {} requests failed, above the threshold of {:.0}%",
+                error_rate * 100.0,
+                abort_min_samples,
+                abort_on_error_rate.unwrap_or_default() * 100.0
+            )));
+        }
+
+        // A missed throughput target is the test's result, not a transient failure, so it is final too.
+        if throughput_passed == Some(false) {
+            return Err(AttemptError::last(run_error_kind, format!(
+                "Achieved {:.2} requests/second, below the required minimum of {:.2}",
+                achieved_rps,
+                min_achieved_rps.unwrap_or_default()
+            )));
+        }
 
         Ok(())
     }
+
+    /// Adds completed requests to the API's history, in the order they completed, when `history_size` is set.
+    async fn record_history(&self, started_at: SystemTime, samples: &[RequestSample]) {
+        let Some(history_size) = self.settings.history_size else {
+            return;
+        };
+        let mut samples: Vec<HistorySample> = samples.iter().map(|sample| {
+            let status_code = sample.outcome.as_ref().ok().map(|(status, _, _)| status.as_u16());
+            HistorySample::at(started_at + sample.completed_at, sample.elapsed.as_millis() as u64, status_code)
+        }).collect();
+        samples.sort_by_key(|sample| sample.timestamp_ms);
+        self.app_state.lock().await.record_history(&self.api_config.url, history_size, samples).await;
+    }
+
+    /// Opens connections to the target ahead of the measured run so the pool starts warm.
+    ///
+    /// Sends `count` bodiless `prime_method` requests, HEAD by default, with at most `prime_parallelism`
+    /// in flight at once, so the pool fills gradually rather than spiking the target. Returns how many
+    /// succeeded and how long priming took.
+    async fn prime_connections(&self, client: &Client, count: usize) -> Priming {
+        let parallelism = self.load_test_config.prime_parallelism.unwrap_or(count).max(1);
+        let method = self.load_test_config.prime_method.as_ref().map_or("HEAD", HttpMethod::as_str);
+        let method = Method::from_bytes(method.as_bytes()).expect("HttpMethod names are valid methods");
+        let started = Instant::now();
+
+        let responses: Vec<Response> = stream::iter(0..count)
+            .map(|_| client.request(method.clone(), &self.api_config.url).send())
+            .buffer_unordered(parallelism)
+            .filter_map(|result| future::ready(result.ok()))
+            .collect()
+            .await;
+        let verified = responses.iter()
+            .filter(|response| keeps_connection_open(response.version(), response.headers()))
+            .count();
+
+        let duration = started.elapsed();
+        log::info!("Primed {}/{} connections for '{}' in {:?}, {} verified open", responses.len(), count, self.api_config.name, duration, verified);
+        Priming { primed: responses.len(), verified, duration }
+    }
+}
+
+
+/// The outcome of priming the connection pool.
+struct Priming {
+    /// Priming requests that got a response.
+    primed: usize,
+    /// Responses after which the server kept the connection open for reuse.
+    verified: usize,
+    duration: Duration,
 }
 
+/// Whether the server keeps the connection a response arrived on open, so the pool can reuse it.
+///
+/// HTTP/1.1 and later keep connections open unless told to close; HTTP/1.0 only with `Connection: keep-alive`.
+fn keeps_connection_open(version: Version, headers: &HeaderMap) -> bool {
+    let has_token = |token: &str| headers.get_all(CONNECTION).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token));
+
+    if version <= Version::HTTP_10 {
+        has_token("keep-alive")
+    } else {
+        !has_token("close")
+    }
+}
 
 /// Analyzes the results of a load test to calculate various performance metrics.
 ///
@@ -294,6 +1014,7 @@ impl LoadTest {
 /// # Parameters
 /// - `results`: A slice of tuples containing the status code, duration, and size in bytes
 ///   of each request made during the load test.
+/// - `is_success`: Whether a status code counts as a successful request.
 ///
 /// # Returns
 /// A tuple containing the following aggregated metrics:
@@ -310,7 +1031,8 @@ impl LoadTest {
 ///
 /// The function ensures that all metrics are calculated accurately to provide a comprehensive
 /// overview of the load test's performance.
-fn analyze_results(results: &[(StatusCode, Duration, usize)]) -> (usize, usize, u128, u128, u128, u128, HashMap<u16, usize>, u128, f64, u128) {
+#[allow(clippy::type_complexity)]
+fn analyze_results(results: &[(StatusCode, Duration, usize)], is_success: impl Fn(StatusCode) -> bool) -> (usize, usize, u128, u128, u128, u128, HashMap<u16, usize>, u128, f64, u128) {
     let mut success_count = 0;
     let mut failure_count = 0;
     let mut total_duration = 0u128;
@@ -321,7 +1043,7 @@ fn analyze_results(results: &[(StatusCode, Duration, usize)]) -> (usize, usize,
     let mut status_code_distribution = HashMap::new();
 
     for (status, duration, bytes) in results {
-        if status.is_success() {
+        if is_success(*status) {
             success_count += 1;
         } else {
             failure_count += 1;
@@ -343,13 +1065,20 @@ fn analyze_results(results: &[(StatusCode, Duration, usize)]) -> (usize, usize,
         0
     };
 
+    // With no responses there is no minimum to report.
+    if results.is_empty() {
+        min_response_time_ms = 0;
+    }
+
     // Calculate the 95th percentile
     response_times_ms.sort_unstable();
-    let percentile_95th_index = (0.95 * (response_times_ms.len() as f64)).ceil() as usize - 1;
+    let percentile_95th_index = ((0.95 * (response_times_ms.len() as f64)).ceil() as usize).saturating_sub(1);
     let percentile_95th_response_time_ms = *response_times_ms.get(percentile_95th_index).unwrap_or(&0);
 
     // Calculate Median
-    let median_response_time_ms = if response_times_ms.len() % 2 == 0 {
+    let median_response_time_ms = if response_times_ms.is_empty() {
+        0
+    } else if response_times_ms.len() % 2 == 0 {
         let mid_right = response_times_ms.len() / 2;
         let mid_left = mid_right - 1;
         (response_times_ms[mid_left] + response_times_ms[mid_right]) / 2
@@ -386,6 +1115,185 @@ fn analyze_results(results: &[(StatusCode, Duration, usize)]) -> (usize, usize,
     )
 }
 
+/// A load test request after its final attempt.
+struct SentRequest {
+    /// The final attempt's response, or why it could not be built.
+    outcome: Result<reqwest::Result<Response>, String>,
+    /// How many times the request was retried.
+    retries: usize,
+    /// How many of its attempts were resent after their connection broke.
+    connection_retries: usize,
+    /// When the final attempt started.
+    started: Instant,
+    /// Time from the start of the first attempt to the start of the final one, spent on failed attempts and backoffs.
+    retry_elapsed: Duration,
+}
+
+impl SentRequest {
+    /// A request that was never attempted, for the reason given.
+    fn unsent(error: String) -> Self {
+        SentRequest { outcome: Err(error), retries: 0, connection_retries: 0, started: Instant::now(), retry_elapsed: Duration::ZERO }
+    }
+}
+
+/// Sends one load test request, retrying connection errors, timeouts, and 5xx responses up to `retry_count` times,
+/// and only while less than `retry_budget` has passed since the first attempt started.
+///
+/// Every attempt builds the request afresh, from the same `data_row`. Retries wait `REQUEST_RETRY_BACKOFF_MS` first, doubling each
+/// time up to 64 times that, but never past the end of `retry_budget`. Other transport errors, 4xx responses,
+/// and requests that cannot be built are not retried, since another attempt would not change them. An
+/// idempotent attempt that broke on a stale connection is first resent once on its own, which does not use
+/// up a retry.
+async fn send_with_retries(client: &Client, api_config: &ApiConfig, iteration: u64, data_row: Option<usize>, retry_count: usize, retry_budget: Option<Duration>, variables: &HashMap<String, String>) -> SentRequest {
+    let retry_broken_connection = api_config.retry_broken_connection.unwrap_or(true);
+    let mut retries = 0;
+    let mut connection_retries = 0;
+    let first_started = Instant::now();
+    loop {
+        let request_builder = create_request_builder_for_iteration(client, api_config, iteration, data_row, variables);
+        // Building the request may read, template, and compress its body, none of which is part of the response time.
+        let started = Instant::now();
+        let outcome = match request_builder {
+            Ok(request_builder) => {
+                let (response, resent) = with_redirect_limit(api_config.max_redirects(), send_retrying_broken_connection(request_builder, retry_broken_connection)).await;
+                connection_retries += usize::from(resent);
+                Ok(response)
+            },
+            Err(e) => Err(e),
+        };
+        let retryable = match &outcome {
+            // A 5xx response the API is expected to return is a pass, not a transient failure.
+            Ok(Ok(resp)) => resp.status().is_server_error() && !api_config.is_expected_status(resp.status().as_u16()),
+            Ok(Err(e)) => e.is_connect() || e.is_timeout(),
+            Err(_) => false,
+        };
+        let remaining_budget = retry_budget.map(|budget| budget.saturating_sub(first_started.elapsed()));
+        if !retryable || retries >= retry_count || remaining_budget == Some(Duration::ZERO) {
+            return SentRequest { outcome, retries, connection_retries, started, retry_elapsed: started - first_started };
+        }
+        let backoff = Duration::from_millis(REQUEST_RETRY_BACKOFF_MS << retries.min(6));
+        tokio::time::sleep(remaining_budget.map_or(backoff, |remaining| backoff.min(remaining))).await;
+        retries += 1;
+    }
+}
+
+/// The samples of finished request tasks, leaving out dropped arrivals and standing in for tasks that panicked.
+fn collect_samples(join_results: Vec<Result<Option<RequestSample>, JoinError>>, completed_at: Duration) -> Vec<RequestSample> {
+    join_results.into_iter().filter_map(|join_result| {
+        join_result.unwrap_or_else(|join_error| {
+            log::error!("Task panicked: {:?}", join_error);
+            Some(RequestSample { completed_at, outcome: Err("Task panicked".to_string()), elapsed: Duration::ZERO, passed: false, concurrency: 0, warmup: false, error_kind: Some(ErrorKind::Other), retries: 0, connection_retries: 0, retry_elapsed: Duration::ZERO, api: 0 })
+        })
+    }).collect()
+}
+
+/// Offers a response to the slowest-requests record, which never keeps warmup responses.
+fn offer_slow_request(slowest: &mut SlowestRequests, warmup: bool, duration: Duration, request: impl FnOnce() -> SlowRequest) {
+    if !warmup && slowest.admits(duration) {
+        slowest.insert(duration, request());
+    }
+}
+
+/// The concurrency limit advertised in `header_name`, if the response carries a positive whole number there.
+fn advertised_concurrency_limit(headers: &HeaderMap, header_name: &str) -> Option<usize> {
+    headers.get(header_name)?
+        .to_str().ok()?
+        .trim()
+        .parse().ok()
+        .filter(|limit| *limit > 0)
+}
+
+/// The number of users a ramp of the given shape adds at `tick`, counted in seconds from the start.
+///
+/// The result is not capped; callers limit it to what remains below `max_load`.
+fn ramp_users(shape: RampShape, tick: u64, current_load: usize, spawn_rate: usize, step_secs: u64) -> usize {
+    match shape {
+        RampShape::Linear => spawn_rate,
+        RampShape::Exponential => current_load.max(1),
+        RampShape::Step if tick.is_multiple_of(step_secs.max(1)) => spawn_rate,
+        RampShape::Step => 0,
+    }
+}
+
+/// The load a staged test runs at `elapsed` into the test, interpolated linearly from the previous
+/// stage's target, or `initial_load` during the first stage, to the current stage's target.
+///
+/// Past the last stage, the load is its target.
+fn stage_load(stages: &[LoadStage], initial_load: usize, elapsed: Duration) -> usize {
+    let elapsed = elapsed.as_secs_f64();
+    let mut stage_start = 0.0;
+    let mut from = initial_load as f64;
+    for stage in stages {
+        let stage_end = stage_start + stage.duration_secs as f64;
+        if elapsed < stage_end {
+            let progress = (elapsed - stage_start) / stage.duration_secs as f64;
+            return (from + (stage.target_load as f64 - from) * progress).round() as usize;
+        }
+        stage_start = stage_end;
+        from = stage.target_load as f64;
+    }
+    from as usize
+}
+
+/// The fraction of the most recent `window` requests that failed, given failures newest first.
+///
+/// `None` until `window` requests have completed, so a few early errors cannot stop a test.
+fn rolling_error_rate(recent_failures: impl Iterator<Item = bool>, window: usize) -> Option<f64> {
+    let (requests, failures) = recent_failures.take(window)
+        .fold((0, 0), |(requests, failures), failed| (requests + 1, failures + failed as usize));
+    (requests >= window).then(|| failures as f64 / requests as f64)
+}
+
+/// Groups `(concurrency, is_error)` samples into bands `band_size` wide, lowest first.
+///
+/// Only bands that saw at least one request are returned.
+fn concurrency_bands(samples: &[(usize, bool)], band_size: usize) -> Vec<ConcurrencyBand> {
+    let band_size = band_size.max(1);
+    let mut bands: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+    for &(concurrency, is_error) in samples {
+        let (requests, errors) = bands.entry(concurrency.saturating_sub(1) / band_size).or_default();
+        *requests += 1;
+        *errors += usize::from(is_error);
+    }
+
+    bands.into_iter().map(|(band, (requests, errors))| ConcurrencyBand {
+        min_concurrency: band * band_size + 1,
+        max_concurrency: (band + 1) * band_size,
+        requests,
+        errors,
+    }).collect()
+}
+
+/// Buckets scheduled and completed requests into one-second intervals.
+///
+/// `scheduled` holds the number of requests issued at a given second offset and `completed`
+/// the second offset at which each request completed. A bucket is flagged when its achieved
+/// rate trails the target by more than `tolerance`, a fraction of the target.
+fn rps_timeline(scheduled: &[(u64, usize)], completed: &[u64], tolerance: f64) -> Vec<RpsBucket> {
+    let last_second = scheduled.iter().map(|(second, _)| *second)
+        .chain(completed.iter().copied())
+        .max();
+
+    let Some(last_second) = last_second else {
+        return Vec::new();
+    };
+
+    (0..=last_second).map(|second| {
+        let target_rps = scheduled.iter()
+            .filter(|(scheduled_second, _)| *scheduled_second == second)
+            .map(|(_, count)| *count)
+            .sum::<usize>() as f64;
+        let achieved_rps = completed.iter().filter(|completed_second| **completed_second == second).count() as f64;
+
+        RpsBucket {
+            second,
+            target_rps,
+            achieved_rps,
+            below_target: target_rps > 0.0 && achieved_rps < target_rps * (1.0 - tolerance),
+        }
+    }).collect()
+}
+
 /// Updates the shared application state with the results of a load test.
 ///
 /// # Parameters
@@ -416,3 +1324,486 @@ async fn update_load_test_app_state(
     // Log the update for debugging or informational purposes
     log::info!("Updated load test data for {} in workflow {}", task_name, workflow_name);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::settings;
+    use crate::config::{DataOrder, FailureRule};
+
+    fn load_curve(shape: RampShape, initial_load: usize, max_load: usize, spawn_rate: usize, step_secs: u64, ticks: u64) -> Vec<usize> {
+        let mut current_load = initial_load;
+        (0..ticks).map(|tick| {
+            current_load += ramp_users(shape, tick, current_load, spawn_rate, step_secs).min(max_load - current_load);
+            current_load
+        }).collect()
+    }
+
+    #[test]
+    fn test_stages_interpolate_between_their_targets() {
+        let stages = [
+            LoadStage { duration_secs: 10, target_load: 50 },
+            LoadStage { duration_secs: 10, target_load: 50 },
+            LoadStage { duration_secs: 2, target_load: 200 },
+            LoadStage { duration_secs: 4, target_load: 0 },
+        ];
+        let loads: Vec<usize> = [0, 5, 10, 15, 21, 22, 25, 30].iter()
+            .map(|secs| stage_load(&stages, 0, Duration::from_secs(*secs)))
+            .collect();
+        assert_eq!(loads, vec![0, 25, 50, 50, 125, 200, 50, 0]);
+        assert_eq!(stage_load(&stages[..1], 10, Duration::from_millis(2500)), 20);
+    }
+
+    #[test]
+    fn test_analyze_results_handles_no_responses() {
+        let (success_count, failure_count, median, average, min, max, distribution, p95, rps, bytes) = analyze_results(&[], |status| status.is_success());
+        assert_eq!((success_count, failure_count, median, average, min, max, p95, bytes), (0, 0, 0, 0, 0, 0, 0, 0));
+        assert!(distribution.is_empty());
+        assert_eq!(rps, 0.0);
+    }
+
+    #[test]
+    fn test_warmup_requests_are_not_kept_as_slowest() {
+        let request = |response_time_ms| SlowRequest { completed_at_ms: 0, response_time_ms, status_code: 200, bytes: 0, body: None };
+        let mut slowest = SlowestRequests::new(2);
+        offer_slow_request(&mut slowest, true, Duration::from_millis(900), || request(900));
+        offer_slow_request(&mut slowest, false, Duration::from_millis(20), || request(20));
+        offer_slow_request(&mut slowest, false, Duration::from_millis(30), || request(30));
+
+        let kept: Vec<u64> = slowest.into_sorted().iter().map(|request| request.response_time_ms).collect();
+        assert_eq!(kept, vec![30, 20]);
+    }
+
+    #[test]
+    fn test_errors_are_banded_by_concurrency() {
+        let samples = [(1, false), (4, false), (5, true), (6, false), (12, true), (13, true)];
+        assert_eq!(concurrency_bands(&samples, 5), vec![
+            ConcurrencyBand { min_concurrency: 1, max_concurrency: 5, requests: 3, errors: 1 },
+            ConcurrencyBand { min_concurrency: 6, max_concurrency: 10, requests: 1, errors: 0 },
+            ConcurrencyBand { min_concurrency: 11, max_concurrency: 15, requests: 2, errors: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_linear_ramp_adds_spawn_rate() {
+        assert_eq!(load_curve(RampShape::Linear, 0, 7, 2, 5, 5), vec![2, 4, 6, 7, 7]);
+    }
+
+    #[test]
+    fn test_exponential_ramp_doubles() {
+        assert_eq!(load_curve(RampShape::Exponential, 0, 20, 2, 5, 7), vec![1, 2, 4, 8, 16, 20, 20]);
+        assert_eq!(load_curve(RampShape::Exponential, 3, 100, 2, 5, 3), vec![6, 12, 24]);
+    }
+
+    #[test]
+    fn test_step_ramp_holds_each_level() {
+        assert_eq!(load_curve(RampShape::Step, 0, 10, 4, 3, 8), vec![4, 4, 4, 8, 8, 8, 10, 10]);
+    }
+
+    #[test]
+    fn test_rps_timeline_flags_buckets_below_target() {
+        let scheduled = [(0, 10), (1, 10), (2, 10)];
+        let completed: Vec<u64> = std::iter::repeat_n(0, 10)
+            .chain(std::iter::repeat_n(1, 9))
+            .chain(std::iter::repeat_n(2, 5))
+            .collect();
+
+        let timeline = rps_timeline(&scheduled, &completed, 0.1);
+
+        assert_eq!(timeline.len(), 3);
+        assert_eq!(timeline.iter().map(|bucket| bucket.achieved_rps).collect::<Vec<_>>(), vec![10.0, 9.0, 5.0]);
+        assert_eq!(timeline.iter().map(|bucket| bucket.below_target).collect::<Vec<_>>(), vec![false, false, true]);
+    }
+
+    #[test]
+    fn test_rps_timeline_includes_completions_after_the_last_tick() {
+        let timeline = rps_timeline(&[(0, 2)], &[1, 1], 0.1);
+
+        assert_eq!(timeline[0], RpsBucket { second: 0, target_rps: 2.0, achieved_rps: 0.0, below_target: true });
+        assert_eq!(timeline[1], RpsBucket { second: 1, target_rps: 0.0, achieved_rps: 2.0, below_target: false });
+        assert!(rps_timeline(&[], &[], 0.1).is_empty());
+    }
+
+    #[test]
+    fn test_rolling_error_rate_needs_a_full_window() {
+        assert_eq!(rolling_error_rate([true, true, true].into_iter(), 4), None);
+        assert_eq!(rolling_error_rate([true, false, true, false, true, true].into_iter(), 4), Some(0.5));
+    }
+
+    #[test]
+    fn test_advertised_concurrency_limit_needs_a_positive_number() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(advertised_concurrency_limit(&headers, "X-RateLimit-Limit"), None);
+
+        headers.insert("x-ratelimit-limit", " 25 ".parse().unwrap());
+        assert_eq!(advertised_concurrency_limit(&headers, "X-RateLimit-Limit"), Some(25));
+
+        headers.insert("x-ratelimit-limit", "0".parse().unwrap());
+        assert_eq!(advertised_concurrency_limit(&headers, "X-RateLimit-Limit"), None);
+
+        headers.insert("x-ratelimit-limit", "100, 100;w=60".parse().unwrap());
+        assert_eq!(advertised_concurrency_limit(&headers, "X-RateLimit-Limit"), None);
+    }
+
+    /// Answers every connection with `status` and counts the requests received.
+    async fn serve_status(status: u16) -> (String, Arc<AtomicUsize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut request = [0; 1024];
+                let _ = socket.read(&mut request).await;
+                let response = format!("HTTP/1.1 {} Status\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (url, requests)
+    }
+
+    fn api_config(url: &str) -> ApiConfig {
+        serde_yaml::from_str(&format!(r#"
+name: "Orders"
+url: "{}"
+method: GET
+headers: {{}}
+expected_field: ""
+response_time_threshold: 1000
+"#, url)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_are_retried_but_client_errors_are_not() {
+        let client = Client::new();
+
+        let (url, requests) = serve_status(503).await;
+        let sent = send_with_retries(&client, &api_config(&url), 0, None, 2, None, &HashMap::new()).await;
+        assert_eq!(sent.outcome.unwrap().unwrap().status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!((sent.retries, requests.load(Ordering::SeqCst)), (2, 3));
+        // Backoffs of 100ms and 200ms came before the final attempt.
+        assert!(sent.retry_elapsed >= Duration::from_millis(300), "{:?}", sent.retry_elapsed);
+
+        let (url, requests) = serve_status(404).await;
+        let sent = send_with_retries(&client, &api_config(&url), 0, None, 2, None, &HashMap::new()).await;
+        assert_eq!((sent.retries, requests.load(Ordering::SeqCst)), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_priming_sends_the_configured_method() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let methods = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = methods.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let read = socket.read(&mut request).await.unwrap_or_default();
+                let method = String::from_utf8_lossy(&request[..read]).split(' ').next().unwrap_or_default().to_string();
+                seen.lock().unwrap().push(method);
+                let _ = socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").await;
+            }
+        });
+
+        for (prime_method, expected) in [(None, "HEAD"), (Some(HttpMethod::GET), "GET")] {
+            methods.lock().unwrap().clear();
+            let load_test_config = LoadTestConfig { prime_method, ..LoadTestConfig::default() };
+            let load_test = LoadTest { api_config: Arc::new(api_config(&url)), app_state: Arc::new(Mutex::new(AppState::default())), load_test_config, settings: Arc::new(settings()), weighted_apis: Vec::new() };
+
+            assert_eq!(load_test.prime_connections(&Client::new(), 2).await.primed, 2);
+            assert_eq!(*methods.lock().unwrap(), vec![expected; 2]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expected_server_errors_are_not_retried() {
+        let (url, requests) = serve_status(503).await;
+        let api_config = ApiConfig { expected_status_codes: Some(vec![503]), ..api_config(&url) };
+
+        let sent = send_with_retries(&Client::new(), &api_config, 0, None, 2, None, &HashMap::new()).await;
+        assert_eq!((sent.retries, requests.load(Ordering::SeqCst)), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_when_the_retry_budget_is_spent() {
+        let (url, _) = serve_status(503).await;
+        let started = Instant::now();
+
+        let sent = send_with_retries(&Client::new(), &api_config(&url), 0, None, 100, Some(Duration::from_millis(1000)), &HashMap::new()).await;
+
+        // Backoffs of 100, 200, and 400ms leave 300ms of the budget, so the fourth is cut short to fit it.
+        assert_eq!(sent.retries, 4);
+        assert!(sent.retry_elapsed >= Duration::from_millis(1000), "{:?}", sent.retry_elapsed);
+        assert!(started.elapsed() < Duration::from_millis(1500), "took {:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn test_responses_matching_a_failure_rule_are_streamed_as_errors() {
+        let (url, _) = serve_status(200).await;
+        let mut records = ndjson::subscribe();
+        let failure_rule = FailureRule { label: "Maintenance".to_string(), status_codes: Some(vec![200]), body_contains: None, header: None, header_value: None };
+        let api_config = ApiConfig { failure_rules: Some(vec![failure_rule]), ..api_config(&url) };
+        let load_test_config = LoadTestConfig { initial_load: Some(1), max_load: Some(1), ..LoadTestConfig::default() };
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let load_test = LoadTest { api_config: Arc::new(api_config), app_state: app_state.clone(), load_test_config, settings: Arc::new(settings()), weighted_apis: Vec::new() };
+
+        let _ = load_test.execute(&Client::new(), "Shop", &CancellationToken::new()).await;
+
+        // Other tests may stream records of their own meanwhile.
+        let record = loop {
+            let record = records.recv().await.unwrap();
+            if record.url == url {
+                break record;
+            }
+        };
+        assert_eq!((record.status.as_str(), record.status_code), ("ERROR", Some(200)));
+        let results = app_state.lock().await.load_test_monitoring_data.lock().await.clone();
+        let band = &results["Shop"]["Orders"].errors_by_concurrency[0];
+        assert_eq!((band.requests, band.errors), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_load_starts_at_initial_load_and_adds_spawn_rate_per_second() {
+        let (url, requests) = serve_status(200).await;
+        let load_test_config = LoadTestConfig { initial_load: Some(3), max_load: Some(5), spawn_rate: Some(4), max_duration_secs: Some(10), ..LoadTestConfig::default() };
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let load_test = LoadTest { api_config: Arc::new(api_config(&url)), app_state: app_state.clone(), load_test_config, settings: Arc::new(settings()), weighted_apis: Vec::new() };
+
+        load_test.execute(&Client::new(), "Shop", &CancellationToken::new()).await.unwrap();
+
+        let results = app_state.lock().await.load_test_monitoring_data.lock().await.clone();
+        let data = &results["Shop"]["Orders"];
+        let users: Vec<usize> = data.ramp_timeline.iter().map(|step| step.users).collect();
+        assert_eq!(users, vec![3, 5]);
+        assert!((1..=3).contains(&data.peak_concurrency), "{}", data.peak_concurrency);
+        assert_eq!((data.end_reason, requests.load(Ordering::SeqCst)), (EndReason::MaxLoad, 5));
+    }
+
+    #[tokio::test]
+    async fn test_load_test_stops_once_unique_data_rows_are_used_up() {
+        let (url, requests) = serve_status(200).await;
+        let path = std::env::temp_dir().join(format!("load_test_tool_unique_{}.csv", std::process::id()));
+        std::fs::write(&path, "email\na@example.com\nb@example.com\nc@example.com\n").unwrap();
+        let api_config = ApiConfig { data_file: Some(path.to_string_lossy().into_owned()), data_order: Some(DataOrder::Unique), ..api_config(&url) };
+        let load_test_config = LoadTestConfig { initial_load: Some(2), max_load: Some(100), spawn_rate: Some(2), max_duration_secs: Some(30), ..LoadTestConfig::default() };
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let load_test = LoadTest { api_config: Arc::new(api_config), app_state: app_state.clone(), load_test_config, settings: Arc::new(settings()), weighted_apis: Vec::new() };
+
+        load_test.execute(&Client::new(), "Shop", &CancellationToken::new()).await.unwrap();
+
+        let results = app_state.lock().await.load_test_monitoring_data.lock().await.clone();
+        let data = &results["Shop"]["Orders"];
+        assert_eq!((data.end_reason, data.failure_count, requests.load(Ordering::SeqCst)), (EndReason::DataExhausted, 0, 3));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_staged_load_test_sends_each_stage_at_its_rate() {
+        let (url, requests) = serve_status(200).await;
+        // Four requests a second for two seconds, down to one over the third, then one a second for two more.
+        let stages = vec![
+            LoadStage { duration_secs: 2, target_load: 4 },
+            LoadStage { duration_secs: 1, target_load: 1 },
+            LoadStage { duration_secs: 2, target_load: 1 },
+        ];
+        let load_test_config = LoadTestConfig { initial_load: Some(4), max_load: Some(10), stages: Some(stages), ..LoadTestConfig::default() };
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let load_test = LoadTest { api_config: Arc::new(api_config(&url)), app_state: app_state.clone(), load_test_config, settings: Arc::new(settings()), weighted_apis: Vec::new() };
+
+        load_test.execute(&Client::new(), "Shop", &CancellationToken::new()).await.unwrap();
+
+        let results = app_state.lock().await.load_test_monitoring_data.lock().await.clone();
+        let data = &results["Shop"]["Orders"];
+        assert_eq!(data.end_reason, EndReason::StagesComplete);
+        let users: Vec<usize> = data.ramp_timeline.iter().map(|step| step.users).collect();
+        assert_eq!(users, [4, 4, 4, 1, 1]);
+        let achieved: Vec<f64> = data.rps_timeline.iter().map(|bucket| bucket.achieved_rps).collect();
+        assert_eq!(achieved, [4.0, 4.0, 4.0, 1.0, 1.0]);
+        assert_eq!(requests.load(Ordering::SeqCst), 14);
+    }
+
+    #[tokio::test]
+    async fn test_load_test_stops_after_total_requests() {
+        let (url, requests) = serve_status(200).await;
+        let load_test_config = LoadTestConfig {
+            initial_load: Some(4), max_load: Some(100), spawn_rate: Some(4), max_duration_secs: Some(30), total_requests: Some(6), ..LoadTestConfig::default()
+        };
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let load_test = LoadTest { api_config: Arc::new(api_config(&url)), app_state: app_state.clone(), load_test_config, settings: Arc::new(settings()), weighted_apis: Vec::new() };
+
+        load_test.execute(&Client::new(), "Shop", &CancellationToken::new()).await.unwrap();
+
+        let results = app_state.lock().await.load_test_monitoring_data.lock().await.clone();
+        let data = &results["Shop"]["Orders"];
+        assert_eq!((data.end_reason, data.requests_completed, data.total_requests_cap), (EndReason::TotalRequests, 6, Some(6)));
+        let latency = data.latency.as_ref().unwrap();
+        assert_eq!(latency.count, 6);
+        assert!(latency.min <= latency.p50.unwrap() && latency.p50 <= latency.p90 && latency.p90 <= latency.p99 && latency.p99 == Some(latency.max));
+        assert_eq!(requests.load(Ordering::SeqCst), 6);
+    }
+
+    #[tokio::test]
+    async fn test_open_model_keeps_its_arrival_rate_and_drops_arrivals_over_max_in_flight() {
+        let settings = Arc::new(settings());
+
+        let (url, requests) = serve_status(200).await;
+        let load_test_config = LoadTestConfig { arrival_rate: Some(4), max_duration_secs: Some(2), ..LoadTestConfig::default() };
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let load_test = LoadTest { api_config: Arc::new(api_config(&url)), app_state: app_state.clone(), load_test_config, settings: settings.clone(), weighted_apis: Vec::new() };
+        load_test.execute(&Client::new(), "Shop", &CancellationToken::new()).await.unwrap();
+
+        let results = app_state.lock().await.load_test_monitoring_data.lock().await.clone();
+        let data = &results["Shop"]["Orders"];
+        // The default max_load of 10 would have ended a closed model after 10 requests.
+        assert_eq!((data.end_reason, data.requests_completed, requests.load(Ordering::SeqCst)), (EndReason::MaxDuration, 8, 8));
+        assert_eq!((data.arrival_rate, data.dropped_arrivals), (Some(4), Some(0)));
+
+        // A target that never answers holds on to the only slot, so every later arrival in the second is dropped.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+        let load_test_config = LoadTestConfig { arrival_rate: Some(4), max_in_flight: Some(1), max_duration_secs: Some(1), ..LoadTestConfig::default() };
+        let api_config = ApiConfig { timeout_seconds: Some(1), ..api_config(&url) };
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let load_test = LoadTest { api_config: Arc::new(api_config), app_state: app_state.clone(), load_test_config, settings, weighted_apis: Vec::new() };
+        let _ = load_test.execute(&Client::new(), "Shop", &CancellationToken::new()).await;
+
+        let results = app_state.lock().await.load_test_monitoring_data.lock().await.clone();
+        let data = &results["Shop"]["Orders"];
+        assert_eq!((data.requests_completed, data.dropped_arrivals), (1, Some(3)));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_load_test_stops_adding_load_and_is_not_retried() {
+        let (url, requests) = serve_status(200).await;
+        let load_test_config = LoadTestConfig {
+            initial_load: Some(2), max_load: Some(100), spawn_rate: Some(2), max_duration_secs: Some(30), rerun_count: Some(3), ..LoadTestConfig::default()
+        };
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let load_test = LoadTest { api_config: Arc::new(api_config(&url)), app_state: app_state.clone(), load_test_config, settings: Arc::new(settings()), weighted_apis: Vec::new() };
+        let cancel = CancellationToken::new();
+        let stop = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(1500)).await;
+            stop.cancel();
+        });
+
+        let error = load_test.execute(&Client::new(), "Shop", &cancel).await.unwrap_err();
+        assert!(error.contains("was cancelled"), "{}", error);
+
+        let results = app_state.lock().await.load_test_monitoring_data.lock().await.clone();
+        let data = &results["Shop"]["Orders"];
+        let users: Vec<usize> = data.ramp_timeline.iter().map(|step| step.users).collect();
+        assert_eq!((data.end_reason, users), (EndReason::Cancelled, vec![2, 4]));
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_load_test_aborts_when_the_error_rate_is_exceeded_and_is_not_rerun() {
+        let (url, requests) = serve_status(500).await;
+        let load_test_config = LoadTestConfig {
+            initial_load: Some(4), max_load: Some(100), spawn_rate: Some(4), max_duration_secs: Some(30),
+            abort_on_error_rate: Some(0.5), abort_min_samples: Some(4), rerun_count: Some(2), ..LoadTestConfig::default()
+        };
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let load_test = LoadTest { api_config: Arc::new(api_config(&url)), app_state: app_state.clone(), load_test_config, settings: Arc::new(settings()), weighted_apis: Vec::new() };
+
+        let error = load_test.execute(&Client::new(), "Shop", &CancellationToken::new()).await.unwrap_err();
+        assert!(error.contains("Aborted after 100% of the last 4 requests failed"), "{}", error);
+
+        let results = app_state.lock().await.load_test_monitoring_data.lock().await.clone();
+        let data = &results["Shop"]["Orders"];
+        assert_eq!((data.end_reason, data.aborted, data.abort_error_rate), (EndReason::ErrorRate, true, Some(1.0)));
+        // A rerun would have sent another 4 requests after its backoff.
+        assert_eq!((data.retry_backoffs.len(), requests.load(Ordering::SeqCst)), (0, 4));
+    }
+
+    #[tokio::test]
+    async fn test_missed_min_achieved_rps_fails_without_a_rerun() {
+        let (url, requests) = serve_status(200).await;
+        let load_test_config = LoadTestConfig { initial_load: Some(2), max_load: Some(2), min_achieved_rps: Some(1_000_000.0), rerun_count: Some(2), ..LoadTestConfig::default() };
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let load_test = LoadTest { api_config: Arc::new(api_config(&url)), app_state: app_state.clone(), load_test_config, settings: Arc::new(settings()), weighted_apis: Vec::new() };
+
+        let error = load_test.execute(&Client::new(), "Shop", &CancellationToken::new()).await.unwrap_err();
+        assert!(error.contains("below the required minimum of 1000000.00"), "{}", error);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_requests_issued_during_warmup_secs_are_not_measured() {
+        let (url, requests) = serve_status(200).await;
+        let load_test_config = LoadTestConfig { initial_load: Some(2), max_load: Some(5), spawn_rate: Some(3), warmup_secs: Some(1), ..LoadTestConfig::default() };
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let load_test = LoadTest { api_config: Arc::new(api_config(&url)), app_state: app_state.clone(), load_test_config, settings: Arc::new(settings()), weighted_apis: Vec::new() };
+
+        load_test.execute(&Client::new(), "Shop", &CancellationToken::new()).await.unwrap();
+
+        let results = app_state.lock().await.load_test_monitoring_data.lock().await.clone();
+        let data = &results["Shop"]["Orders"];
+        assert_eq!((data.warmup_requests, data.total_requests, requests.load(Ordering::SeqCst)), (2, 3, 5));
+    }
+
+    #[tokio::test]
+    async fn test_weighted_apis_share_the_requests_by_weight() {
+        let (orders_url, orders_requests) = serve_status(200).await;
+        let (search_url, search_requests) = serve_status(200).await;
+        let orders = Arc::new(ApiConfig { weight: Some(3), ..api_config(&orders_url) });
+        let search = Arc::new(ApiConfig { name: "Search".to_string(), weight: Some(1), ..api_config(&search_url) });
+        let load_test_config = LoadTestConfig { initial_load: Some(40), max_load: Some(40), ..LoadTestConfig::default() };
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let load_test = LoadTest { api_config: orders.clone(), app_state: app_state.clone(), load_test_config, settings: Arc::new(settings()), weighted_apis: vec![orders, search] };
+
+        load_test.execute(&Client::new(), "Shop", &CancellationToken::new()).await.unwrap();
+
+        let results = app_state.lock().await.load_test_monitoring_data.lock().await.clone();
+        let requests_by_api = &results["Shop"]["Orders"].requests_by_api;
+        let sent = (orders_requests.load(Ordering::SeqCst), search_requests.load(Ordering::SeqCst));
+        assert_eq!((requests_by_api["Orders"], requests_by_api["Search"]), sent);
+        assert_eq!(sent.0 + sent.1, 40);
+        assert!(sent.0 > sent.1, "{:?}", sent);
+    }
+
+    #[tokio::test]
+    async fn test_target_rps_paces_requests() {
+        let (url, requests) = serve_status(200).await;
+        let load_test_config = LoadTestConfig { initial_load: Some(6), max_load: Some(6), target_rps: Some(20), ..LoadTestConfig::default() };
+        let load_test = LoadTest { api_config: Arc::new(api_config(&url)), app_state: Arc::new(Mutex::new(AppState::default())), load_test_config, settings: Arc::new(settings()), weighted_apis: Vec::new() };
+
+        let started = Instant::now();
+        load_test.execute(&Client::new(), "Shop", &CancellationToken::new()).await.unwrap();
+
+        // The first request goes out at once and the other five 50ms apart.
+        assert!(started.elapsed() >= Duration::from_millis(250), "took {:?}", started.elapsed());
+        assert_eq!(requests.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn test_keeps_connection_open() {
+        let headers = |value: &str| HeaderMap::from_iter([(CONNECTION, value.parse().unwrap())]);
+
+        assert!(keeps_connection_open(Version::HTTP_11, &HeaderMap::new()));
+        assert!(!keeps_connection_open(Version::HTTP_11, &headers("Close")));
+        assert!(!keeps_connection_open(Version::HTTP_11, &headers("upgrade, close")));
+        assert!(!keeps_connection_open(Version::HTTP_10, &HeaderMap::new()));
+        assert!(keeps_connection_open(Version::HTTP_10, &headers("keep-alive")));
+        assert!(keeps_connection_open(Version::HTTP_2, &HeaderMap::new()));
+    }
+
+    #[tokio::test]
+    async fn test_connection_errors_are_retried() {
+        let sent = send_with_retries(&Client::new(), &api_config("http://127.0.0.1:1/"), 0, None, 1, None, &HashMap::new()).await;
+        assert!(sent.outcome.unwrap().is_err());
+        assert_eq!(sent.retries, 1);
+    }
+}
@@ -1,20 +1,36 @@
 pub mod appstate;
+pub mod batch;
+pub mod canary;
 pub mod config;
 pub mod utils;
 pub mod factory;
+pub mod har;
+pub mod health_probe;
 pub mod loadtest;
+pub mod openapi;
+pub mod summary;
 pub mod tasks;
+pub mod websocket;
 pub mod cli;
+#[cfg(test)]
+mod test_support;
 
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
-use cli::process_http_default_headers;
-use config::{load_workflow, Settings, Workflow};
-use factory::start_monitoring;
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::Mutex;
-use crate::appstate::AppState;
+use cli::{process_http_default_headers, process_parallel_groups, process_run_labels, process_tls_versions};
+use config::{load_workflow, prepare_api, ApiConfig, CanaryConfig, HttpOverrides, Settings, Workflow};
+use factory::{start_monitoring, MonitorClients};
+use std::{collections::HashMap, sync::Arc, time::{Duration, Instant}};
+use tokio::sync::{oneshot, Mutex, RwLock};
+use tokio_util::sync::CancellationToken;
+use crate::appstate::{AppState, ServerHealth};
 use crate::cli::build_cli;
 
+/// How many error messages `/errors` lists per URL unless `?top=` says otherwise.
+const DEFAULT_TOP_ERROR_MESSAGES: usize = 10;
+
+/// How long runs in progress may keep going after a shutdown signal before the server stops anyway.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
 
 
 // Entry point for the Actix web server.
@@ -28,7 +44,7 @@ async fn main() -> std::io::Result<()> {
     let config_dir = matches.get_one::<String>("config-dir").map(|s| s.to_string());
 
     // Load workflows based on provided configuration.
-    let workflows = load_workflow(config_file, config_dir).await.expect("Failed to load workflows");
+    let mut workflows = load_workflow(config_file, config_dir).await.expect("Failed to load workflows");
 
     // Extract optional HTTP proxy URL from CLI arguments.
     let http_proxy_url = matches.get_one::<String>("http_proxy_url").map(|s| s.to_string());
@@ -40,6 +56,44 @@ async fn main() -> std::io::Result<()> {
             std::process::exit(1);
         });
 
+    // Process and validate the run labels specified in CLI arguments.
+    let run_labels = process_run_labels(&matches)
+        .unwrap_or_else(|err| {
+            eprintln!("Error processing run labels: {}", err);
+            std::process::exit(1);
+        });
+
+    // Process and validate the task-order groups allowed to run concurrently.
+    let parallel_groups = process_parallel_groups(&matches)
+        .unwrap_or_else(|err| {
+            eprintln!("Error processing parallel groups: {}", err);
+            std::process::exit(1);
+        });
+
+    // Process and validate the TLS version limits.
+    let (min_tls_version, max_tls_version) = process_tls_versions(&matches)
+        .unwrap_or_else(|err| {
+            eprintln!("Error processing TLS versions: {}", err);
+            std::process::exit(1);
+        });
+
+    // The results file format follows its extension, so an unsupported one is rejected before the run.
+    let results_output_path = matches.get_one::<String>("results_output_path").map(|path| {
+        utils::results_file::ResultsFormat::from_path(path).unwrap_or_else(|err| {
+            eprintln!("Error processing results output: {}", err);
+            std::process::exit(1);
+        });
+        path.to_string()
+    });
+
+    // Extract optional HTTP client overrides for load tests.
+    let load_test_http = HttpOverrides {
+        timeout_seconds: matches.get_one::<String>("load_test_http_timeout_seconds")
+            .and_then(|s| s.parse().ok()),
+        proxy_url: matches.get_one::<String>("load_test_http_proxy_url").map(|s| s.to_string()),
+    };
+    let has_load_test_http = load_test_http.timeout_seconds.is_some() || load_test_http.proxy_url.is_some();
+
     // Initialize application settings based on CLI arguments.
     let global_settings = Settings {
         monitoring_interval_seconds: matches.get_one::<String>("monitoring_interval_seconds")
@@ -47,16 +101,135 @@ async fn main() -> std::io::Result<()> {
             .unwrap_or(60), // Default to 60 seconds if not specified
         log_level: matches.get_one::<String>("log_level")
             .unwrap_or(&"info".to_string()).clone(), // Default to "info" if not specified
+        log_format: matches.get_one::<String>("log_format").map(|s| s.to_string()),
         http_timeout_seconds: matches.get_one::<String>("http_timeout_seconds")
             .and_then(|s| s.parse().ok())
             .unwrap_or(20), // Default to 20 seconds if not specified
         http_proxy_url,
         http_default_headers,
+        wait_for_server_ready: matches.get_flag("wait_for_server_ready").then_some(true),
+        record_redirects: matches.get_flag("record_redirects").then_some(true),
+        cookie_store: matches.get_flag("cookie_store").then_some(true),
+        request_timeline: matches.get_flag("request_timeline").then_some(true),
+        run_labels: (!run_labels.is_empty()).then_some(run_labels),
+        stream_ndjson: matches.get_flag("stream_ndjson").then_some(true),
+        failure_body_snippet_bytes: matches.get_one::<String>("failure_body_snippet_bytes")
+            .and_then(|s| s.parse().ok()),
+        parallel_groups,
+        respect_task_order: matches.get_flag("ignore_task_order").then_some(false),
+        load_test_http: has_load_test_http.then_some(load_test_http),
+        log_per_request: matches.get_flag("no_log_per_request").then_some(false),
+        time_unit: matches.get_one::<String>("time_unit")
+            .and_then(|s| s.parse().ok()),
+        no_server: matches.get_flag("no_server").then_some(true),
+        headless: matches.get_flag("headless").then_some(true),
+        dry_run: matches.get_flag("dry_run").then_some(true)
+            .or_else(|| std::env::var("APP__DRY_RUN").ok().and_then(|s| s.parse().ok())),
+        capture_slow_bodies: matches.get_flag("capture_slow_bodies").then_some(true),
+        slow_body_max_bytes: matches.get_one::<String>("slow_body_max_bytes")
+            .and_then(|s| s.parse().ok()),
+        output_dir: matches.get_one::<String>("output_dir").map(|s| s.to_string()),
+        trace_output_path: matches.get_one::<String>("trace_output_path").map(|s| s.to_string()),
+        results_output_path,
+        error_log_rate_limit: matches.get_one::<usize>("error_log_rate_limit").copied(),
+        max_concurrent_requests: matches.get_one::<u32>("max_concurrent_requests").map(|max| *max as usize),
+        history_size: matches.get_one::<u32>("history_size").map(|size| *size as usize),
+        wait_until_healthy: matches.get_flag("wait_until_healthy").then_some(true),
+        wait_until_healthy_timeout_seconds: matches.get_one::<String>("wait_until_healthy_timeout_seconds")
+            .and_then(|s| s.parse().ok()),
+        connect_error_backoff_secs: matches.get_one::<String>("connect_error_backoff_secs")
+            .and_then(|s| s.parse().ok()),
+        http_error_backoff_secs: matches.get_one::<String>("http_error_backoff_secs")
+            .and_then(|s| s.parse().ok()),
+        repeat: matches.get_one::<u32>("repeat").copied(),
+        min_samples_for_percentiles: matches.get_one::<String>("min_samples_for_percentiles")
+            .and_then(|s| s.parse().ok()),
+        ip_version: matches.get_one::<String>("ip_version").map(|s| s.to_string()),
+        min_tls_version,
+        max_tls_version,
+        client_cert_path: matches.get_one::<String>("client_cert_path").map(|s| s.to_string()),
+        client_key_path: matches.get_one::<String>("client_key_path").map(|s| s.to_string()),
+        ca_cert_path: matches.get_one::<String>("ca_cert_path").map(|s| s.to_string()),
+        // The environment is consulted when the flags are absent, e.g. to bind 0.0.0.0 inside a container.
+        server_bind_address: matches.get_one::<String>("server_bind_address").map(|s| s.to_string())
+            .or_else(|| std::env::var("APP__SERVER_BIND_ADDRESS").ok()),
+        server_port: matches.get_one::<u16>("server_port").copied()
+            .or_else(|| std::env::var("APP__SERVER_PORT").ok().and_then(|s| s.parse().ok())),
+        canary: matches.get_one::<String>("canary_baseline_host")
+            .zip(matches.get_one::<String>("canary_host"))
+            .map(|(baseline_host, canary_host)| CanaryConfig {
+                baseline_host: baseline_host.to_string(),
+                canary_host: canary_host.to_string(),
+            }),
     };
 
     // Initialize logging based on the specified log level.
     global_settings.init_logging();
 
+    // Replay a recorded HAR file as one more workflow; loaded after logging starts so skipped entries are reported.
+    if let Some(har_path) = matches.get_one::<String>("replay_har") {
+        workflows.push(har::load_har_workflow(har_path).expect("Failed to load HAR file"));
+    }
+
+    // Generate a workflow covering the operations of an OpenAPI spec.
+    if let Some(spec_path) = matches.get_one::<String>("from_openapi") {
+        let filter = openapi::OpenApiFilter {
+            tag: matches.get_one::<String>("openapi_tag").cloned(),
+            path_prefix: matches.get_one::<String>("openapi_path_prefix").cloned(),
+        };
+        let base_url = matches.get_one::<String>("openapi_base_url").map(String::as_str);
+        workflows.push(openapi::load_openapi_workflow(spec_path, &filter, base_url).expect("Failed to load OpenAPI spec"));
+    }
+
+    // Start streaming completed requests to stdout if requested.
+    if global_settings.stream_ndjson.unwrap_or(false) {
+        utils::ndjson::start_stream();
+    }
+
+    // Give this run its own folder for file outputs; without one the run continues without them.
+    if let Some(output_dir) = &global_settings.output_dir {
+        match utils::output::init(output_dir) {
+            Ok(run_dir) => log::info!("Writing outputs for this run to {}", run_dir.display()),
+            Err(e) => log::error!("Failed to create a run folder under '{}', file outputs are disabled: {}", output_dir, e),
+        }
+    }
+
+    // Collect a timeline of requests if a trace file was requested.
+    if let Some(trace_output_path) = &global_settings.trace_output_path {
+        utils::trace::start(utils::output::resolve(trace_output_path));
+    }
+
+    // Throttle request error logs so a failing target doesn't flood the output.
+    if let Some(per_second) = global_settings.error_log_rate_limit {
+        utils::error_log::start(per_second);
+    }
+
+    // Bound the requests in flight so large groups or loads can't exhaust file descriptors.
+    if let Some(max_concurrent_requests) = global_settings.max_concurrent_requests {
+        utils::request_limit::start(max_concurrent_requests);
+    }
+
+    // Certificate files are read while building the HTTP clients, so a bad one is reported now rather than mid-run.
+    let clients = MonitorClients::new(&global_settings).unwrap_or_else(|err| {
+        eprintln!("Error configuring the HTTP client: {:#}", err);
+        std::process::exit(1);
+    });
+
+    // Check that every request can be built before any load is sent, reporting failures through the exit code.
+    if global_settings.dry_run.unwrap_or(false) {
+        let workflows: Vec<_> = workflows.into_iter().map(Arc::new).collect();
+        let checks = factory::check_requests(&workflows, &clients.default);
+        for check in &checks {
+            match &check.error {
+                None => println!("OK    {} / {}", check.workflow, check.api),
+                Some(error) => println!("ERROR {} / {}: {}", check.workflow, check.api, error),
+            }
+        }
+        let failed = checks.iter().filter(|check| check.error.is_some()).count();
+        println!("{} of {} requests built successfully", checks.len() - failed, checks.len());
+        std::process::exit(if failed == 0 { 0 } else { 1 });
+    }
+
     // Wrap workflows and settings in Arcs for thread-safe shared access across async tasks.
     let workflows_arc = Arc::new(workflows.into_iter().map(Arc::new).collect::<Vec<_>>());
     let settings_arc = Arc::new(global_settings);
@@ -65,37 +238,234 @@ async fn main() -> std::io::Result<()> {
     let app_state_arc = Arc::new(Mutex::new(AppState {
         load_test_monitoring_data: Arc::new(Mutex::new(HashMap::new())),
         task_monitoring_data: Arc::new(Mutex::new(HashMap::new())),
+        task_response_times: Arc::new(Mutex::new(HashMap::new())),
+        batch_monitoring_data: Arc::new(Mutex::new(HashMap::new())),
+        canary_monitoring_data: Arc::new(Mutex::new(HashMap::new())),
+        websocket_monitoring_data: Arc::new(Mutex::new(HashMap::new())),
+        error_messages: Arc::new(Mutex::new(HashMap::new())),
+        variables: Arc::new(Mutex::new(HashMap::new())),
+        request_history: Arc::new(Mutex::new(HashMap::new())),
+        request_metrics: Arc::new(Mutex::new(Default::default())),
     }));
 
+    // Modes that run without the server stop on Ctrl-C or SIGTERM and still report what they measured.
+    let runs_without_server = settings_arc.wait_until_healthy.unwrap_or(false) || settings_arc.repeat.is_some()
+        || settings_arc.headless.unwrap_or(false) || settings_arc.no_server.unwrap_or(false);
+    let cancel = if runs_without_server { cancel_on_shutdown_signal() } else { CancellationToken::new() };
+
+    // As a readiness gate, rerun the workflows until a cycle is clean and report the outcome through the exit code.
+    if settings_arc.wait_until_healthy.unwrap_or(false) {
+        let timeout = Duration::from_secs(settings_arc.wait_until_healthy_timeout_seconds.unwrap_or(300));
+        let interval = Duration::from_secs(settings_arc.monitoring_interval_seconds);
+        let started = Instant::now();
+
+        for attempt in 1.. {
+            // An attempt still running when the budget is spent counts as not healthy in time.
+            let remaining = timeout.saturating_sub(started.elapsed());
+            let run = start_monitoring(settings_arc.clone(), (*workflows_arc).clone(), app_state_arc.clone(), cancel.clone());
+            let Ok(summary) = tokio::time::timeout(remaining, run).await else {
+                log::error!("Not healthy within {:?}; attempt {} was still running", timeout, attempt);
+                std::process::exit(1);
+            };
+            let failures = summary.failed_monitors.unwrap_or_default();
+            if cancel.is_cancelled() {
+                log::error!("Stopped after {} health check attempts", attempt);
+                std::process::exit(1);
+            }
+            if failures == 0 {
+                log::info!("Health check attempt {} passed after {:?}", attempt, started.elapsed());
+                return Ok(());
+            }
+            log::warn!("Health check attempt {} had {} failures", attempt, failures);
+
+            if started.elapsed() + interval >= timeout {
+                log::error!("Not healthy after {} attempts within {:?}", attempt, timeout);
+                std::process::exit(1);
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = cancel.cancelled() => {
+                    log::error!("Stopped after {} health check attempts", attempt);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
+    // Repeat the whole cycle to measure how much the results vary from run to run, then print the spread.
+    if let Some(repeat) = settings_arc.repeat {
+        let mut runs = Vec::new();
+        for run in 1..=repeat {
+            // Runs cut short by a shutdown are still reported; those not yet started are skipped.
+            if cancel.is_cancelled() {
+                log::warn!("Stopped after {} of {} runs", run - 1, repeat);
+                break;
+            }
+            log::info!("Starting run {} of {}", run, repeat);
+            runs.push(start_monitoring(settings_arc.clone(), (*workflows_arc).clone(), app_state_arc.clone(), cancel.clone()).await);
+        }
+
+        let report = summary::aggregate(runs);
+        utils::output::write_json("repeat_summary.json", &report);
+        let report = serde_json::to_string_pretty(&report).map_err(std::io::Error::other)?;
+        println!("{}", report);
+        return Ok(());
+    }
+
+    // For CI: run once, print the summary, and report through the exit code whether the run passed.
+    if settings_arc.headless.unwrap_or(false) {
+        let run_summary = start_monitoring(settings_arc.clone(), (*workflows_arc).clone(), app_state_arc.clone(), cancel.clone()).await;
+        let report = serde_json::to_string_pretty(&run_summary).map_err(std::io::Error::other)?;
+        println!("{}", report);
+        if !run_summary.passed() {
+            log::error!("Run failed: {} failed monitors, {} slow tasks", run_summary.failed_monitors.unwrap_or_default(), run_summary.slow_tasks);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Without the server there is nothing to serve results from, so run once and print them.
+    if settings_arc.no_server.unwrap_or(false) {
+        start_monitoring(settings_arc.clone(), (*workflows_arc).clone(), app_state_arc.clone(), cancel.clone()).await;
+
+        let snapshot = app_state_arc.lock().await
+            .snapshot(settings_arc.run_labels.clone().unwrap_or_default()).await;
+        let results = serde_json::to_string_pretty(&snapshot).map_err(std::io::Error::other)?;
+        println!("{}", results);
+        return Ok(());
+    }
+
     // Make shared state accessible in Actix web handlers through web::Data.
     let app_state_for_actix = web::Data::new(app_state_arc.clone());
-    let workflows_for_actix = web::Data::new(workflows_arc.clone());
+    // Workflows may be edited at runtime through PUT /apis/{name}; each run works on a snapshot.
+    let workflows_for_actix = web::Data::new(Arc::new(RwLock::new((*workflows_arc).clone())));
     let settings_for_actix = web::Data::new(settings_arc.clone());
+    let apis_configured = workflows_arc.iter().map(|workflow| workflow.apis.len()).sum();
+    let health = Arc::new(ServerHealth::new(apis_configured));
+    let health_for_actix = web::Data::new(health.clone());
+    let health_for_shutdown = health.clone();
 
     // Launch a background task for monitoring based on the current configuration.
     let workflows_vec = Arc::clone(&workflows_arc);
     let app_state_clone = app_state_arc.clone();
     let settings_clone = settings_arc.clone();
 
+    // Signals the monitoring task once the server has bound, if it was asked to wait for that.
+    let (server_ready_tx, server_ready_rx) = oneshot::channel::<()>();
+    let wait_for_server_ready = settings_arc.wait_for_server_ready.unwrap_or(false);
+
     tokio::spawn(async move {
-        start_monitoring(settings_clone, (*workflows_vec).clone(), app_state_clone).await;
+        if wait_for_server_ready && server_ready_rx.await.is_err() {
+            // The sender was dropped, meaning the server never bound; there is nowhere to report to.
+            return;
+        }
+        if health.is_shutting_down() {
+            return;
+        }
+        let _run = health.begin_run();
+        start_monitoring(settings_clone, (*workflows_vec).clone(), app_state_clone, health.run_cancellation()).await;
     });
 
     // Set up and run the Actix web server with configured routes and handlers.
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
             .app_data(app_state_for_actix.clone())
             .app_data(settings_for_actix.clone())
             .app_data(workflows_for_actix.clone())
+            .app_data(health_for_actix.clone())
+            .route("/health", web::get().to(get_health))
             .route("/load_test_results", web::get().to(get_load_test_data))
             .route("/trigger_load_tests", web::get().to(trigger_monitoring))
+            .route("/stop_load_tests", web::post().to(stop_monitoring))
             .route("/task_results", web::get().to(get_task_data))
+            .route("/summary", web::get().to(get_summary))
+            .route("/batch_results", web::get().to(get_batch_data))
+            .route("/results", web::get().to(get_results))
+            .route("/canary_results", web::get().to(get_canary_data))
+            .route("/websocket_results", web::get().to(get_websocket_data))
+            .route("/errors", web::get().to(get_errors))
+            .route("/metrics", web::get().to(get_metrics))
+            .route("/events", web::get().to(get_events))
+            .route("/history", web::get().to(get_history))
+            .route("/apis/{name}", web::put().to(update_api))
     })
-    .bind("127.0.0.1:8080")?
-    .run()
-    .await
+    .bind(settings_arc.server_address())?
+    // Signals are handled below, so runs in progress can finish before the server stops.
+    .disable_signals()
+    .shutdown_timeout(SHUTDOWN_GRACE_PERIOD.as_secs())
+    .run();
+
+    // Binding succeeded, so results are queryable from here on.
+    let _ = server_ready_tx.send(());
+
+    let server_handle = server.handle();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        log::info!("Shutdown requested; waiting up to {:?} for runs in progress to finish", SHUTDOWN_GRACE_PERIOD);
+        health_for_shutdown.begin_shutdown();
+        if !health_for_shutdown.drain(SHUTDOWN_GRACE_PERIOD).await {
+            // Stop them, so the summary covers what they measured before the deadline.
+            let stopped = health_for_shutdown.stop_runs();
+            log::warn!("Stopped {} runs still in progress after {:?}; the summary includes what they recorded so far", stopped, SHUTDOWN_GRACE_PERIOD);
+            // Cancelled runs return promptly; give them a moment to record their last results.
+            health_for_shutdown.drain(Duration::from_secs(1)).await;
+        }
+
+        let snapshot = app_state_arc.lock().await
+            .snapshot(settings_arc.run_labels.clone().unwrap_or_default()).await;
+        log_final_summary(&summary::RunSummary::from_snapshot(&snapshot));
+        server_handle.stop(true).await;
+    });
+
+    server.await
 }
 
+// A token cancelled by the first SIGINT or SIGTERM, for runs that have no server to shut down.
+fn cancel_on_shutdown_signal() -> CancellationToken {
+    let cancel = CancellationToken::new();
+    let on_signal = cancel.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        log::info!("Shutdown requested; stopping the run and reporting what it recorded so far");
+        on_signal.cancel();
+    });
+    cancel
+}
+
+// Resolves on the first SIGINT (Ctrl-C) or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            log::error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                log::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {},
+        _ = terminate => {},
+    }
+}
+
+// Logs the totals of everything recorded so far, so a run cut short still reports what it measured.
+fn log_final_summary(run_summary: &summary::RunSummary) {
+    log::info!("Final summary: {} results, {} errors, mean response time {:.1}ms",
+        run_summary.total_results, run_summary.error_count, run_summary.mean_response_time_ms);
+}
 
 // Handles web requests to retrieve load test data, utilizing shared application state.
 async fn get_load_test_data(
@@ -115,22 +485,62 @@ async fn get_load_test_data(
 async fn trigger_monitoring(
     settings: web::Data<Arc<Settings>>,
     app_state: web::Data<Arc<Mutex<AppState>>>,
-    workflows: web::Data<Arc<Vec<Arc<Workflow>>>>,
+    workflows: web::Data<Arc<RwLock<Vec<Arc<Workflow>>>>>,
+    health: web::Data<Arc<ServerHealth>>,
+    query: web::Query<HashMap<String, String>>,
 ) -> impl actix_web::Responder {
+    if health.is_shutting_down() {
+        return HttpResponse::ServiceUnavailable().body("Shutting down; no new load tests are started.");
+    }
+
     // Clones the settings, app state, and workflows to pass to the monitoring task.
     let settings_clone = Arc::clone(settings.get_ref());
     let app_state_clone = Arc::clone(app_state.get_ref());
-    let workflows_clone = Arc::clone(workflows.get_ref());
+    let workflows_clone = workflows.read().await.clone();
+    let run = health.begin_run();
+    let cancel = health.run_cancellation();
 
     // Spawns an asynchronous task to start monitoring with the cloned arguments.
-    tokio::spawn(async move {
-        start_monitoring(settings_clone, (*workflows_clone).clone(), app_state_clone).await;
+    let monitoring = tokio::spawn(async move {
+        let _run = run;
+        start_monitoring(settings_clone, workflows_clone, app_state_clone, cancel).await
     });
 
+    // With `?wait=true` the response waits for the run and reports its summary.
+    if query.get("wait").is_some_and(|wait| wait == "true") {
+        return match monitoring.await {
+            Ok(run_summary) => HttpResponse::Ok().json(run_summary),
+            Err(e) => HttpResponse::InternalServerError().body(format!("Monitoring run failed: {}", e)),
+        };
+    }
+
     // Responds to indicate that load test monitoring has been triggered.
     HttpResponse::Ok().body("Load test triggered.")
 }
 
+// Cancels every monitoring run in progress; load tests stop adding load and tasks abandon their requests.
+async fn stop_monitoring(health: web::Data<Arc<ServerHealth>>) -> impl actix_web::Responder {
+    let stopped = health.stop_runs();
+    log::info!("Stop requested; cancelling {} monitoring runs in progress", stopped);
+    HttpResponse::Ok().json(serde_json::json!({ "cancelled_runs": stopped }))
+}
+
+// Reports that the server is up, for orchestrator probes; it reads only atomics, never the results.
+async fn get_health(health: web::Data<Arc<ServerHealth>>) -> impl actix_web::Responder {
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "ok",
+        "apis_configured": health.apis_configured,
+        "monitoring_active": health.monitoring_active(),
+    }))
+}
+
+// Lists the most recent requests to each URL, oldest first; empty unless --history-size is set.
+async fn get_history(data: web::Data<Arc<Mutex<AppState>>>) -> impl actix_web::Responder {
+    let app_state = data.lock().await;
+    let request_history = app_state.request_history.lock().await;
+    HttpResponse::Ok().json(&*request_history)
+}
+
 // Retrieves and responds with HTTP status data from the shared application state.
 async fn get_task_data(data: web::Data<Arc<Mutex<AppState>>>) -> impl actix_web::Responder {
     // Safely accesses the application state and its HTTP status data.
@@ -140,3 +550,125 @@ async fn get_task_data(data: web::Data<Arc<Mutex<AppState>>>) -> impl actix_web:
     // Serializes and responds with the HTTP status data in JSON format.
     HttpResponse::Ok().json(&*http_status_data)
 }
+
+// Condenses the current results into the key metrics and per-endpoint health scores.
+async fn get_summary(data: web::Data<Arc<Mutex<AppState>>>, settings: web::Data<Arc<Settings>>) -> impl actix_web::Responder {
+    let app_state = data.lock().await;
+    let snapshot = app_state.snapshot(settings.run_labels.clone().unwrap_or_default()).await;
+
+    HttpResponse::Ok().json(summary::RunSummary::from_snapshot(&snapshot))
+}
+
+// Exposes the latest task and load test results for Prometheus to scrape.
+async fn get_metrics(data: web::Data<Arc<Mutex<AppState>>>, settings: web::Data<Arc<Settings>>) -> impl actix_web::Responder {
+    let app_state = data.lock().await;
+    let snapshot = app_state.snapshot(settings.run_labels.clone().unwrap_or_default()).await;
+    let request_metrics = app_state.request_metrics.lock().await;
+
+    HttpResponse::Ok()
+        .content_type(utils::prometheus::CONTENT_TYPE)
+        .body(utils::prometheus::render(&snapshot, &request_metrics))
+}
+
+// Streams the same envelope as /results as Server-Sent Events, once straight away and then every monitoring interval.
+// The stream is dropped, and stops polling the state, as soon as the client disconnects.
+async fn get_events(data: web::Data<Arc<Mutex<AppState>>>, settings: web::Data<Arc<Settings>>) -> impl actix_web::Responder {
+    let ticker = tokio::time::interval(Duration::from_secs(settings.monitoring_interval_seconds.max(1)));
+    let state = (ticker, data.get_ref().clone(), settings.get_ref().clone());
+    let events = futures::stream::unfold(state, |(mut ticker, app_state, settings)| async move {
+        ticker.tick().await;
+        let snapshot = app_state.lock().await.snapshot(settings.run_labels.clone().unwrap_or_default()).await;
+        // Compact JSON has no line breaks, so it fits in a single `data:` field.
+        let event = serde_json::to_string(&snapshot)
+            .map(|json| web::Bytes::from(format!("data: {}\n\n", json)))
+            .map_err(actix_web::error::ErrorInternalServerError);
+        Some((event, (ticker, app_state, settings)))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events)
+}
+
+// Retrieves and responds with batch timing data from the shared application state.
+async fn get_batch_data(data: web::Data<Arc<Mutex<AppState>>>) -> impl actix_web::Responder {
+    let app_state = data.lock().await;
+    let batch_data = app_state.batch_monitoring_data.lock().await;
+
+    HttpResponse::Ok().json(&*batch_data)
+}
+
+// Responds with all results in a single envelope, together with the run's labels.
+async fn get_results(
+    data: web::Data<Arc<Mutex<AppState>>>,
+    settings: web::Data<Arc<Settings>>,
+) -> impl actix_web::Responder {
+    let app_state = data.lock().await;
+    let snapshot = app_state.snapshot(settings.run_labels.clone().unwrap_or_default()).await;
+
+    HttpResponse::Ok().json(snapshot)
+}
+
+// Responds with the most frequent error messages of each API URL; `?top=N` sets how many, 10 by default.
+async fn get_errors(data: web::Data<Arc<Mutex<AppState>>>, query: web::Query<HashMap<String, String>>) -> impl actix_web::Responder {
+    let top = query.get("top").and_then(|top| top.parse().ok()).unwrap_or(DEFAULT_TOP_ERROR_MESSAGES);
+    let app_state = data.lock().await;
+    let error_messages = app_state.error_messages.lock().await;
+
+    let top_messages: HashMap<&String, _> = error_messages.iter()
+        .map(|(url, counts)| (url, counts.top(top)))
+        .collect();
+    HttpResponse::Ok().json(top_messages)
+}
+
+// Retrieves and responds with baseline-versus-canary comparisons from the shared application state.
+async fn get_canary_data(data: web::Data<Arc<Mutex<AppState>>>) -> impl actix_web::Responder {
+    let app_state = data.lock().await;
+    let canary_data = app_state.canary_monitoring_data.lock().await;
+
+    HttpResponse::Ok().json(&*canary_data)
+}
+
+// Retrieves and returns the results of WebSocket load tests in JSON format.
+async fn get_websocket_data(data: web::Data<Arc<Mutex<AppState>>>) -> impl actix_web::Responder {
+    let app_state = data.lock().await;
+    let websocket_data = app_state.websocket_monitoring_data.lock().await;
+
+    HttpResponse::Ok().json(&*websocket_data)
+}
+
+// Replaces the named API in every workflow that contains it; runs already in progress keep their snapshot.
+async fn update_api(
+    name: web::Path<String>,
+    api_config: web::Json<ApiConfig>,
+    workflows: web::Data<Arc<RwLock<Vec<Arc<Workflow>>>>>,
+) -> impl actix_web::Responder {
+    let api_config = api_config.into_inner();
+    let mut workflows = workflows.write().await;
+
+    // Prepared against each workflow it belongs to, so variables the others extract stay runtime placeholders.
+    let mut replacements = Vec::new();
+    for (position, workflow) in workflows.iter().enumerate() {
+        if let Some(index) = workflow.apis.iter().position(|api| api.name == *name) {
+            match prepare_api(workflow, api_config.clone()) {
+                Ok(prepared) => replacements.push((position, index, prepared)),
+                Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+            }
+        }
+    }
+
+    let updated = !replacements.is_empty();
+    for (position, index, prepared) in replacements {
+        let mut replacement = (*workflows[position]).clone();
+        replacement.apis[index] = prepared;
+        workflows[position] = Arc::new(replacement);
+    }
+
+    if updated {
+        log::info!("Updated configuration for API '{}'", name);
+        HttpResponse::Ok().body(format!("API '{}' updated.", name))
+    } else {
+        HttpResponse::NotFound().body(format!("No API named '{}'.", name))
+    }
+}
@@ -0,0 +1,300 @@
+use anyhow::Context;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::config::{prepare_api, ApiConfig, HttpMethod, Workflow};
+
+/// Response time threshold given to generated APIs; specs don't state one.
+const GENERATED_RESPONSE_TIME_THRESHOLD_MS: u64 = 1000;
+/// How deeply nested schemas are followed when building examples, which also stops recursive `$ref`s.
+const MAX_SCHEMA_DEPTH: usize = 8;
+
+/// Narrows which operations of a spec become APIs.
+#[derive(Debug, Clone, Default)]
+pub struct OpenApiFilter {
+    /// Only operations carrying this tag.
+    pub tag: Option<String>,
+    /// Only operations whose path starts with this prefix.
+    pub path_prefix: Option<String>,
+}
+
+impl OpenApiFilter {
+    fn matches(&self, path: &str, operation: &Value) -> bool {
+        let tag_matches = self.tag.as_ref().is_none_or(|tag| {
+            operation["tags"].as_array().is_some_and(|tags| tags.iter().any(|t| t.as_str() == Some(tag.as_str())))
+        });
+        tag_matches && self.path_prefix.as_ref().is_none_or(|prefix| path.starts_with(prefix.as_str()))
+    }
+}
+
+/// Loads an OpenAPI 3 or Swagger 2 spec, in JSON or YAML, as a workflow with one task per operation.
+///
+/// Path parameters, required query parameters and headers, and request bodies are filled from the
+/// spec's examples and defaults, or from placeholder values matching the schema. `base_url` replaces
+/// the server the spec declares.
+pub fn load_openapi_workflow(path: &str, filter: &OpenApiFilter, base_url: Option<&str>) -> anyhow::Result<Workflow> {
+    let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read OpenAPI spec at {}", path))?;
+    // YAML is a superset of JSON, so one parser covers both formats.
+    let spec: Value = serde_yaml::from_str(&contents).with_context(|| format!("Failed to parse OpenAPI spec at {}", path))?;
+
+    let base_url = match base_url {
+        Some(base_url) => base_url.to_string(),
+        None => declared_base_url(&spec).with_context(|| format!("OpenAPI spec at {} declares no absolute server URL; pass --openapi-base-url", path))?,
+    };
+    let name = spec["info"]["title"].as_str().map(str::to_string)
+        .unwrap_or_else(|| Path::new(path).file_stem().map_or_else(|| path.to_string(), |stem| stem.to_string_lossy().into_owned()));
+
+    let mut workflow = Workflow { name, apis: Vec::new(), teardown: None, teardown_failures_fail_run: None };
+    workflow.apis = generate_apis(&workflow, &spec, filter, base_url.trim_end_matches('/'));
+    if workflow.apis.is_empty() {
+        log::warn!("OpenAPI spec {} has no operations matching the filter", path);
+    }
+    Ok(workflow)
+}
+
+/// The base URL from `servers` (OpenAPI 3) or `host` and `basePath` (Swagger 2).
+fn declared_base_url(spec: &Value) -> Option<String> {
+    if let Some(url) = spec["servers"][0]["url"].as_str() {
+        return url.contains("://").then(|| url.to_string());
+    }
+    let host = spec["host"].as_str()?;
+    let scheme = spec["schemes"][0].as_str().unwrap_or("https");
+    Some(format!("{}://{}{}", scheme, host, spec["basePath"].as_str().unwrap_or_default()))
+}
+
+fn generate_apis(workflow: &Workflow, spec: &Value, filter: &OpenApiFilter, base_url: &str) -> Vec<ApiConfig> {
+    let Some(paths) = spec["paths"].as_object() else {
+        return Vec::new();
+    };
+
+    let mut apis = Vec::new();
+    for (path, path_item) in paths {
+        let Some(operations) = path_item.as_object() else { continue };
+        for (method, operation) in operations {
+            let Ok(method) = method.parse::<HttpMethod>() else { continue }; // `parameters`, `summary`, `trace`, ...
+            if !filter.matches(path, operation) {
+                continue;
+            }
+            let api = generate_api(spec, base_url, path, path_item, &method, operation, apis.len())
+                .and_then(|api| prepare_api(workflow, api).map_err(|e| e.to_string()));
+            match api {
+                Ok(api) => apis.push(api),
+                Err(reason) => log::warn!("Skipping {} {}: {}", method.as_str(), path, reason),
+            }
+        }
+    }
+    apis
+}
+
+fn generate_api(spec: &Value, base_url: &str, path: &str, path_item: &Value, method: &HttpMethod, operation: &Value, index: usize) -> Result<ApiConfig, String> {
+    // Operation parameters override path-level ones of the same name and location.
+    let mut parameters: Vec<Value> = Vec::new();
+    for parameter in [&path_item["parameters"], &operation["parameters"]].into_iter().filter_map(Value::as_array).flatten() {
+        let parameter = resolve(spec, parameter);
+        parameters.retain(|existing| existing["name"] != parameter["name"] || existing["in"] != parameter["in"]);
+        parameters.push(parameter.clone());
+    }
+
+    let mut url_path = path.to_string();
+    let mut query = Vec::new();
+    let mut headers = HashMap::new();
+    let mut body = None;
+    for parameter in &parameters {
+        let name = parameter["name"].as_str().unwrap_or_default();
+        let required = parameter["required"].as_bool().unwrap_or(false);
+        match parameter["in"].as_str() {
+            Some("path") => url_path = url_path.replace(&format!("{{{}}}", name), &parameter_value(spec, parameter)),
+            Some("query") if required => query.push(format!("{}={}", name, parameter_value(spec, parameter))),
+            Some("header") if required => { headers.insert(name.to_string(), parameter_value(spec, parameter)); },
+            // Swagger 2 puts the request body among the parameters.
+            Some("body") => body = Some(example(spec, &parameter["schema"], 0)),
+            _ => {}
+        }
+    }
+    if let Some(content) = operation["requestBody"]["content"].as_object() {
+        if let Some(media) = content.get("application/json").or_else(|| content.values().next()) {
+            body = Some(media.get("example").cloned().unwrap_or_else(|| example(spec, &media["schema"], 0)));
+        }
+    }
+    if url_path.contains('{') {
+        return Err(format!("path parameters of {} are not all declared", path));
+    }
+    if body.is_some() {
+        headers.entry("Content-Type".to_string()).or_insert_with(|| "application/json".to_string());
+    }
+
+    let url = if query.is_empty() { format!("{}{}", base_url, url_path) } else { format!("{}{}?{}", base_url, url_path, query.join("&")) };
+    let name = operation["operationId"].as_str().map(str::to_string).unwrap_or_else(|| format!("{} {}", method.as_str(), path));
+    serde_json::from_value(json!({
+        "name": name,
+        "url": url,
+        "method": method.as_str(),
+        "headers": headers,
+        "expected_field": "",
+        "response_time_threshold": GENERATED_RESPONSE_TIME_THRESHOLD_MS,
+        "body": body.map(|body| body.to_string()),
+        "task_order": index,
+    })).map_err(|e| e.to_string())
+}
+
+/// Follows a local `$ref` such as `#/components/schemas/Order`; anything else is returned as is.
+fn resolve<'a>(spec: &'a Value, value: &'a Value) -> &'a Value {
+    match value["$ref"].as_str().and_then(|reference| reference.strip_prefix('#')) {
+        Some(pointer) => spec.pointer(pointer).unwrap_or(&Value::Null),
+        None => value,
+    }
+}
+
+/// The value used for a parameter, as it appears in the URL or header.
+fn parameter_value(spec: &Value, parameter: &Value) -> String {
+    // Swagger 2 puts the type on the parameter itself rather than in a schema.
+    let schema = if parameter["schema"].is_null() { parameter } else { &parameter["schema"] };
+    match parameter.get("example").cloned().unwrap_or_else(|| example(spec, schema, 0)) {
+        Value::String(value) => value,
+        value => value.to_string(),
+    }
+}
+
+/// Builds an example value for a schema, preferring what the spec provides over placeholders.
+///
+/// Objects get their required properties and any optional ones that carry an example or default.
+fn example(spec: &Value, schema: &Value, depth: usize) -> Value {
+    let schema = resolve(spec, schema);
+    if depth > MAX_SCHEMA_DEPTH {
+        return Value::Null;
+    }
+    if let Some(value) = schema.get("example").or_else(|| schema.get("default")).or_else(|| schema["enum"].get(0)) {
+        return value.clone();
+    }
+    if let Some(first) = schema["oneOf"].get(0).or_else(|| schema["anyOf"].get(0)) {
+        return example(spec, first, depth + 1);
+    }
+    if let Some(parts) = schema["allOf"].as_array() {
+        let mut merged = Map::new();
+        for part in parts {
+            if let Value::Object(object) = example(spec, part, depth + 1) {
+                merged.extend(object);
+            }
+        }
+        return Value::Object(merged);
+    }
+
+    match schema["type"].as_str() {
+        Some("array") => json!([example(spec, &schema["items"], depth + 1)]),
+        Some("integer") => schema.get("minimum").cloned().unwrap_or(json!(1)),
+        Some("number") => schema.get("minimum").cloned().unwrap_or(json!(1.0)),
+        Some("boolean") => json!(true),
+        Some("string") => json!(match schema["format"].as_str() {
+            Some("date-time") => "2024-01-01T00:00:00Z",
+            Some("date") => "2024-01-01",
+            Some("uuid") => "00000000-0000-0000-0000-000000000000",
+            Some("email") => "user@example.com",
+            Some("uri") => "https://example.com",
+            _ => "string",
+        }),
+        _ => {
+            let Some(properties) = schema["properties"].as_object() else {
+                return if schema["type"] == "object" { json!({}) } else { Value::Null };
+            };
+            let required: Vec<&str> = schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+            properties.iter()
+                .filter(|(name, property)| {
+                    let property = resolve(spec, property);
+                    required.contains(&name.as_str()) || property.get("example").is_some() || property.get("default").is_some()
+                })
+                .map(|(name, property)| (name.clone(), example(spec, property, depth + 1)))
+                .collect::<Map<_, _>>()
+                .into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workflow() -> Workflow {
+        Workflow { name: "Orders API".to_string(), apis: Vec::new(), teardown: None, teardown_failures_fail_run: None }
+    }
+
+    fn spec() -> Value {
+        serde_yaml::from_str(r##"
+openapi: 3.0.0
+info: {title: Shop}
+servers: [{url: "http://localhost:8000/v1"}]
+paths:
+  /orders/{orderId}:
+    parameters:
+      - {name: orderId, in: path, required: true, schema: {type: integer}}
+    get:
+      operationId: getOrder
+      tags: [orders]
+      parameters:
+        - {name: verbose, in: query, required: true, schema: {type: boolean}}
+        - {name: page, in: query, schema: {type: integer}}
+    put:
+      tags: [orders]
+      requestBody:
+        content:
+          application/json:
+            schema: {$ref: "#/components/schemas/Order"}
+  /health:
+    get:
+      tags: [ops]
+components:
+  schemas:
+    Order:
+      type: object
+      required: [id, items]
+      properties:
+        id: {type: string, format: uuid}
+        items: {type: array, items: {$ref: "#/components/schemas/Item"}}
+        note: {type: string}
+        status: {type: string, enum: [open, closed], default: open}
+    Item:
+      type: object
+      required: [sku]
+      properties:
+        sku: {type: string, example: ABC-1}
+"##).unwrap()
+    }
+
+    #[test]
+    fn test_operations_become_apis_with_examples() {
+        let apis = generate_apis(&workflow(), &spec(), &OpenApiFilter::default(), &declared_base_url(&spec()).unwrap());
+        let by_name: HashMap<&str, &ApiConfig> = apis.iter().map(|api| (api.name.as_str(), api)).collect();
+
+        assert_eq!(apis.len(), 3);
+        assert_eq!(by_name["getOrder"].url, "http://localhost:8000/v1/orders/1?verbose=true");
+        let put = by_name["PUT /orders/{orderId}"];
+        assert_eq!(put.url, "http://localhost:8000/v1/orders/1");
+        assert_eq!(put.headers["Content-Type"], "application/json");
+        let body: Value = serde_json::from_str(put.body.as_deref().unwrap()).unwrap();
+        assert_eq!(body, json!({"id": "00000000-0000-0000-0000-000000000000", "items": [{"sku": "ABC-1"}], "status": "open"}));
+    }
+
+    #[test]
+    fn test_filter_by_tag_and_path_prefix() {
+        let by_tag = OpenApiFilter { tag: Some("ops".to_string()), path_prefix: None };
+        let apis = generate_apis(&workflow(), &spec(), &by_tag, "http://localhost");
+        assert_eq!(apis.iter().map(|api| api.url.as_str()).collect::<Vec<_>>(), ["http://localhost/health"]);
+
+        let by_prefix = OpenApiFilter { tag: None, path_prefix: Some("/orders".to_string()) };
+        assert_eq!(generate_apis(&workflow(), &spec(), &by_prefix, "http://localhost").len(), 2);
+    }
+
+    #[test]
+    fn test_swagger_2_base_url_and_body_parameter() {
+        let spec: Value = serde_json::from_value(json!({
+            "swagger": "2.0", "host": "api.example.com", "basePath": "/v2", "schemes": ["http"],
+            "paths": {"/pets": {"post": {"parameters": [
+                {"name": "pet", "in": "body", "schema": {"type": "object", "required": ["name"], "properties": {"name": {"type": "string"}}}}
+            ]}}}
+        })).unwrap();
+
+        assert_eq!(declared_base_url(&spec).as_deref(), Some("http://api.example.com/v2"));
+        let apis = generate_apis(&workflow(), &spec, &OpenApiFilter::default(), "http://api.example.com/v2");
+        assert_eq!(apis[0].body.as_deref(), Some(r#"{"name":"string"}"#));
+    }
+}
@@ -0,0 +1,245 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::appstate::ResultsSnapshot;
+
+/// The key metrics of one monitoring run, condensed from its results.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunSummary {
+    /// Task results and load test requests together. A task counts once, by the outcome of its latest
+    /// iteration, however many iterations it ran.
+    pub total_results: usize,
+    /// Results among `total_results` that succeeded.
+    pub success_count: usize,
+    /// Results among `total_results` that failed.
+    pub error_count: usize,
+    /// `error_count` divided by `total_results`.
+    pub error_rate: f64,
+    /// The fastest response time of any task or load test request, in milliseconds.
+    pub min_response_time_ms: f64,
+    /// Mean response time over every task and load test request, in milliseconds.
+    pub mean_response_time_ms: f64,
+    /// The slowest response time of any task or load test request, in milliseconds.
+    pub max_response_time_ms: f64,
+    /// Monitors that failed in the run, teardown steps included; only the run itself knows this, so `/summary` leaves it out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_monitors: Option<usize>,
+    /// Tasks that ran to completion, across all workflows.
+    pub tasks: usize,
+    /// Tasks that ended in an error.
+    pub task_failures: usize,
+    /// Tasks whose response took longer than their `response_time_threshold`.
+    pub slow_tasks: usize,
+    /// Mean response time of the tasks, in milliseconds.
+    pub average_task_response_time_ms: f64,
+    /// Requests sent by all load tests together.
+    pub load_test_requests: usize,
+    /// Load test requests that failed.
+    pub load_test_failures: usize,
+    /// Failed load test requests divided by `load_test_requests`.
+    pub load_test_error_rate: f64,
+    /// Mean response time of load test requests, weighted by each test's request count.
+    pub average_load_test_response_time_ms: f64,
+    /// The highest 95th percentile response time of any load test with enough samples to report one.
+    pub max_p95_response_time_ms: f64,
+    /// Achieved requests per second, summed over the load tests.
+    pub achieved_rps: f64,
+    /// WebSocket load tests that ran, across all workflows.
+    pub websocket_tests: usize,
+    /// WebSocket load tests with a connection that failed or a message that went unanswered.
+    pub websocket_failures: usize,
+    /// Each endpoint's health score, keyed by `<workflow>/<api>`.
+    pub health_scores: BTreeMap<String, f64>,
+}
+
+impl RunSummary {
+    pub fn from_snapshot(snapshot: &ResultsSnapshot) -> Self {
+        // A task cancelled mid-request neither succeeded nor failed, so it is left out.
+        let tasks: Vec<_> = snapshot.task_results.values().flat_map(|apis| apis.values())
+            .filter(|data| data.status != "CANCELLED")
+            .collect();
+        let load_tests: Vec<_> = snapshot.load_test_results.values().flat_map(|apis| apis.values()).collect();
+        let websocket_tests: Vec<_> = snapshot.websocket_results.values().flat_map(|apis| apis.values()).collect();
+
+        let load_test_requests = load_tests.iter().map(|data| data.total_requests).sum::<usize>();
+        let load_test_failures = load_tests.iter().map(|data| data.failure_count).sum::<usize>();
+        let weighted_response_time = load_tests.iter()
+            .map(|data| data.average_response_time_ms as f64 * data.total_requests as f64)
+            .sum::<f64>();
+        let task_failures = tasks.iter().filter(|data| data.status != "OK").count();
+        let task_response_time = tasks.iter().map(|data| data.response_time as f64).sum::<f64>();
+
+        // A load test that sent no requests has no response times to offer.
+        let fastest = tasks.iter().map(|data| data.response_time as f64)
+            .chain(load_tests.iter().filter(|data| data.total_requests > 0).map(|data| data.min_response_time_ms as f64));
+        let slowest = tasks.iter().map(|data| data.response_time as f64)
+            .chain(load_tests.iter().filter(|data| data.total_requests > 0).map(|data| data.max_response_time_ms as f64));
+
+        let total_results = tasks.len() + load_test_requests;
+        let error_count = task_failures + load_test_failures;
+        RunSummary {
+            total_results,
+            success_count: total_results - error_count,
+            error_count,
+            error_rate: ratio(error_count as f64, total_results as f64),
+            min_response_time_ms: fastest.reduce(f64::min).unwrap_or_default(),
+            mean_response_time_ms: ratio(task_response_time + weighted_response_time, total_results as f64),
+            max_response_time_ms: slowest.reduce(f64::max).unwrap_or_default(),
+            failed_monitors: None,
+            tasks: tasks.len(),
+            task_failures,
+            slow_tasks: tasks.iter().filter(|data| data.exceeded_threshold).count(),
+            average_task_response_time_ms: mean(tasks.iter().map(|data| data.response_time as f64)),
+            load_test_requests,
+            load_test_failures,
+            load_test_error_rate: ratio(load_test_failures as f64, load_test_requests as f64),
+            average_load_test_response_time_ms: ratio(weighted_response_time, load_test_requests as f64),
+            max_p95_response_time_ms: load_tests.iter()
+                .filter_map(|data| data.percentile_95th_response_time_ms)
+                .map(|p95| p95 as f64)
+                .fold(0.0, f64::max),
+            achieved_rps: load_tests.iter().map(|data| data.achieved_rps).fold(0.0, |total, rps| total + rps),
+            websocket_tests: websocket_tests.len(),
+            websocket_failures: websocket_tests.iter().filter(|data| data.status != "OK").count(),
+            health_scores: health_scores(snapshot),
+        }
+    }
+
+    /// Whether the run had no failed monitors, no task slower than its threshold, and no failed WebSocket load test.
+    pub fn passed(&self) -> bool {
+        self.failed_monitors.unwrap_or_default() == 0 && self.task_failures == 0 && self.slow_tasks == 0 && self.websocket_failures == 0
+    }
+
+    /// The metrics compared across repeated runs, by name.
+    fn metrics(&self) -> [(&'static str, f64); 7] {
+        [
+            ("error_rate", self.error_rate),
+            ("task_failures", self.task_failures as f64),
+            ("average_task_response_time_ms", self.average_task_response_time_ms),
+            ("load_test_error_rate", self.load_test_error_rate),
+            ("average_load_test_response_time_ms", self.average_load_test_response_time_ms),
+            ("max_p95_response_time_ms", self.max_p95_response_time_ms),
+            ("achieved_rps", self.achieved_rps),
+        ]
+    }
+}
+
+fn health_scores(snapshot: &ResultsSnapshot) -> BTreeMap<String, f64> {
+    let task_scores = snapshot.task_results.iter().flat_map(|(workflow, apis)| {
+        apis.iter().map(move |(api, data)| (workflow, api, data.health_score))
+    });
+    let load_test_scores = snapshot.load_test_results.iter().flat_map(|(workflow, apis)| {
+        apis.iter().map(move |(api, data)| (workflow, api, data.health_score))
+    });
+
+    task_scores.chain(load_test_scores)
+        .filter_map(|(workflow, api, score)| Some((format!("{}/{}", workflow, api), score?)))
+        .collect()
+}
+
+/// How one metric varied across repeated runs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricStats {
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Sample variance between runs; 0 when there was a single run.
+    pub variance: f64,
+    pub std_dev: f64,
+}
+
+/// The outcome of `--repeat`: every run's summary and the spread of each metric across them.
+#[derive(Debug, Clone, Serialize)]
+pub struct RepeatReport {
+    pub runs: Vec<RunSummary>,
+    pub metrics: BTreeMap<&'static str, MetricStats>,
+}
+
+/// Aggregates the summaries of repeated runs, so run-to-run noise can be told apart from real changes.
+pub fn aggregate(runs: Vec<RunSummary>) -> RepeatReport {
+    let mut values: BTreeMap<&'static str, Vec<f64>> = BTreeMap::new();
+    for run in &runs {
+        for (name, value) in run.metrics() {
+            values.entry(name).or_default().push(value);
+        }
+    }
+
+    let metrics = values.into_iter()
+        .map(|(name, values)| (name, metric_stats(&values)))
+        .collect();
+    RepeatReport { runs, metrics }
+}
+
+fn metric_stats(values: &[f64]) -> MetricStats {
+    let mean = mean(values.iter().copied());
+    let variance = if values.len() > 1 {
+        values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64
+    } else {
+        0.0
+    };
+
+    MetricStats {
+        mean,
+        min: values.iter().copied().fold(f64::INFINITY, f64::min),
+        max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        variance,
+        std_dev: variance.sqrt(),
+    }
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+    ratio(sum, count as f64)
+}
+
+fn ratio(numerator: f64, denominator: f64) -> f64 {
+    if denominator == 0.0 { 0.0 } else { numerator / denominator }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(achieved_rps: f64, task_failures: usize) -> RunSummary {
+        RunSummary {
+            tasks: 2,
+            task_failures,
+            average_task_response_time_ms: 10.0,
+            load_test_requests: 100,
+            average_load_test_response_time_ms: 20.0,
+            max_p95_response_time_ms: 40.0,
+            achieved_rps,
+            ..RunSummary::default()
+        }
+    }
+
+    #[test]
+    fn test_aggregate_reports_spread_between_runs() {
+        let report = aggregate(vec![run(90.0, 0), run(100.0, 1), run(110.0, 2)]);
+        let rps = &report.metrics["achieved_rps"];
+
+        assert_eq!(report.runs.len(), 3);
+        assert_eq!((rps.mean, rps.min, rps.max), (100.0, 90.0, 110.0));
+        assert_eq!(rps.variance, 100.0);
+        assert_eq!(rps.std_dev, 10.0);
+        assert_eq!(report.metrics["task_failures"].mean, 1.0);
+        assert_eq!(report.metrics["max_p95_response_time_ms"].variance, 0.0);
+    }
+
+    #[test]
+    fn test_failures_and_slow_tasks_fail_the_run() {
+        assert!(run(50.0, 0).passed());
+        assert!(!run(50.0, 1).passed());
+        assert!(!RunSummary { slow_tasks: 1, ..run(50.0, 0) }.passed());
+        assert!(!RunSummary { failed_monitors: Some(1), ..run(50.0, 0) }.passed());
+        assert!(!RunSummary { websocket_tests: 1, websocket_failures: 1, ..run(50.0, 0) }.passed());
+    }
+
+    #[test]
+    fn test_single_run_has_no_variance() {
+        let report = aggregate(vec![run(50.0, 0)]);
+        let rps = &report.metrics["achieved_rps"];
+
+        assert_eq!((rps.mean, rps.min, rps.max, rps.variance), (50.0, 50.0, 50.0, 0.0));
+    }
+}
@@ -1,11 +1,12 @@
 use std::{collections::HashMap, str::FromStr, sync::Arc};
-use log::{info,error};
+use log::{info,warn,error};
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 use reqwest::Client;
 use serde::Serialize;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use crate::{appstate::AppState, config::{ApiConfig, HttpMethod}, factory::{create_request_builder, ApiMonitor}};
-use std::time::Instant;
+use crate::{appstate::AppState, config::{ApiConfig, HttpMethod, Settings, TimeUnit}, factory::{create_request_builder_for_iteration, ApiMonitor}, utils::{data_feeder, error_kind::{self, ErrorKind}, error_log, extract::extract_variables, failure_rules::{classify_failure, expected_field_error, invalid_json}, server_timing::parse_processing_time, snippet::body_snippet, http_client::{send_recording_redirects, with_dns_timing, with_redirect_limit, RedirectHop}, ndjson::{self, NdjsonRecord}, health_score::health_score, random_body, request_limit, request_history::HistorySample, response_times::ResponseTimeStats, timeline::{RequestTimeline, TimelineEvent}, trace}};
+use std::time::{Duration, Instant, SystemTime};
 
 
 /// Represents the data collected during the monitoring of an API call.
@@ -13,14 +14,63 @@ use std::time::Instant;
 pub struct MonitoringData {
     /// The name of the workflow this data is associated with.
     pub api_url: String,
-    /// The status of the monitoring operation, e.g., "OK" or "ERROR".
+    /// The status of the monitoring operation: "OK", "ERROR", or "CANCELLED" when the run was stopped mid-request.
     pub status: String,
     /// The response time measured for the API call, in milliseconds.
     pub response_time: u64,
+    /// The response time at microsecond resolution, expressed in `time_unit`.
+    pub response_time_precise: f64,
+    /// The unit of `response_time_precise`, either "ms" or "us".
+    pub time_unit: TimeUnit,
+    /// Time spent resolving host names, in `time_unit`; absent when a pooled connection was reused or the host is an IP address.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns_time: Option<f64>,
+    /// Time from sending the request until its response headers arrived, in `time_unit`, leaving out host name
+    /// lookups and earlier redirect hops. reqwest does not report when a connection is established, so opening a
+    /// new connection is still included.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_to_first_byte: Option<f64>,
     /// The HTTP status code returned by the API call, if applicable.
     pub status_code: Option<u16>,
     /// The HTTP method used for the API call.
     pub method: HttpMethod,
+    /// The method sent on the wire when it differs from `method` because of a method override header.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wire_method: Option<HttpMethod>,
+    /// The size of the generated request body, when the API sends random bodies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_body_bytes: Option<usize>,
+    /// The redirects followed before the final response, when redirect recording is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_chain: Option<Vec<RedirectHop>>,
+    /// The label of the failure rule that classified this response as an error, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_label: Option<String>,
+    /// What kind of failure occurred, for results with an "ERROR" status.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<ErrorKind>,
+    /// A description of what went wrong, for results with an "ERROR" status.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The address family of the connection the response arrived on, "ipv4" or "ipv6".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address_family: Option<&'static str>,
+    /// Processing time reported by the server in the configured timing header, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_processing_ms: Option<f64>,
+    /// The request's attempt, redirects, and outcome with per-step latency, when request timelines are enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeline: Option<Vec<TimelineEvent>>,
+    /// Whether a response arrived but took longer than the API's `response_time_threshold`.
+    pub exceeded_threshold: bool,
+    /// Whether the request was resent once because the connection it was sent on broke.
+    pub connection_retried: bool,
+    /// Statistics over this task's response times across every run so far, filled in when the result is recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_time_stats: Option<ResponseTimeStats>,
+    /// The mean outcome score across every run so far, weighted by the API's `status_weights`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health_score: Option<f64>,
 }
 
 
@@ -35,13 +85,67 @@ pub struct Task {
     pub api_config: Arc<ApiConfig>,
     /// A reference to the shared application state for recording monitoring data.
     pub app_state: Arc<Mutex<AppState>>, // Include a reference to AppState
+    /// Global settings that influence how results are recorded.
+    pub settings: Arc<Settings>,
 }
 
 #[async_trait::async_trait]
 impl ApiMonitor for Task {
 
-    async fn execute(&self, client: &Client, workflow_name: &str) -> Result<(), String> {
-        let start = Instant::now();
+    async fn execute(&self, client: &Client, workflow_name: &str, cancel: &CancellationToken) -> Result<(), String> {
+        let iterations = self.api_config.iterations.unwrap_or(1);
+        let mut failures = 0;
+        let mut last_error = None;
+        // Iterations run one after another; each is recorded, so repeated runs build up percentiles.
+        for iteration in 0..iterations {
+            if iteration > 0 {
+                if let Some(delay_ms) = self.api_config.delay_ms {
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_millis(delay_ms)) => {},
+                        _ = cancel.cancelled() => {},
+                    }
+                }
+            }
+            if cancel.is_cancelled() {
+                return Err(format!("'{}' was cancelled after {} of {} iterations", self.api_config.name, iteration, iterations));
+            }
+            // With unique data rows used up, the remaining iterations would only fail the same way.
+            if data_feeder::unique_rows_left(&self.api_config) == Some(0) {
+                failures += iterations - iteration;
+                last_error = Some(format!("'{}' has no unique data rows left for its remaining {} iterations", self.api_config.name, iterations - iteration));
+                break;
+            }
+            if let Err(e) = self.execute_iteration(client, workflow_name, iteration as u64, cancel).await {
+                failures += 1;
+                last_error = Some(e);
+            }
+        }
+
+        match last_error {
+            None => Ok(()),
+            Some(e) if iterations == 1 => Err(e),
+            Some(e) => Err(format!("{} of {} iterations failed, the last with: {}", failures, iterations, e)),
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("Task for {}", self.api_config.name)
+    }
+
+    fn response_time_threshold(&self) -> Option<u64> {
+        Some(self.api_config.response_time_threshold)
+    }
+
+    fn get_task_order(&self) -> usize {
+        self.api_config.task_order.unwrap_or(usize::MAX)
+    }
+}
+
+impl Task {
+    /// Sends the request once and records the outcome; `iteration` numbers the run for generated bodies.
+    async fn execute_iteration(&self, client: &Client, workflow_name: &str, iteration: u64, cancel: &CancellationToken) -> Result<(), String> {
+        // Held until the result is recorded; waiting for it is not part of the measured response time.
+        let _permit = request_limit::acquire().await;
         let mut headers = HeaderMap::new();
 
         for (key, value) in &self.api_config.headers {
@@ -53,70 +157,257 @@ impl ApiMonitor for Task {
             }
         }
 
-        let request_builder = create_request_builder(client, &self.api_config)?;
+        let variables = self.app_state.lock().await.variables(workflow_name).await;
+        let data_row = data_feeder::claim_row(&self.api_config, iteration)?;
+        let request_builder = create_request_builder_for_iteration(client, &self.api_config, iteration, data_row, &variables)?;
+        // Building the request may read, template, and compress its body, none of which is part of the response time.
+        let lane = trace::is_tracing().then(trace::Lane::acquire);
+        let start = Instant::now();
 
-        let response = request_builder.send().await;
+        let retry_broken_connection = self.api_config.retry_broken_connection.unwrap_or(true);
+        let send = with_dns_timing(
+            with_redirect_limit(self.api_config.max_redirects(), send_recording_redirects(request_builder, retry_broken_connection)),
+        );
+        // A cancelled run abandons the request; its result says so rather than counting as an error.
+        let ((response, redirect_hops, connection_retried), dns_duration) = tokio::select! {
+            sent = send => sent,
+            _ = cancel.cancelled() => {
+                self.record_cancelled(workflow_name, start.elapsed()).await;
+                return Err(format!("'{}' was cancelled while waiting for a response", self.api_config.name));
+            },
+        };
 
         let duration = start.elapsed();
+        // The first byte is timed from when the request could go out: after the host name lookups, or
+        // after the last redirect response when hops were recorded.
+        let request_sent = redirect_hops.last().map(|(_, at)| *at).unwrap_or(start + dns_duration.unwrap_or_default());
+        let time_to_first_byte = (start + duration).saturating_duration_since(request_sent);
+        if let Some(lane) = &lane {
+            let status_code = response.as_ref().ok().map(|resp| resp.status().as_u16());
+            trace::record(&self.api_config.name, "task", start, duration, lane, status_code);
+        }
+        let time_unit = self.settings.time_unit.unwrap_or_default();
+        let response_time_precise = time_unit.measure(duration);
+        let dns_time = dns_duration.map(|dns_duration| time_unit.measure(dns_duration));
+        let request_body_bytes = random_body::body_size(&self.api_config, iteration);
+        let wire_method = self.api_config.method_override_header.as_ref().map(|_| self.api_config.wire_method());
+        let mut timeline = self.settings.request_timeline.unwrap_or(false).then(|| {
+            let mut timeline = RequestTimeline::start(start, &self.api_config.url);
+            for (hop, at) in &redirect_hops {
+                timeline.redirect(hop, *at);
+            }
+            timeline
+        });
+        let redirect_hops: Vec<_> = redirect_hops.into_iter().map(|(hop, _)| hop).collect();
+        let redirect_chain = if redirect_hops.is_empty() || !self.settings.record_redirects.unwrap_or(false) { None } else { Some(redirect_hops) };
 
         // Create a MonitoringData instance based on the response
         match response {
             Ok(resp) => {
                 let status_code = resp.status().as_u16();
-                if resp.status().is_success() {
+                let address_family = resp.remote_addr().map(|addr| if addr.is_ipv4() { "ipv4" } else { "ipv6" });
+                let exceeded_threshold = self.response_time_threshold().is_some_and(|threshold| duration.as_millis() > threshold as u128);
+                if exceeded_threshold {
+                    warn!("'{}' took {:?}, exceeding its threshold of {}ms", self.api_config.name, duration, self.api_config.response_time_threshold);
+                }
+                if let Some(timeline) = &mut timeline {
+                    timeline.response(resp.url().as_str(), status_code, start + duration);
+                }
+                let timeline = timeline.map(RequestTimeline::into_events);
+                let is_success = self.api_config.is_expected_status(status_code);
+                let server_processing_ms = self.api_config.server_timing_header.as_deref()
+                    .and_then(|header_name| {
+                        let value = resp.headers().get(header_name)?.to_str().ok()?;
+                        parse_processing_time(header_name, value)
+                    });
+
+                // Failure rules, JSON validation, the expected field and extractions inspect the body, so it is only read when one of them is configured.
+                let failure_rules = self.api_config.failure_rules.as_deref().unwrap_or_default();
+                let require_valid_json = self.api_config.require_valid_json.unwrap_or(false);
+                let expected_field = Some(self.api_config.expected_field.as_str()).filter(|field| !field.is_empty());
+                let extractions = self.api_config.extract.as_deref().unwrap_or_default();
+                let (failure_label, body) = if failure_rules.is_empty() && !require_valid_json && expected_field.is_none() && extractions.is_empty() {
+                    (None, None)
+                } else {
+                    let response_headers = resp.headers().clone();
+                    let body = resp.text().await.unwrap_or_default();
+                    (classify_failure(failure_rules, status_code, &response_headers, &body).map(str::to_string), Some(body))
+                };
+                let body_error = match (&body, expected_field) {
+                    (Some(body), Some(field)) if is_success && failure_label.is_none() => {
+                        expected_field_error(body, field, self.api_config.expected_value.as_deref())
+                    },
+                    (Some(body), None) if require_valid_json && is_success && failure_label.is_none() => {
+                        invalid_json(body).map(|error| format!("invalid JSON: {}", error))
+                    },
+                    _ => None,
+                };
+                // Values are only extracted from responses that passed every other check.
+                let body_error = match (&body, body_error) {
+                    (Some(body), None) if is_success && failure_label.is_none() && !extractions.is_empty() => {
+                        match extract_variables(body, extractions) {
+                            Ok(extracted) => {
+                                self.app_state.lock().await.set_variables(workflow_name, extracted).await;
+                                None
+                            },
+                            Err(error) => Some(error),
+                        }
+                    },
+                    (_, body_error) => body_error,
+                };
+
+                if is_success && failure_label.is_none() && body_error.is_none() {
                     // If the status is within the range of success codes
                     let monitoring_data = MonitoringData {
                         api_url: self.api_config.url.clone(),
                         status: "OK".to_string(),
                         response_time: duration.as_millis() as u64,
+                        response_time_precise,
+                        time_unit,
+                        dns_time,
+                        time_to_first_byte: Some(time_unit.measure(time_to_first_byte)),
                         status_code: Some(status_code), // Store the successful status code
                         method: self.api_config.method.clone(), // Include the method in the monitoring data
+                        wire_method,
+                        request_body_bytes,
+                        redirect_chain,
+                        failure_label: None,
+                        error_kind: None,
+                        error: None,
+                        address_family,
+                        server_processing_ms,
+                        timeline,
+                        exceeded_threshold,
+                        connection_retried,
+                        response_time_stats: None,
+                        health_score: None,
                     };
-                    update_app_state(&self.app_state, &workflow_name, &self.api_config.name, MonitoringDataType::Task, monitoring_data).await;
-                    info!("'{}' succeeded with status code {} in {:?}", self.api_config.name, status_code, duration);
+                    update_app_state(&self.app_state, workflow_name, &self.api_config.name, self.api_config.status_weights.as_ref(), MonitoringDataType::Task, monitoring_data, &self.settings).await;
+                    if self.settings.log_per_request() {
+                        info!("'{}' succeeded with status code {} in {:?}", self.api_config.name, status_code, duration);
+                    }
                     Ok(())
                 } else {
-                    // For non-successful HTTP status codes
-                    let error_message = format!("'{}' responded with HTTP status {}", self.api_config.name, status_code);
-                    error!("{}", error_message);
+                    // For non-successful HTTP status codes or responses matching a failure rule
+                    let mut error_message = match (&failure_label, &body_error) {
+                        (Some(label), _) => format!("'{}' matched failure rule '{}' with HTTP status {}", self.api_config.name, label, status_code),
+                        (None, Some(body_error)) => format!("'{}' responded with {}", self.api_config.name, body_error),
+                        (None, None) => format!("'{}' responded with HTTP status {}", self.api_config.name, status_code),
+                    };
+                    // Include part of the body when an assertion failed so the failure can be diagnosed.
+                    if let (true, Some(body), Some(max_bytes)) = (failure_label.is_some() || body_error.is_some(), &body, self.settings.failure_body_snippet_bytes) {
+                        error_message = format!("{}; body: {}", error_message, body_snippet(body, max_bytes));
+                    }
+                    if error_log::allow() {
+                        error!("{}", error_message);
+                    }
                     let monitoring_data = MonitoringData {
                         api_url: self.api_config.url.clone(),
                         status: "ERROR".to_string(),
                         response_time: duration.as_millis() as u64,
+                        response_time_precise,
+                        time_unit,
+                        dns_time,
+                        time_to_first_byte: Some(time_unit.measure(time_to_first_byte)),
                         status_code: Some(status_code), // Store the error status code
                         method: self.api_config.method.clone(), // Include the method in the monitoring data
+                        wire_method,
+                        request_body_bytes,
+                        redirect_chain,
+                        failure_label,
+                        error_kind: Some(ErrorKind::Http),
+                        error: Some(error_message.clone()),
+                        address_family,
+                        server_processing_ms,
+                        timeline,
+                        exceeded_threshold,
+                        connection_retried,
+                        response_time_stats: None,
+                        health_score: None,
                     };
-                    update_app_state(&self.app_state, &workflow_name, &self.api_config.name, MonitoringDataType::Task, monitoring_data).await;
+                    update_app_state(&self.app_state, workflow_name, &self.api_config.name, self.api_config.status_weights.as_ref(), MonitoringDataType::Task, monitoring_data, &self.settings).await;
                     Err(error_message)
                 }
             },
             Err(e) => {
                 // Error handling remains similar, but now without a status code
-                let error_message = format!("Failed to reach '{}': {}", self.api_config.name, e);
-                error!("{}", &error_message);
+                let error_message = if e.is_timeout() {
+                    format!("'{}' timed out after {:?}", self.api_config.name, duration)
+                } else {
+                    format!("Failed to reach '{}': {}", self.api_config.name, e)
+                };
+                if error_log::allow() {
+                    error!("{}", &error_message);
+                }
+                let timeline = timeline.map(|mut timeline| {
+                    timeline.error(e.to_string(), start + duration);
+                    timeline.into_events()
+                });
                 let monitoring_data = MonitoringData {
                     api_url: self.api_config.url.clone(),
                     status: "ERROR".to_string(),
                     response_time: duration.as_millis() as u64,
+                    response_time_precise,
+                    time_unit,
+                    dns_time,
+                    time_to_first_byte: None,
                     status_code: None, // No status code available in case of a connection error
                     method: self.api_config.method.clone(), // Include the method in the monitoring data
+                    wire_method,
+                    request_body_bytes,
+                    redirect_chain,
+                    failure_label: None,
+                    error_kind: Some(error_kind::classify(&e)),
+                    error: Some(error_message.clone()),
+                    address_family: None,
+                    server_processing_ms: None,
+                    timeline,
+                    exceeded_threshold: false,
+                    connection_retried,
+                    response_time_stats: None,
+                    health_score: None,
                 };
-                update_app_state(&self.app_state, &workflow_name,  &self.api_config.name, MonitoringDataType::Task, monitoring_data).await;
+                update_app_state(&self.app_state, workflow_name,  &self.api_config.name, self.api_config.status_weights.as_ref(), MonitoringDataType::Task, monitoring_data, &self.settings).await;
                 Err(error_message)
             }
         }
     }
 
-    fn describe(&self) -> String {
-        format!("Task for {}", self.api_config.name)
-    }
-
-    fn response_time_threshold(&self) -> Option<u64> {
-        None // No specific threshold for HTTP status monitoring
-    }
-
-    fn get_task_order(&self) -> usize {
-        self.api_config.task_order.unwrap_or(usize::MAX)
+    /// Records a request abandoned because its run was cancelled, leaving the task's statistics untouched.
+    async fn record_cancelled(&self, workflow_name: &str, elapsed: Duration) {
+        let state = self.app_state.lock().await;
+        let mut task_monitoring_data = state.task_monitoring_data.lock().await;
+        let apis = task_monitoring_data.entry(workflow_name.to_string()).or_default();
+        // The statistics of earlier runs still describe the task, so they carry over.
+        let (response_time_stats, health_score) = apis.get(&self.api_config.name)
+            .map(|previous| (previous.response_time_stats.clone(), previous.health_score))
+            .unwrap_or_default();
+        let time_unit = self.settings.time_unit.unwrap_or_default();
+        let monitoring_data = MonitoringData {
+            api_url: self.api_config.url.clone(),
+            status: "CANCELLED".to_string(),
+            response_time: elapsed.as_millis() as u64,
+            response_time_precise: time_unit.measure(elapsed),
+            time_unit,
+            dns_time: None,
+            time_to_first_byte: None,
+            status_code: None,
+            method: self.api_config.method.clone(),
+            wire_method: None,
+            request_body_bytes: None,
+            redirect_chain: None,
+            failure_label: None,
+            error_kind: None,
+            error: None,
+            address_family: None,
+            server_processing_ms: None,
+            timeline: None,
+            exceeded_threshold: false,
+            connection_retried: false,
+            response_time_stats,
+            health_score,
+        };
+        apis.insert(self.api_config.name.clone(), monitoring_data);
     }
 }
 
@@ -125,9 +416,21 @@ async fn update_app_state(
     app_state: &Arc<Mutex<AppState>>,
     workflow_name: &str,
     task_name: &str,
+    status_weights: Option<&HashMap<u16, f64>>,
     data_type: MonitoringDataType,
-    monitoring_data: MonitoringData
+    mut monitoring_data: MonitoringData,
+    settings: &Settings
 ) {
+    if ndjson::is_streaming() {
+        ndjson::emit(NdjsonRecord::now(
+            &monitoring_data.api_url,
+            &monitoring_data.method,
+            &monitoring_data.status,
+            monitoring_data.status_code,
+            monitoring_data.response_time,
+        ));
+    }
+
     // Lock the Mutex to access the underlying HashMap
     let state = app_state.lock().await;
 
@@ -142,10 +445,36 @@ async fn update_app_state(
                 .entry(workflow_name.to_string()) // Now correctly using entry on the HashMap
                 .or_insert_with(HashMap::new);
 
+            // Fold this response time into the task's history, so repeated calls build up percentiles.
+            let mut task_response_times = state.task_response_times.lock().await;
+            let history = task_response_times
+                .entry(workflow_name.to_string())
+                .or_default()
+                .entry(task_name.to_string())
+                .or_default();
+            // A 2xx response that failed a rule or check is scored like a response that never arrived.
+            let scored_status = monitoring_data.status_code
+                .filter(|status_code| monitoring_data.status == "OK" || !(200..300).contains(status_code));
+            history.record(monitoring_data.response_time, scored_status);
+            monitoring_data.response_time_stats = history.stats(settings.min_samples_for_percentiles.unwrap_or(0));
+            monitoring_data.health_score = health_score(status_weights, history.status_codes().map(|status_code| (status_code, 1)));
+
+            state.request_metrics.lock().await.record(task_name, monitoring_data.method.as_str(), monitoring_data.status_code,
+                Duration::from_millis(monitoring_data.response_time), monitoring_data.status != "OK");
+            if let Some(error) = &monitoring_data.error {
+                state.record_errors(&monitoring_data.api_url, [error.as_str()]).await;
+            }
+            if let Some(history_size) = settings.history_size {
+                let sample = HistorySample::at(SystemTime::now(), monitoring_data.response_time, monitoring_data.status_code);
+                state.record_history(&monitoring_data.api_url, history_size, [sample]).await;
+            }
+
             // Update the monitoring data for the specific API URL within the workflow
             workflow_data.insert(task_name.to_string(), monitoring_data);
 
-            log::info!("Updated task data for {} in workflow {}", task_name, workflow_name);
+            if settings.log_per_request() {
+                log::info!("Updated task data for {} in workflow {}", task_name, workflow_name);
+            }
         },
         // Handle other data types as needed
     };
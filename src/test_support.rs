@@ -0,0 +1,45 @@
+use crate::config::{ApiConfig, HttpMethod, Settings, TimeUnit};
+use crate::tasks::MonitoringData;
+
+/// A GET API named `name` that accepts any 2xx response within a second.
+pub fn api(name: &str) -> ApiConfig {
+    serde_json::from_value(serde_json::json!({
+        "name": name, "url": "http://localhost/orders", "method": "GET", "headers": {},
+        "expected_field": "", "response_time_threshold": 1000
+    })).unwrap()
+}
+
+/// Global settings with logging off and every optional setting left unset.
+pub fn settings() -> Settings {
+    serde_json::from_value(serde_json::json!({
+        "monitoring_interval_seconds": 60, "log_level": "off", "http_timeout_seconds": 5, "http_default_headers": {}
+    })).unwrap()
+}
+
+/// The result of a GET task to `url` that took 42ms, with only its status set.
+pub fn task_result(url: &str, status: &str, status_code: Option<u16>) -> MonitoringData {
+    MonitoringData {
+        api_url: url.to_string(),
+        status: status.to_string(),
+        response_time: 42,
+        response_time_precise: 42.0,
+        time_unit: TimeUnit::default(),
+        dns_time: None,
+        time_to_first_byte: None,
+        status_code,
+        method: HttpMethod::GET,
+        wire_method: None,
+        request_body_bytes: None,
+        redirect_chain: None,
+        failure_label: None,
+        error_kind: None,
+        error: None,
+        address_family: None,
+        server_processing_ms: None,
+        timeline: None,
+        exceeded_threshold: false,
+        connection_retried: false,
+        response_time_stats: None,
+        health_score: None,
+    }
+}
@@ -0,0 +1,44 @@
+use bytes::Bytes;
+use flate2::{write::{GzEncoder, ZlibEncoder}, Compression};
+use std::io::Write;
+
+/// The `Content-Encoding` values a request body can be compressed with.
+pub const ENCODINGS: [&str; 2] = ["gzip", "deflate"];
+
+/// Compresses `body` with `encoding`, "gzip" or "deflate"; the latter is the zlib format HTTP calls deflate.
+pub fn compress(encoding: &str, body: &[u8]) -> Result<Bytes, String> {
+    let compressed = match encoding {
+        "gzip" => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body).and_then(|_| encoder.finish())
+        },
+        "deflate" => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body).and_then(|_| encoder.finish())
+        },
+        other => return Err(format!("Unsupported body compression '{}', expected one of {}", other, ENCODINGS.join(", "))),
+    };
+    compressed.map(Bytes::from).map_err(|e| format!("Failed to {} the request body: {}", encoding, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use std::io::Read;
+
+    #[test]
+    fn test_compressed_bodies_decompress_to_the_original() {
+        let body = br#"{"items": ["a", "a", "a", "a", "a", "a", "a", "a"]}"#;
+
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&compress("gzip", body).unwrap()[..]).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, body);
+
+        decompressed.clear();
+        ZlibDecoder::new(&compress("deflate", body).unwrap()[..]).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, body);
+
+        assert!(compress("br", body).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use lazy_static::lazy_static;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::config::{ApiConfig, DataOrder};
+
+/// Prefix of the variables a data row provides, as in `${data.email}` for the row's `email` column.
+pub const DATA_PREFIX: &str = "data.";
+
+/// One row of a data file, by column name.
+pub type DataRow = HashMap<String, String>;
+
+lazy_static! {
+    // Rows per data file, read once and shared by every request that uses the file.
+    static ref ROWS: Mutex<HashMap<String, Arc<Vec<DataRow>>>> = Mutex::new(HashMap::new());
+    // Rows handed out so far per data file read in unique order, kept for the life of the process.
+    static ref UNIQUE_ROWS_USED: Mutex<HashMap<String, usize>> = Mutex::new(HashMap::new());
+}
+
+/// The rows of `path`, read on first use.
+///
+/// Files ending in `.jsonl` or `.ndjson` hold one JSON object per line; their strings are taken as they
+/// are and other values as JSON text. Anything else is read as CSV with a header row naming the columns.
+pub fn load(path: &str) -> Result<Arc<Vec<DataRow>>, String> {
+    if let Some(rows) = ROWS.lock().unwrap_or_else(PoisonError::into_inner).get(path) {
+        return Ok(rows.clone());
+    }
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("Error reading data_file '{}': {}", path, e))?;
+    let is_jsonl = Path::new(path).extension().is_some_and(|extension| extension == "jsonl" || extension == "ndjson");
+    let rows = if is_jsonl { parse_jsonl(&contents) } else { parse_csv(&contents) }
+        .map_err(|e| format!("Error parsing data_file '{}': {}", path, e))?;
+    if rows.is_empty() {
+        return Err(format!("data_file '{}' has no rows", path));
+    }
+    let rows = Arc::new(rows);
+    ROWS.lock().unwrap_or_else(PoisonError::into_inner).insert(path.to_string(), rows.clone());
+    Ok(rows)
+}
+
+/// Picks the row of the `data_file` one request uses; `None` without a `data_file`.
+///
+/// Round-robin order takes the row `iteration` points at, starting over once every row has been used. Unique
+/// order hands out each row once for the life of the process, whatever the iteration, so no row is sent twice
+/// across task runs or load test reruns; it fails once every row has been used. A request keeps its row when
+/// it is retried, so it is claimed once per request rather than per attempt.
+pub fn claim_row(api_config: &ApiConfig, iteration: u64) -> Result<Option<usize>, String> {
+    let Some(path) = &api_config.data_file else {
+        return Ok(None);
+    };
+    let rows = load(path)?;
+    match api_config.data_order.unwrap_or_default() {
+        DataOrder::RoundRobin => Ok(Some((iteration % rows.len() as u64) as usize)),
+        DataOrder::Unique => {
+            let mut unique_rows_used = UNIQUE_ROWS_USED.lock().unwrap_or_else(PoisonError::into_inner);
+            let used = unique_rows_used.entry(path.clone()).or_default();
+            if *used >= rows.len() {
+                return Err(format!("data_file '{}' has no rows left: all {} have been used", path, rows.len()));
+            }
+            *used += 1;
+            Ok(Some(*used - 1))
+        },
+    }
+}
+
+/// How many rows a `data_file` read in unique order has left to hand out; `None` for round-robin order, which never
+/// runs out, or without a readable `data_file`.
+pub fn unique_rows_left(api_config: &ApiConfig) -> Option<usize> {
+    let path = api_config.data_file.as_ref().filter(|_| api_config.data_order.unwrap_or_default() == DataOrder::Unique)?;
+    let rows = load(path).ok()?;
+    let used = UNIQUE_ROWS_USED.lock().unwrap_or_else(PoisonError::into_inner).get(path).copied().unwrap_or_default();
+    Some(rows.len().saturating_sub(used))
+}
+
+/// Adds the columns of row `row` of the `data_file` to `variables`, as `data.<column>`; unchanged without a row.
+pub fn with_data_row<'a>(api_config: &ApiConfig, row: Option<usize>, variables: &'a HashMap<String, String>) -> Result<Cow<'a, HashMap<String, String>>, String> {
+    let (Some(path), Some(row)) = (&api_config.data_file, row) else {
+        return Ok(Cow::Borrowed(variables));
+    };
+    let rows = load(path)?;
+    let row = rows.get(row).ok_or_else(|| format!("data_file '{}' has no row {}", path, row + 1))?;
+    let mut variables = variables.clone();
+    variables.extend(row.iter().map(|(column, value)| (format!("{}{}", DATA_PREFIX, column), value.clone())));
+    Ok(Cow::Owned(variables))
+}
+
+fn parse_jsonl(contents: &str) -> Result<Vec<DataRow>, String> {
+    contents.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| match serde_json::from_str::<Value>(line) {
+            Ok(Value::Object(object)) => Ok(object.into_iter()
+                .map(|(column, value)| match value {
+                    Value::String(value) => (column, value),
+                    value => (column, value.to_string()),
+                })
+                .collect()),
+            Ok(_) => Err(format!("line {} is not a JSON object", index + 1)),
+            Err(e) => Err(format!("line {}: {}", index + 1, e)),
+        })
+        .collect()
+}
+
+/// Reads CSV whose first line names the columns. Quoted fields may hold commas and doubled quotes, but not line
+/// breaks; a quote left open at the end of a line is rejected rather than read as a field spanning lines.
+fn parse_csv(contents: &str) -> Result<Vec<DataRow>, String> {
+    let mut lines = contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let Some((header_index, header)) = lines.next() else {
+        return Ok(Vec::new());
+    };
+    let columns = split_csv_line(header).ok_or_else(|| unclosed_quote(header_index))?;
+    lines
+        .map(|(index, line)| {
+            let fields = split_csv_line(line).ok_or_else(|| unclosed_quote(index))?;
+            if fields.len() != columns.len() {
+                return Err(format!("line {} has {} fields, but the header names {} columns", index + 1, fields.len(), columns.len()));
+            }
+            Ok(columns.iter().cloned().zip(fields).collect())
+        })
+        .collect()
+}
+
+fn unclosed_quote(index: usize) -> String {
+    format!("line {} has a quoted field that is not closed; fields spanning several lines are not supported", index + 1)
+}
+
+/// The fields of one CSV line, or `None` if a quoted field is still open at its end.
+fn split_csv_line(line: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    (!quoted).then_some(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_config(data_file: &str, data_order: Option<DataOrder>) -> ApiConfig {
+        let mut api_config: ApiConfig = serde_yaml::from_str(r#"
+name: "Signup"
+url: "http://localhost/signup"
+method: POST
+headers: {}
+expected_field: ""
+response_time_threshold: 1000
+"#).unwrap();
+        api_config.data_file = Some(data_file.to_string());
+        api_config.data_order = data_order;
+        api_config
+    }
+
+    #[test]
+    fn test_csv_rows_feed_each_iteration() {
+        let path = std::env::temp_dir().join(format!("load_test_tool_users_{}.csv", std::process::id()));
+        std::fs::write(&path, "email,name\nada@example.com,\"Lovelace, Ada\"\nalan@example.com,\"Alan \"\"T\"\"\"\n").unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        let round_robin = api_config(&path, None);
+        let variables = HashMap::from([("token".to_string(), "abc".to_string())]);
+        let row = |api_config: &ApiConfig, iteration| with_data_row(api_config, claim_row(api_config, iteration).unwrap(), &variables).unwrap().into_owned();
+        let first = row(&round_robin, 0);
+        assert_eq!(first["data.email"], "ada@example.com");
+        assert_eq!(first["data.name"], "Lovelace, Ada");
+        assert_eq!(first["token"], "abc");
+        assert_eq!(row(&round_robin, 1)["data.name"], "Alan \"T\"");
+        assert_eq!(row(&round_robin, 2)["data.email"], "ada@example.com");
+        assert_eq!(unique_rows_left(&round_robin), None);
+
+        // Unique rows are handed out in turn whatever the iteration, as when a task runs again from iteration 0.
+        let unique = api_config(&path, Some(DataOrder::Unique));
+        assert_eq!(row(&unique, 0)["data.email"], "ada@example.com");
+        assert_eq!(unique_rows_left(&unique), Some(1));
+        assert_eq!(row(&unique, 0)["data.email"], "alan@example.com");
+        assert_eq!(unique_rows_left(&unique), Some(0));
+        assert!(claim_row(&unique, 0).unwrap_err().contains("no rows left"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_jsonl_rows_keep_strings_and_render_other_values() {
+        let rows = parse_jsonl("{\"sku\": \"A-1\", \"quantity\": 2}\n\n{\"sku\": \"B-2\", \"quantity\": 5}\n").unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1]["sku"], "B-2");
+        assert_eq!(rows[1]["quantity"], "5");
+        assert!(parse_jsonl("[1, 2]").unwrap_err().contains("line 1"));
+        assert!(parse_csv("a,b\n1,2,3").unwrap_err().contains("line 2 has 3 fields"));
+        assert!(parse_csv("a,b\n1,\"two\nlines\"").unwrap_err().contains("line 2 has a quoted field that is not closed"));
+    }
+}
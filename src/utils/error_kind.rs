@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+/// What kind of failure a request ran into, used to pick how to back off before retrying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The connection could not be established, e.g. the target is down.
+    Connect,
+    /// The request timed out.
+    Timeout,
+    /// A response arrived but indicated failure.
+    Http,
+    /// Anything else, such as an invalid request.
+    Other,
+}
+
+/// Classifies a transport error returned by the HTTP client.
+pub fn classify(error: &reqwest::Error) -> ErrorKind {
+    if error.is_connect() {
+        ErrorKind::Connect
+    } else if error.is_timeout() {
+        ErrorKind::Timeout
+    } else if error.status().is_some() {
+        ErrorKind::Http
+    } else {
+        ErrorKind::Other
+    }
+}
+
+/// Whether an error means the connection broke under the request, as happens when a pooled
+/// keep-alive connection was closed by the server, rather than the target being unreachable.
+pub fn is_broken_connection(error: &reqwest::Error) -> bool {
+    if error.is_connect() || error.is_timeout() {
+        return false;
+    }
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        if cause.downcast_ref::<hyper::Error>().is_some_and(hyper::Error::is_incomplete_message) {
+            return true;
+        }
+        if let Some(io_error) = cause.downcast_ref::<std::io::Error>() {
+            use std::io::ErrorKind::{BrokenPipe, ConnectionAborted, ConnectionReset, UnexpectedEof};
+            return matches!(io_error.kind(), BrokenPipe | ConnectionAborted | ConnectionReset | UnexpectedEof);
+        }
+        source = cause.source();
+    }
+    false
+}
+
+/// Classifies a failed run from the errors of its requests.
+///
+/// A run counts as a connection failure only when every request failed to connect, since that
+/// means the target is unreachable rather than struggling.
+pub fn classify_run<'a>(request_errors: impl IntoIterator<Item = Option<&'a ErrorKind>>) -> ErrorKind {
+    let mut request_errors = request_errors.into_iter().peekable();
+    if request_errors.peek().is_none() {
+        return ErrorKind::Http;
+    }
+
+    if request_errors.all(|kind| kind == Some(&ErrorKind::Connect)) {
+        ErrorKind::Connect
+    } else {
+        ErrorKind::Http
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_is_a_connect_failure_only_when_nothing_connected() {
+        let connect = ErrorKind::Connect;
+        assert_eq!(classify_run([Some(&connect), Some(&connect)]), ErrorKind::Connect);
+        assert_eq!(classify_run([Some(&connect), None]), ErrorKind::Http);
+        assert_eq!(classify_run([Some(&connect), Some(&ErrorKind::Timeout)]), ErrorKind::Http);
+        assert_eq!(classify_run(std::iter::empty()), ErrorKind::Http);
+    }
+
+    #[tokio::test]
+    async fn test_refused_connections_are_classified_as_connect() {
+        let error = reqwest::get("http://127.0.0.1:1/").await.unwrap_err();
+        assert_eq!(classify(&error), ErrorKind::Connect);
+        assert!(!is_broken_connection(&error));
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+/// How often the number of suppressed error lines is reported.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+static LIMITER: OnceLock<Mutex<TokenBucket>> = OnceLock::new();
+static SUPPRESSED: AtomicUsize = AtomicUsize::new(0);
+
+/// Allows bursts of up to `capacity` events, refilling at `capacity` per second.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_second: usize, now: Instant) -> Self {
+        TokenBucket { capacity: per_second as f64, tokens: per_second as f64, refilled_at: now }
+    }
+
+    fn try_take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.capacity).min(self.capacity);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Limits error log lines to `per_second`, and reports how many were suppressed once a second.
+///
+/// Only log output is throttled; results and error counts are recorded in full.
+pub fn start(per_second: usize) {
+    if LIMITER.set(Mutex::new(TokenBucket::new(per_second, Instant::now()))).is_err() {
+        return; // Already limiting
+    }
+    std::thread::spawn(|| loop {
+        std::thread::sleep(REPORT_INTERVAL);
+        report_suppressed();
+    });
+}
+
+/// Logs how many error lines were suppressed since the last report, if any; called once a run ends so none go unreported.
+pub fn report_suppressed() {
+    let suppressed = SUPPRESSED.swap(0, Ordering::Relaxed);
+    if suppressed > 0 {
+        log::warn!("{} errors suppressed by the error log rate limit", suppressed);
+    }
+}
+
+/// Whether an error line may be logged now; when it may not, it is counted as suppressed.
+pub fn allow() -> bool {
+    let Some(limiter) = LIMITER.get() else {
+        return true;
+    };
+    let allowed = limiter.lock().unwrap_or_else(PoisonError::into_inner).try_take(Instant::now());
+    if !allowed {
+        SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+    }
+    allowed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_allows_bursts_then_refills() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2, start);
+
+        assert!(bucket.try_take(start));
+        assert!(bucket.try_take(start));
+        assert!(!bucket.try_take(start));
+        assert!(!bucket.try_take(start + Duration::from_millis(400)));
+        assert!(bucket.try_take(start + Duration::from_millis(600)));
+        // A long pause refills no more than the capacity.
+        let later = start + Duration::from_secs(10);
+        assert!(bucket.try_take(later) && bucket.try_take(later));
+        assert!(!bucket.try_take(later));
+    }
+}
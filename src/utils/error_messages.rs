@@ -0,0 +1,103 @@
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Most distinct messages kept per endpoint; further new messages are counted under `OTHER_MESSAGES`.
+const MAX_DISTINCT_MESSAGES: usize = 50;
+/// The bucket that absorbs new messages once an endpoint has `MAX_DISTINCT_MESSAGES`.
+const OTHER_MESSAGES: &str = "<other messages>";
+
+lazy_static! {
+    static ref UUID: Regex = Regex::new(r"\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b").unwrap();
+    static ref HEX: Regex = Regex::new(r"\b[0-9a-fA-F]{16,}\b").unwrap();
+    static ref NUMBER: Regex = Regex::new(r"\d+(?:\.\d+)*").unwrap();
+}
+
+/// Replaces the parts of an error message that differ between otherwise identical errors, so they group together.
+///
+/// UUIDs, hex tokens of 16 or more characters, and numbers become placeholders; three-digit integers are kept
+/// since they are usually HTTP status codes. A captured response body is dropped.
+pub fn normalize(message: &str) -> String {
+    let message = message.split("; body: ").next().unwrap_or_default();
+    let message = UUID.replace_all(message, "<uuid>");
+    let message = HEX.replace_all(&message, "<hex>");
+    NUMBER.replace_all(&message, |caps: &Captures| {
+        let number = &caps[0];
+        if number.len() == 3 && !number.contains('.') { number.to_string() } else { "<n>".to_string() }
+    }).into_owned()
+}
+
+/// How often each distinct, normalized error message occurred for one endpoint.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorMessageCounts {
+    counts: HashMap<String, usize>,
+}
+
+/// One row of an endpoint's error frequency table.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ErrorMessageCount {
+    pub message: String,
+    pub count: usize,
+}
+
+impl ErrorMessageCounts {
+    /// Counts one occurrence of `message` after normalizing it.
+    pub fn record(&mut self, message: &str) {
+        let message = normalize(message);
+        let key = if self.counts.contains_key(&message) || self.counts.len() < MAX_DISTINCT_MESSAGES {
+            message
+        } else {
+            OTHER_MESSAGES.to_string()
+        };
+        *self.counts.entry(key).or_default() += 1;
+    }
+
+    /// The `limit` most frequent messages, most frequent first.
+    pub fn top(&self, limit: usize) -> Vec<ErrorMessageCount> {
+        let mut counts: Vec<ErrorMessageCount> = self.counts.iter()
+            .map(|(message, count)| ErrorMessageCount { message: message.clone(), count: *count })
+            .collect();
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.message.cmp(&b.message)));
+        counts.truncate(limit);
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_strips_volatile_tokens() {
+        assert_eq!(
+            normalize("error sending request for url (http://127.0.0.1:49152/orders/7): connection refused"),
+            "error sending request for url (http://<n>:<n>/orders/<n>): connection refused"
+        );
+        assert_eq!(
+            normalize("'Orders' responded with HTTP status 503; body: {\"id\": 12}"),
+            "'Orders' responded with HTTP status 503"
+        );
+        assert_eq!(
+            normalize("trace 0af7651916cd43dd8448eb211c80319c for 123e4567-e89b-12d3-a456-426614174000 took 1.5s"),
+            "trace <hex> for <uuid> took <n>s"
+        );
+    }
+
+    #[test]
+    fn test_counts_group_similar_messages_and_stay_bounded() {
+        let mut counts = ErrorMessageCounts::default();
+        counts.record("timed out after 1001ms");
+        counts.record("timed out after 2002ms");
+        counts.record("timed out after 30.5ms");
+        counts.record("HTTP status 500");
+        for i in 0..MAX_DISTINCT_MESSAGES {
+            counts.record(&format!("failure {}", "x".repeat(i + 1)));
+        }
+
+        let top = counts.top(2);
+        assert_eq!(top[0], ErrorMessageCount { message: "timed out after <n>ms".to_string(), count: 3 });
+        assert_eq!(counts.counts.len(), MAX_DISTINCT_MESSAGES + 1);
+        assert_eq!(counts.counts[OTHER_MESSAGES], 2);
+    }
+}
@@ -0,0 +1,86 @@
+use regex::Regex;
+use serde_json::Value;
+
+use crate::config::Extraction;
+
+/// Takes each extraction's value from a response body, as `(variable, value)` pairs.
+///
+/// JSON strings are taken as they are and other values as JSON text; a regex takes its first capture
+/// group, or its whole match. Fails if a field is missing or null, the body is not JSON, or a regex does
+/// not match, since requests relying on the variable could not be sent as intended.
+pub fn extract_variables(body: &str, extractions: &[Extraction]) -> Result<Vec<(String, String)>, String> {
+    // Extractions by regex work on any body, so it is only parsed as JSON when a field is extracted.
+    let mut json: Option<Value> = None;
+    extractions.iter()
+        .map(|extraction| {
+            if let Some(pattern) = &extraction.regex {
+                return capture(body, pattern).map(|value| (extraction.variable.clone(), value));
+            }
+            let json = match &mut json {
+                Some(json) => json,
+                json => json.insert(serde_json::from_str(body).map_err(|e| format!("invalid JSON to extract from: {}", e))?),
+            };
+            match lookup(json, &extraction.field) {
+                Some(Value::String(value)) => Ok((extraction.variable.clone(), value.clone())),
+                Some(value) if !value.is_null() => Ok((extraction.variable.clone(), value.to_string())),
+                _ => Err(format!("JSON missing the field '{}' to extract", extraction.field)),
+            }
+        })
+        .collect()
+}
+
+/// The first capture group of `pattern`'s first match in `body`, or the whole match without a group.
+fn capture(body: &str, pattern: &str) -> Result<String, String> {
+    let regex = Regex::new(pattern).map_err(|e| format!("invalid extraction regex '{}': {}", pattern, e))?;
+    let captures = regex.captures(body).ok_or_else(|| format!("response did not match the extraction regex '{}'", pattern))?;
+    Ok(captures.get(1).or_else(|| captures.get(0)).map(|value| value.as_str().to_string()).unwrap_or_default())
+}
+
+/// Follows a dotted path through objects and, by index, arrays; a leading `$.` as in JSONPath is allowed.
+pub fn lookup<'a>(json: &'a Value, path: &str) -> Option<&'a Value> {
+    let path = path.strip_prefix("$.").unwrap_or(path);
+    path.split('.').try_fold(json, |value, segment| match value {
+        Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+        _ => value.get(segment),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extraction(field: &str, variable: &str) -> Extraction {
+        Extraction { field: field.to_string(), regex: None, variable: variable.to_string() }
+    }
+
+    #[test]
+    fn test_values_are_extracted_by_path() {
+        let body = r#"{"token": "abc123", "data": {"user": {"id": 7}, "roles": ["admin", "ops"]}}"#;
+        let extracted = extract_variables(body, &[
+            extraction("token", "token"),
+            extraction("$.data.user.id", "user_id"),
+            extraction("data.roles.1", "role"),
+        ]).unwrap();
+        assert_eq!(extracted, vec![
+            ("token".to_string(), "abc123".to_string()),
+            ("user_id".to_string(), "7".to_string()),
+            ("role".to_string(), "ops".to_string()),
+        ]);
+
+        assert!(extract_variables(body, &[extraction("data.missing", "x")]).unwrap_err().contains("data.missing"));
+        assert!(extract_variables("<html>", &[extraction("token", "token")]).is_err());
+    }
+
+    #[test]
+    fn test_values_are_extracted_by_regex() {
+        let regex = |pattern: &str, variable: &str| Extraction { regex: Some(pattern.to_string()), ..extraction("", variable) };
+        let body = r#"<input name="csrf" value="f00d"> session=abc123;"#;
+        let extracted = extract_variables(body, &[regex(r#"value="(\w+)""#, "csrf"), regex(r"session=\w+", "cookie")]).unwrap();
+        assert_eq!(extracted, vec![
+            ("csrf".to_string(), "f00d".to_string()),
+            ("cookie".to_string(), "session=abc123".to_string()),
+        ]);
+
+        assert!(extract_variables(body, &[regex("token=(\\w+)", "token")]).unwrap_err().contains("did not match"));
+    }
+}
@@ -0,0 +1,162 @@
+use reqwest::header::HeaderMap;
+
+use serde_json::Value;
+
+use crate::config::FailureRule;
+use crate::utils::extract::lookup;
+
+impl FailureRule {
+    /// Returns true when every condition configured on the rule holds for the response.
+    ///
+    /// A rule without any conditions never matches.
+    pub fn matches(&self, status_code: u16, headers: &HeaderMap, body: &str) -> bool {
+        let mut has_condition = false;
+
+        if let Some(status_codes) = &self.status_codes {
+            has_condition = true;
+            if !status_codes.contains(&status_code) {
+                return false;
+            }
+        }
+
+        if let Some(needle) = &self.body_contains {
+            has_condition = true;
+            if !body.contains(needle.as_str()) {
+                return false;
+            }
+        }
+
+        if let Some(header_name) = &self.header {
+            has_condition = true;
+            let header_value = headers.get(header_name.as_str()).and_then(|value| value.to_str().ok());
+            match (header_value, &self.header_value) {
+                (None, _) => return false,
+                (Some(actual), Some(expected)) if actual != expected => return false,
+                _ => {}
+            }
+        }
+
+        has_condition
+    }
+}
+
+/// Returns the label of the first rule matching the response, if any.
+pub fn classify_failure<'a>(rules: &'a [FailureRule], status_code: u16, headers: &HeaderMap, body: &str) -> Option<&'a str> {
+    rules.iter()
+        .find(|rule| rule.matches(status_code, headers, body))
+        .map(|rule| rule.label.as_str())
+}
+
+/// Returns why `body` is not valid JSON, including where parsing failed, or `None` when it is.
+pub fn invalid_json(body: &str) -> Option<String> {
+    serde_json::from_str::<Value>(body)
+        .err()
+        .map(|e| e.to_string()) // serde_json's message ends with "at line L column C"
+}
+
+/// Returns why `body` does not contain the dotted path `field`, or holds something other than
+/// `expected_value` there, or `None` when the assertion holds.
+///
+/// Strings are compared as they are and other values as JSON text, as extractions take them.
+pub fn expected_field_error(body: &str, field: &str, expected_value: Option<&str>) -> Option<String> {
+    let json = match serde_json::from_str::<Value>(body) {
+        Ok(json) => json,
+        Err(e) => return Some(format!("invalid JSON: {}", e)),
+    };
+    match (lookup(&json, field), expected_value) {
+        (Some(_), None) => None,
+        (Some(actual), Some(expected)) if actual.as_str().map_or_else(|| actual.to_string(), str::to_string) == expected => None,
+        (Some(actual), Some(expected)) => Some(format!("JSON field '{}' is {} rather than the expected '{}'", field, actual, expected)),
+        (None, _) if json.is_object() || json.is_array() => Some(format!("JSON missing the expected field '{}'", field)),
+        (None, _) => Some(format!("JSON that is not an object, so it has no field '{}'", field)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn rule(label: &str) -> FailureRule {
+        FailureRule {
+            label: label.to_string(),
+            status_codes: None,
+            body_contains: None,
+            header: None,
+            header_value: None,
+        }
+    }
+
+    #[test]
+    fn test_status_rule() {
+        let rules = vec![FailureRule { status_codes: Some(vec![200, 204]), ..rule("unexpected_ok") }];
+
+        assert_eq!(classify_failure(&rules, 200, &HeaderMap::new(), ""), Some("unexpected_ok"));
+        assert_eq!(classify_failure(&rules, 500, &HeaderMap::new(), ""), None);
+    }
+
+    #[test]
+    fn test_body_rule() {
+        let rules = vec![FailureRule { body_contains: Some(r#""status":"error""#.to_string()), ..rule("app_error") }];
+
+        assert_eq!(classify_failure(&rules, 200, &HeaderMap::new(), r#"{"status":"error"}"#), Some("app_error"));
+        assert_eq!(classify_failure(&rules, 200, &HeaderMap::new(), r#"{"status":"ok"}"#), None);
+    }
+
+    #[test]
+    fn test_header_rule() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-degraded", HeaderValue::from_static("true"));
+
+        let presence = vec![FailureRule { header: Some("X-Degraded".to_string()), ..rule("degraded") }];
+        assert_eq!(classify_failure(&presence, 200, &headers, ""), Some("degraded"));
+        assert_eq!(classify_failure(&presence, 200, &HeaderMap::new(), ""), None);
+
+        let value = vec![FailureRule {
+            header: Some("x-degraded".to_string()),
+            header_value: Some("false".to_string()),
+            ..rule("degraded")
+        }];
+        assert_eq!(classify_failure(&value, 200, &headers, ""), None);
+    }
+
+    #[test]
+    fn test_conditions_are_combined() {
+        let rules = vec![
+            FailureRule { status_codes: Some(vec![200]), body_contains: Some("maintenance".to_string()), ..rule("maintenance") },
+            rule("empty"),
+        ];
+
+        assert_eq!(classify_failure(&rules, 200, &HeaderMap::new(), "down for maintenance"), Some("maintenance"));
+        assert_eq!(classify_failure(&rules, 503, &HeaderMap::new(), "down for maintenance"), None);
+        assert_eq!(classify_failure(&rules, 200, &HeaderMap::new(), "fine"), None);
+    }
+
+    #[test]
+    fn test_invalid_json_reports_location() {
+        assert_eq!(invalid_json(r#"{"ok": true}"#), None);
+        let error = invalid_json("{\n  \"a\": 1\n  \"b\": 2\n}").unwrap();
+        assert!(error.ends_with("at line 3 column 3"), "{}", error);
+        assert!(invalid_json("").is_some());
+    }
+
+    #[test]
+    fn test_missing_expected_field() {
+        assert_eq!(expected_field_error(r#"{"id": 1}"#, "id", None), None);
+        assert_eq!(expected_field_error(r#"{"data": {"id": 1}}"#, "id", None).unwrap(), "JSON missing the expected field 'id'");
+        assert_eq!(expected_field_error(r#"{"data": {"id": 1}}"#, "$.data.id", None), None);
+        assert_eq!(expected_field_error(r#"[{"id": 1}]"#, "0.id", None), None);
+        assert!(expected_field_error("42", "id", None).unwrap().contains("not an object"));
+        assert!(expected_field_error("<html></html>", "id", None).unwrap().starts_with("invalid JSON"));
+    }
+
+    #[test]
+    fn test_expected_value_is_compared() {
+        let body = r#"{"status": "ok", "data": {"count": 3, "ready": true}}"#;
+        assert_eq!(expected_field_error(body, "status", Some("ok")), None);
+        assert_eq!(expected_field_error(body, "data.count", Some("3")), None);
+        assert_eq!(expected_field_error(body, "data.ready", Some("true")), None);
+        assert_eq!(expected_field_error(body, "status", Some("degraded")).unwrap(), r#"JSON field 'status' is "ok" rather than the expected 'degraded'"#);
+        assert!(expected_field_error(body, "data.count", Some("4")).is_some());
+    }
+}
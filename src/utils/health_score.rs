@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+/// The score of one outcome: its configured weight, or 1.0 for a success and 0.0 otherwise.
+///
+/// A missing status code means no response arrived, which always scores 0.0.
+pub fn outcome_score(status_weights: Option<&HashMap<u16, f64>>, status_code: Option<u16>) -> f64 {
+    let Some(status_code) = status_code else {
+        return 0.0;
+    };
+    match status_weights.and_then(|weights| weights.get(&status_code)) {
+        Some(weight) => *weight,
+        None if (200..300).contains(&status_code) => 1.0,
+        None => 0.0,
+    }
+}
+
+/// The mean score over `(status_code, count)` outcomes, or `None` when there are none.
+pub fn health_score(status_weights: Option<&HashMap<u16, f64>>, outcomes: impl IntoIterator<Item = (Option<u16>, usize)>) -> Option<f64> {
+    let (total, count) = outcomes.into_iter().fold((0.0, 0), |(total, count), (status_code, n)| {
+        (total + outcome_score(status_weights, status_code) * n as f64, count + n)
+    });
+    (count > 0).then(|| total / count as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_scoring_without_weights() {
+        assert_eq!(health_score(None, [(Some(200), 3), (Some(500), 1)]), Some(0.75));
+        assert_eq!(health_score(None, [(None, 1), (Some(204), 1)]), Some(0.5));
+        assert_eq!(health_score(None, []), None);
+    }
+
+    #[test]
+    fn test_weights_override_binary_scores() {
+        let weights = HashMap::from([(429, 0.5), (200, 1.0), (500, 0.0)]);
+        assert_eq!(health_score(Some(&weights), [(Some(200), 2), (Some(429), 2)]), Some(0.75));
+        // Codes without a weight keep their binary score.
+        assert_eq!(outcome_score(Some(&weights), Some(201)), 1.0);
+        assert_eq!(outcome_score(Some(&weights), Some(503)), 0.0);
+        assert_eq!(outcome_score(Some(&weights), None), 0.0);
+    }
+}
@@ -1,12 +1,89 @@
-use reqwest::{Client, Error, header::HeaderMap, header::HeaderName, header::HeaderValue};
+use anyhow::Context;
+use hyper::client::connect::dns::Name;
+use reqwest::{Certificate, Client, Error, Identity, RequestBuilder, Response, dns::{Addrs, Resolve, Resolving}, header::HeaderMap, header::HeaderName, header::HeaderValue, redirect, tls};
+use serde::Serialize;
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::time::Duration;
+use std::fs;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::str::FromStr;
 
+use crate::utils::error_kind::is_broken_connection;
+
+/// Matches reqwest's default redirect limit.
+const MAX_REDIRECTS: usize = 10;
+
 pub struct HttpClientConfig {
     pub timeout_seconds: u64,
     pub proxy_url: Option<String>,
     pub default_headers: HashMap<String, String>,
+    /// Record each redirect hop followed while sending a request.
+    pub record_redirects: bool,
+    /// Keep cookies set by responses and send them with later requests made through this client.
+    ///
+    /// The cookie jar is shared by every clone of the client, so tasks running concurrently, such as
+    /// those with the same `task_order`, see each other's cookies in whatever order their responses arrive.
+    /// Only tasks in later `task_order` groups can rely on a cookie having been set.
+    pub cookie_store: bool,
+    /// Restricts connections to one address family: "v4", "v6", or "auto" for either.
+    pub ip_version: Option<String>,
+    /// Lowest TLS version to negotiate; `None` keeps reqwest's default.
+    pub min_tls_version: Option<tls::Version>,
+    /// Highest TLS version to negotiate; `None` keeps reqwest's default.
+    pub max_tls_version: Option<tls::Version>,
+    /// PEM client certificate for mutual TLS, which may also contain the private key.
+    pub client_cert_path: Option<String>,
+    /// PEM private key for `client_cert_path`, if not in the certificate file.
+    pub client_key_path: Option<String>,
+    /// PEM CA certificate trusted in addition to the system roots.
+    pub ca_cert_path: Option<String>,
+}
+
+/// A single followed redirect: the URL that answered and the redirect status it returned.
+#[derive(Debug, Clone, Serialize)]
+pub struct RedirectHop {
+    pub url: String,
+    pub status: u16,
+}
+
+tokio::task_local! {
+    // The most redirects the request currently being sent on this task may follow, if its API sets a limit.
+    static REDIRECT_LIMIT: usize;
+}
+
+tokio::task_local! {
+    // Hops recorded by the redirect policy for the request currently being sent on this task,
+    // with the time each redirect response arrived.
+    static REDIRECT_HOPS: RefCell<Vec<(RedirectHop, Instant)>>;
+}
+
+tokio::task_local! {
+    // Time spent resolving host names for the request currently being sent on this task.
+    static DNS_TIME: RefCell<Option<Duration>>;
+}
+
+/// Resolves host names as reqwest does by default, noting how long each lookup took.
+///
+/// Lookups only happen when a new connection is opened, and never for hosts given as IP addresses.
+struct TimingResolver;
+
+impl Resolve for TimingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let start = Instant::now();
+            let addrs = tokio::net::lookup_host(format!("{}:0", name.as_str())).await?;
+            let elapsed = start.elapsed();
+            // A connection opened in the background, after a pooled one was reused instead, is not timed.
+            let _ = DNS_TIME.try_with(|dns_time| {
+                let mut dns_time = dns_time.borrow_mut();
+                *dns_time = Some(dns_time.unwrap_or_default() + elapsed);
+            });
+            Ok(Box::new(addrs) as Addrs)
+        })
+    }
 }
 
 impl Default for HttpClientConfig {
@@ -15,15 +92,24 @@ impl Default for HttpClientConfig {
             timeout_seconds: 30, // Default timeout of 30 seconds
             proxy_url: None, // No proxy by default
             default_headers: HashMap::new(), // No default headers
+            record_redirects: false, // Redirects are followed without being recorded
+            cookie_store: false, // Cookies are neither kept nor sent
+            ip_version: None, // Connect over whichever address family resolves
+            min_tls_version: None, // Negotiate any TLS version the backend supports
+            max_tls_version: None,
+            client_cert_path: None, // No client certificate is presented
+            client_key_path: None,
+            ca_cert_path: None, // Only the system roots are trusted
         }
     }
 }
 
-pub fn get_client(config: Option<HttpClientConfig>) -> Result<Client, Error> {
+pub fn get_client(config: Option<HttpClientConfig>) -> anyhow::Result<Client> {
     let config = config.unwrap_or_default();
 
     let mut client_builder = Client::builder()
-        .timeout(Duration::from_secs(config.timeout_seconds));
+        .timeout(Duration::from_secs(config.timeout_seconds))
+        .dns_resolver(Arc::new(TimingResolver));
 
     // Configure proxy if specified
     if let Some(proxy_url) = config.proxy_url {
@@ -49,5 +135,282 @@ pub fn get_client(config: Option<HttpClientConfig>) -> Result<Client, Error> {
 
     client_builder = client_builder.default_headers(headers);
 
-    client_builder.build()
+    // Binding the local side to one family makes the connector only try remote addresses of that family.
+    match config.ip_version.as_deref() {
+        Some("v4") => client_builder = client_builder.local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        Some("v6") => client_builder = client_builder.local_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        None | Some("auto") => {}
+        Some(other) => anyhow::bail!("Invalid IP version '{}': expected v4, v6, or auto", other),
+    }
+
+    // The default native TLS backend cannot require TLS 1.3, so that case uses rustls, which supports 1.2 and 1.3 only.
+    // Capping at 1.3, the newest version, restricts nothing and needs no backend support.
+    if config.min_tls_version == Some(tls::Version::TLS_1_3) {
+        client_builder = client_builder.use_rustls_tls();
+    }
+    if let Some(min_tls_version) = config.min_tls_version {
+        client_builder = client_builder.min_tls_version(min_tls_version);
+    }
+    if let Some(max_tls_version) = config.max_tls_version.filter(|version| *version < tls::Version::TLS_1_3) {
+        client_builder = client_builder.max_tls_version(max_tls_version);
+    }
+
+    // rustls reads the certificate and key as one PEM bundle; the native TLS backend cannot load such an identity.
+    if let Some(client_cert_path) = &config.client_cert_path {
+        let mut pem = fs::read(client_cert_path).with_context(|| format!("Failed to read client certificate at {}", client_cert_path))?;
+        if let Some(client_key_path) = &config.client_key_path {
+            pem.push(b'\n');
+            pem.extend(fs::read(client_key_path).with_context(|| format!("Failed to read client key at {}", client_key_path))?);
+        }
+        let identity = Identity::from_pem(&pem).with_context(|| format!("Invalid client certificate or key in {}", client_cert_path))?;
+        client_builder = client_builder.use_rustls_tls().identity(identity);
+    }
+    if let Some(ca_cert_path) = &config.ca_cert_path {
+        let pem = fs::read(ca_cert_path).with_context(|| format!("Failed to read CA certificate at {}", ca_cert_path))?;
+        let certificate = Certificate::from_pem(&pem).with_context(|| format!("Invalid CA certificate in {}", ca_cert_path))?;
+        client_builder = client_builder.add_root_certificate(certificate);
+    }
+
+    if config.cookie_store {
+        client_builder = client_builder.cookie_store(true);
+    }
+
+    // Follow redirects up to the limit of the request being sent, noting every hop if asked to.
+    let record_redirects = config.record_redirects;
+    client_builder = client_builder.redirect(redirect::Policy::custom(move |attempt| {
+        let max_redirects = REDIRECT_LIMIT.try_with(|limit| *limit).unwrap_or(MAX_REDIRECTS);
+        if max_redirects == 0 {
+            return attempt.stop();
+        }
+        if attempt.previous().len() > max_redirects {
+            return attempt.error("too many redirects");
+        }
+        if let (true, Some(from)) = (record_redirects, attempt.previous().last()) {
+            let hop = RedirectHop { url: from.to_string(), status: attempt.status().as_u16() };
+            let _ = REDIRECT_HOPS.try_with(|hops| hops.borrow_mut().push((hop, Instant::now())));
+        }
+        attempt.follow()
+    }));
+
+    Ok(client_builder.build()?)
+}
+
+/// Runs `send` with redirects limited to `max_redirects` rather than the client's default of 10.
+///
+/// With a limit of 0, a redirect response is returned as the response. `None` keeps the default.
+pub async fn with_redirect_limit<F: Future>(max_redirects: Option<usize>, send: F) -> F::Output {
+    match max_redirects {
+        Some(max_redirects) => REDIRECT_LIMIT.scope(max_redirects, send).await,
+        None => send.await,
+    }
+}
+
+/// Runs `send` and returns how long it spent resolving host names, summed over every lookup.
+///
+/// `None` means no lookup was made, because the request reused a pooled connection or its host is an IP address.
+pub async fn with_dns_timing<F: Future>(send: F) -> (F::Output, Option<Duration>) {
+    DNS_TIME.scope(RefCell::new(None), async move {
+        let output = send.await;
+        (output, DNS_TIME.with(|dns_time| dns_time.take()))
+    }).await
+}
+
+/// Sends a request, resending it once if it broke on a stale connection and its method is idempotent.
+///
+/// A POST is never resent, since the server may have acted on it before the connection broke.
+/// Returns the response and whether the request was resent.
+pub async fn send_retrying_broken_connection(request_builder: RequestBuilder, retry_broken_connection: bool) -> (Result<Response, Error>, bool) {
+    let (client, request) = request_builder.build_split();
+    let request = match request {
+        Ok(request) => request,
+        Err(e) => return (Err(e), false),
+    };
+    // Streaming bodies cannot be cloned, so those requests are never resent.
+    let retry = (retry_broken_connection && request.method().is_idempotent()).then(|| request.try_clone()).flatten();
+    match (client.execute(request).await, retry) {
+        (Err(e), Some(retry)) if is_broken_connection(&e) => {
+            log::debug!("Resending {} {} after its connection broke: {}", retry.method(), retry.url(), e);
+            (client.execute(retry).await, true)
+        },
+        (response, _) => (response, false),
+    }
+}
+
+/// Sends a request and returns the redirect hops recorded while it was followed, each with
+/// the time its redirect response arrived.
+///
+/// Hops are only recorded when the client was built with `record_redirects`; otherwise
+/// the returned list is always empty. Also returns whether the request was resent after its
+/// connection broke, as `send_retrying_broken_connection` does.
+pub async fn send_recording_redirects(request_builder: RequestBuilder, retry_broken_connection: bool) -> (Result<Response, Error>, Vec<(RedirectHop, Instant)>, bool) {
+    REDIRECT_HOPS.scope(RefCell::new(Vec::new()), async move {
+        let (response, resent) = send_retrying_broken_connection(request_builder, retry_broken_connection).await;
+        let hops = REDIRECT_HOPS.with(|hops| hops.take());
+        (response, hops, resent)
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(ip_version: &str) -> Client {
+        get_client(Some(HttpClientConfig { ip_version: Some(ip_version.to_string()), ..Default::default() }))
+            .expect("client should build")
+    }
+
+    #[tokio::test]
+    async fn test_ip_version_restricts_the_address_family() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let _ = socket.write_all(b"HTTP/1.1 204 No Content\r\nconnection: close\r\n\r\n").await;
+            }
+        });
+
+        for ip_version in ["auto", "v4"] {
+            let resp = client(ip_version).get(&url).send().await.unwrap();
+            assert!(resp.remote_addr().unwrap().is_ipv4());
+        }
+        // An IPv4-only target cannot be reached over IPv6.
+        assert!(client("v6").get(&url).send().await.is_err());
+
+        let invalid = HttpClientConfig { ip_version: Some("v5".to_string()), ..Default::default() };
+        assert!(get_client(Some(invalid)).unwrap_err().to_string().contains("Invalid IP version 'v5'"));
+    }
+
+    #[tokio::test]
+    async fn test_only_idempotent_requests_are_resent_after_a_broken_connection() {
+        use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Only the second connection gets a response; the rest are dropped, as a server does to an idle keep-alive connection.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let _ = socket.read(&mut request).await;
+                if counter.fetch_add(1, Ordering::SeqCst) == 1 {
+                    let _ = socket.write_all(b"HTTP/1.1 204 No Content\r\nconnection: close\r\n\r\n").await;
+                }
+            }
+        });
+        let client = Client::new();
+
+        let (response, resent) = send_retrying_broken_connection(client.get(&url), true).await;
+        assert_eq!(response.unwrap().status().as_u16(), 204);
+        assert!(resent);
+
+        let (response, resent) = send_retrying_broken_connection(client.post(&url).body("{}"), true).await;
+        assert!(response.is_err_and(|e| is_broken_connection(&e)));
+        assert!(!resent);
+
+        let (response, resent) = send_retrying_broken_connection(client.delete(&url), false).await;
+        assert!(response.is_err() && !resent);
+        assert_eq!(connections.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_any_tls_version_range_builds() {
+        for (min_tls_version, max_tls_version) in [
+            (Some(tls::Version::TLS_1_3), None),
+            (None, Some(tls::Version::TLS_1_2)),
+            (Some(tls::Version::TLS_1_2), Some(tls::Version::TLS_1_3)),
+        ] {
+            let config = HttpClientConfig { min_tls_version, max_tls_version, ..Default::default() };
+            assert!(get_client(Some(config)).is_ok(), "{:?}..{:?}", min_tls_version, max_tls_version);
+        }
+    }
+
+    #[test]
+    fn test_unusable_certificate_files_are_reported_by_path() {
+        let missing = HttpClientConfig { ca_cert_path: Some("/nonexistent/ca.pem".to_string()), ..Default::default() };
+        let error = format!("{:#}", get_client(Some(missing)).unwrap_err());
+        assert!(error.contains("Failed to read CA certificate at /nonexistent/ca.pem"), "{}", error);
+
+        let not_pem = std::env::temp_dir().join(format!("load_test_tool_not_pem_{}.pem", std::process::id()));
+        fs::write(&not_pem, "not a certificate").unwrap();
+        let invalid = HttpClientConfig { client_cert_path: Some(not_pem.to_string_lossy().into_owned()), ..Default::default() };
+        let error = format!("{:#}", get_client(Some(invalid)).unwrap_err());
+        let _ = fs::remove_file(&not_pem);
+        assert!(error.contains("Invalid client certificate or key in"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_cookie_store_sends_cookies_back() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Sets a session cookie, and echoes back whatever Cookie header the request carried.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let read = socket.read(&mut request).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&request[..read]).to_lowercase();
+                let cookie = request.lines().find_map(|line| line.strip_prefix("cookie: ")).unwrap_or_default().to_string();
+                let response = format!("HTTP/1.1 200 OK\r\nset-cookie: session=abc\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}", cookie.len(), cookie);
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        for (cookie_store, expected) in [(false, ""), (true, "session=abc")] {
+            let client = get_client(Some(HttpClientConfig { cookie_store, ..Default::default() })).unwrap();
+            client.get(&url).send().await.unwrap();
+            assert_eq!(client.get(&url).send().await.unwrap().text().await.unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dns_time_is_only_recorded_for_lookups() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let _ = socket.write_all(b"HTTP/1.1 204 No Content\r\nconnection: close\r\n\r\n").await;
+            }
+        });
+        let client = client("v4");
+
+        let (response, dns_time) = with_dns_timing(client.get(format!("http://localhost:{}/", port)).send()).await;
+        assert_eq!(response.unwrap().status().as_u16(), 204);
+        assert!(dns_time.is_some());
+
+        let (response, dns_time) = with_dns_timing(client.get(format!("http://127.0.0.1:{}/", port)).send()).await;
+        assert_eq!(response.unwrap().status().as_u16(), 204);
+        assert!(dns_time.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_redirect_limit_applies_to_one_request() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // /a redirects to /b, which redirects to /c.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/a", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let read = socket.read(&mut request).await.unwrap_or_default();
+                let response = match &request[..read] {
+                    request if request.starts_with(b"GET /a ") => "HTTP/1.1 302 Found\r\nlocation: /b\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    request if request.starts_with(b"GET /b ") => "HTTP/1.1 302 Found\r\nlocation: /c\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    _ => "HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        let client = get_client(None).unwrap();
+
+        assert_eq!(with_redirect_limit(None, client.get(&url).send()).await.unwrap().status().as_u16(), 200);
+        assert_eq!(with_redirect_limit(Some(0), client.get(&url).send()).await.unwrap().status().as_u16(), 302);
+        assert!(with_redirect_limit(Some(1), client.get(&url).send()).await.unwrap_err().is_redirect());
+        assert_eq!(client.get(&url).send().await.unwrap().status().as_u16(), 200);
+    }
 }
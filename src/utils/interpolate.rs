@@ -1,43 +1,153 @@
 use regex::{Regex, Captures};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
 use lazy_static::lazy_static;
 
-use crate::config::Workflow;
+use crate::config::{ApiConfig, AuthConfig, Workflow};
+use crate::utils::data_feeder::DATA_PREFIX;
 
 lazy_static! {
-    static ref ENV_VAR_REGEX: Regex = Regex::new(r"\$\{([^}]+)\}").unwrap();
+    // `${VAR}`, or `${VAR:-default}` with a fallback.
+    static ref ENV_VAR_REGEX: Regex = Regex::new(r"\$\{([^}:]+)(?::-([^}]*))?\}").unwrap();
 }
 
+/// Marks a reference to an extracted variable, as in `${vars.token}`, so it never reads the environment.
+const VARS_PREFIX: &str = "vars.";
+
+/// Variables the tool provides itself, given a fresh value for every request.
+const BUILTIN_VARIABLES: [&str; 1] = ["__now__"];
+
+fn builtin_variable(name: &str) -> Option<String> {
+    match name {
+        // The current Unix timestamp in seconds, e.g. to make each payload unique.
+        "__now__" => Some(SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or_default().to_string()),
+        _ => None,
+    }
+}
+
+/// Replaces `${VAR}` with the value of the environment variable `VAR`.
+///
+/// As in the shell, `${VAR:-default}` gives `default` when `VAR` is unset or empty. An unset variable
+/// without a default is left in place, with a warning.
 pub fn interpolate_string(input: &str) -> String {
+    interpolate_env(input, &HashSet::new())
+}
+
+/// Like `interpolate_string`, but leaves variables named in `runtime_variables` for `resolve_variables`.
+///
+/// Built-in variables such as `${__now__}`, extracted ones written as `${vars.name}`, and data file
+/// columns written as `${data.column}` are always left for `resolve_variables`.
+pub fn interpolate_env(input: &str, runtime_variables: &HashSet<String>) -> String {
     ENV_VAR_REGEX.replace_all(input, |caps: &Captures| {
-        match env::var(&caps[1]) {
-            Ok(val) => val,
-            Err(_) => {
-                log::warn!("Environment variable {} not found; using default.", &caps[1]);
+        if runtime_variables.contains(&caps[1]) || BUILTIN_VARIABLES.contains(&&caps[1]) || caps[1].starts_with(VARS_PREFIX) || caps[1].starts_with(DATA_PREFIX) {
+            return caps[0].to_string();
+        }
+        let default = caps.get(2);
+        match (env::var(&caps[1]).ok().filter(|val| !val.is_empty() || default.is_none()), default) {
+            (Some(val), _) => val,
+            (None, Some(default)) => default.as_str().to_string(),
+            (None, None) => {
+                log::warn!("Environment variable {} not found; leaving it uninterpolated.", &caps[1]);
                 caps[0].to_string()
             }
         }
     }).to_string()
 }
 
+/// Replaces `${name}` or `${vars.name}` with a variable extracted from an earlier response, or a
+/// built-in such as `${__now__}`, the current Unix timestamp.
+///
+/// `${name:-default}` gives `default` until `name` has been extracted; other unknown names are left in place.
+pub fn resolve_variables<'a>(input: &'a str, variables: &HashMap<String, String>) -> Cow<'a, str> {
+    ENV_VAR_REGEX.replace_all(input, |caps: &Captures| {
+        let name = caps[1].strip_prefix(VARS_PREFIX).unwrap_or(&caps[1]);
+        match (variables.get(name).cloned().or_else(|| builtin_variable(name)), caps.get(2)) {
+            (Some(value), _) => value,
+            (None, Some(default)) => default.as_str().to_string(),
+            (None, None) => caps[0].to_string(),
+        }
+    })
+}
+
+/// Fills in a template read at request time, such as a `body_file`: environment variables first, then
+/// `resolve_variables`. Names in `variables` are left for the second step, so an extracted value is
+/// never mistaken for an environment variable, nor an environment value for a variable reference.
+pub fn interpolate_template(template: &str, variables: &HashMap<String, String>) -> String {
+    let runtime_variables: HashSet<String> = variables.keys().cloned().collect();
+    resolve_variables(&interpolate_env(template, &runtime_variables), variables).into_owned()
+}
+
+/// The names of the variables a workflow extracts from responses, which are only known at request time.
+pub fn runtime_variables(workflow: &Workflow) -> HashSet<String> {
+    workflow.apis.iter().chain(workflow.teardown.iter().flatten())
+        .flat_map(|api| api.extract.iter().flatten())
+        .map(|extraction| extraction.variable.clone())
+        .collect()
+}
+
+/// The `${...}` placeholders in `texts` that nothing will fill in: not environment variables, which have
+/// been interpolated already, nor one of `runtime_variables`, a built-in, or a data file column.
+///
+/// Placeholders with a `:-default` always resolve, so they are never reported.
+pub fn unresolved_placeholders<'a>(texts: impl IntoIterator<Item = &'a str>, runtime_variables: &HashSet<String>) -> BTreeSet<String> {
+    texts.into_iter()
+        .flat_map(|text| ENV_VAR_REGEX.captures_iter(text))
+        .filter(|caps| caps.get(2).is_none())
+        .map(|caps| caps[1].to_string())
+        .filter(|name| {
+            let name = name.strip_prefix(VARS_PREFIX).unwrap_or(name);
+            !runtime_variables.contains(name) && !BUILTIN_VARIABLES.contains(&name) && !name.starts_with(DATA_PREFIX)
+        })
+        .map(|name| format!("${{{}}}", name))
+        .collect()
+}
+
 pub fn interpolate_config(workflow: &mut Workflow) {
+    // Variables extracted from responses are resolved per request, even if the environment has one of the same name.
+    let runtime_variables = runtime_variables(workflow);
 
-    for api in workflow.apis.iter_mut() {
-        api.url = interpolate_string(&api.url);
-        if let Some(body) = &mut api.body {
-            *body = interpolate_string(body);
-        }
-        for header_value in api.headers.values_mut() {
-            *header_value = interpolate_string(header_value);
+    for api in workflow.apis.iter_mut().chain(workflow.teardown.iter_mut().flatten()) {
+        interpolate_api(api, &runtime_variables);
+    }
+}
+
+pub fn interpolate_api(api: &mut ApiConfig, runtime_variables: &HashSet<String>) {
+    api.url = interpolate_env(&api.url, runtime_variables);
+    if let Some(body) = &mut api.body {
+        *body = interpolate_env(body, runtime_variables);
+    }
+    if let Some(body_file) = &mut api.body_file {
+        *body_file = interpolate_env(body_file, runtime_variables);
+    }
+    if let Some(urls_file) = &mut api.urls_file {
+        *urls_file = interpolate_env(urls_file, runtime_variables);
+    }
+    api.expected_field = interpolate_env(&api.expected_field, runtime_variables);
+    for header_value in api.headers.values_mut() {
+        *header_value = interpolate_env(header_value, runtime_variables);
+    }
+    match &mut api.auth {
+        Some(AuthConfig::Basic { username, password }) => {
+            *username = interpolate_env(username, runtime_variables);
+            *password = interpolate_env(password, runtime_variables);
         }
-        // Note: This implementation does not interpolate 'name', 'method', or 'expected_field' as
-        // they are less likely to contain environment variables, but you can add them if needed.
+        Some(AuthConfig::Bearer { token }) => *token = interpolate_env(token, runtime_variables),
+        None => {}
+    }
+    // Batch sub-requests are full API configurations and are interpolated the same way.
+    for sub_api in api.batch.iter_mut().flatten() {
+        interpolate_api(sub_api, runtime_variables);
     }
+    // Note: This implementation does not interpolate 'name' or 'method' as they are less
+    // likely to contain environment variables, but you can add them if needed.
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::Extraction;
     use serde_yaml;
 
     // Adjusted to include a token placeholder in the `http_default_headers`
@@ -72,5 +182,80 @@ apis:
         // Clean up environment variables
         env::remove_var("API_URL");
     }
-}
 
+    #[test]
+    fn test_interpolation_defaults() {
+        env::set_var("INTERPOLATE_TEST_HOST", "api.internal");
+        env::remove_var("INTERPOLATE_TEST_UNSET");
+
+        assert_eq!(interpolate_string("http://${INTERPOLATE_TEST_HOST:-localhost}/"), "http://api.internal/");
+        assert_eq!(interpolate_string("http://${INTERPOLATE_TEST_UNSET:-localhost:8080}/"), "http://localhost:8080/");
+        assert_eq!(interpolate_string("Bearer ${INTERPOLATE_TEST_UNSET:-}"), "Bearer ");
+        assert_eq!(interpolate_string("http://${INTERPOLATE_TEST_UNSET}/"), "http://${INTERPOLATE_TEST_UNSET}/");
+
+        env::remove_var("INTERPOLATE_TEST_HOST");
+    }
+
+    #[test]
+    fn test_interpolation_for_body_file_and_expected_field() {
+        env::set_var("INTERPOLATE_TEST_ENV", "staging");
+
+        let mut workflow = load_test_settings();
+        workflow.apis[0].body_file = Some("${INTERPOLATE_TEST_ENV}/create_user.json".to_string());
+        workflow.apis[0].expected_field = "${INTERPOLATE_TEST_FIELD:-id}".to_string();
+        interpolate_config(&mut workflow);
+
+        assert_eq!(workflow.apis[0].body_file.as_deref(), Some("staging/create_user.json"));
+        assert_eq!(workflow.apis[0].expected_field, "id");
+
+        env::remove_var("INTERPOLATE_TEST_ENV");
+    }
+
+    #[test]
+    fn test_extracted_variables_are_resolved_per_request() {
+        env::set_var("INTERPOLATE_TEST_TOKEN", "from-env");
+
+        let mut workflow = load_test_settings();
+        workflow.apis[0].extract = Some(vec![Extraction { field: "token".to_string(), regex: None, variable: "INTERPOLATE_TEST_TOKEN".to_string() }]);
+        workflow.apis[0].headers.insert("Authorization".to_string(), "Bearer ${INTERPOLATE_TEST_TOKEN}".to_string());
+        interpolate_config(&mut workflow);
+        let header = &workflow.apis[0].headers["Authorization"];
+        assert_eq!(header, "Bearer ${INTERPOLATE_TEST_TOKEN}");
+
+        let variables = HashMap::from([("INTERPOLATE_TEST_TOKEN".to_string(), "abc123".to_string())]);
+        assert_eq!(resolve_variables(header, &variables), "Bearer abc123");
+        assert_eq!(resolve_variables("${user:-guest}/${missing}", &variables), "guest/${missing}");
+        assert_eq!(interpolate_string("Bearer ${vars.INTERPOLATE_TEST_TOKEN}"), "Bearer ${vars.INTERPOLATE_TEST_TOKEN}");
+        assert_eq!(resolve_variables("Bearer ${vars.INTERPOLATE_TEST_TOKEN}", &variables), "Bearer abc123");
+
+        env::remove_var("INTERPOLATE_TEST_TOKEN");
+    }
+
+    #[test]
+    fn test_templates_resolve_the_environment_before_extracted_variables() {
+        env::set_var("INTERPOLATE_TEST_TEMPLATE_TOKEN", "from-env");
+        env::set_var("INTERPOLATE_TEST_TEMPLATE_HOST", "api.internal");
+        // An extracted value that itself looks like a placeholder must be sent as is.
+        let variables = HashMap::from([
+            ("INTERPOLATE_TEST_TEMPLATE_TOKEN".to_string(), "extracted".to_string()),
+            ("note".to_string(), "${INTERPOLATE_TEST_TEMPLATE_HOST}".to_string()),
+        ]);
+
+        assert_eq!(
+            interpolate_template("${INTERPOLATE_TEST_TEMPLATE_HOST} ${INTERPOLATE_TEST_TEMPLATE_TOKEN} ${note}", &variables),
+            "api.internal extracted ${INTERPOLATE_TEST_TEMPLATE_HOST}"
+        );
+
+        env::remove_var("INTERPOLATE_TEST_TEMPLATE_TOKEN");
+        env::remove_var("INTERPOLATE_TEST_TEMPLATE_HOST");
+    }
+
+    #[test]
+    fn test_now_is_left_for_each_request() {
+        assert_eq!(interpolate_string(r#"{"id": "${__now__}"}"#), r#"{"id": "${__now__}"}"#);
+
+        let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let now: u64 = resolve_variables("${__now__}", &HashMap::new()).parse().unwrap();
+        assert!((before..=before + 1).contains(&now), "{}", now);
+    }
+}
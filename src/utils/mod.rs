@@ -1,2 +1,27 @@
+pub mod compression;
+pub mod data_feeder;
+pub mod error_kind;
+pub mod error_log;
+pub mod error_messages;
+pub mod extract;
+pub mod failure_rules;
+pub mod health_score;
 pub mod http_client;
-pub mod interpolate;
\ No newline at end of file
+pub mod interpolate;
+pub mod ndjson;
+pub mod output;
+pub mod prometheus;
+pub mod random_body;
+pub mod request_history;
+pub mod request_limit;
+pub mod request_metrics;
+pub mod response_times;
+pub mod results_file;
+pub mod script;
+pub mod server_timing;
+pub mod slowest;
+pub mod snippet;
+pub mod timeline;
+pub mod trace;
+pub mod weighted;
+pub mod websocket;
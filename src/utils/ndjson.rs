@@ -0,0 +1,99 @@
+use serde::Serialize;
+use std::io::Write;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+use crate::config::HttpMethod;
+
+/// Number of records buffered for the writer before the oldest are dropped.
+const STREAM_CAPACITY: usize = 4096;
+
+static STREAM: OnceLock<broadcast::Sender<NdjsonRecord>> = OnceLock::new();
+
+/// One line of the `--stream-ndjson` output, written when a request completes.
+///
+/// Each line is a JSON object with these fields:
+/// - `timestamp_ms`: completion time as milliseconds since the Unix epoch.
+/// - `url`: the URL the request was sent to.
+/// - `method`: the HTTP method, e.g. `"GET"`.
+/// - `status`: `"OK"` or `"ERROR"`.
+/// - `status_code`: the HTTP status code, or `null` if no response was received.
+/// - `latency_ms`: the request's response time in milliseconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct NdjsonRecord {
+    pub timestamp_ms: u64,
+    pub url: String,
+    pub method: HttpMethod,
+    pub status: String,
+    pub status_code: Option<u16>,
+    pub latency_ms: u64,
+}
+
+impl NdjsonRecord {
+    /// Builds a record stamped with the current time.
+    pub fn now(url: &str, method: &HttpMethod, status: &str, status_code: Option<u16>, latency_ms: u64) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+
+        NdjsonRecord {
+            timestamp_ms,
+            url: url.to_string(),
+            method: method.clone(),
+            status: status.to_string(),
+            status_code,
+            latency_ms,
+        }
+    }
+}
+
+/// Starts the writer that prints emitted records to stdout, one JSON object per line.
+///
+/// The writer runs on its own thread so a slow consumer of stdout never blocks the request
+/// path; if it falls behind, the oldest buffered records are dropped.
+pub fn start_stream() {
+    let (sender, mut receiver) = broadcast::channel(STREAM_CAPACITY);
+    if STREAM.set(sender).is_err() {
+        return; // Already streaming
+    }
+
+    std::thread::spawn(move || {
+        let stdout = std::io::stdout();
+        loop {
+            match receiver.blocking_recv() {
+                Ok(record) => {
+                    let mut out = stdout.lock();
+                    if let Ok(line) = serde_json::to_string(&record) {
+                        if writeln!(out, "{}", line).and_then(|_| out.flush()).is_err() {
+                            break; // stdout was closed
+                        }
+                    }
+                },
+                Err(broadcast::error::RecvError::Lagged(dropped)) => {
+                    log::warn!("NDJSON stream fell behind; dropped {} records", dropped);
+                },
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Emits a record to the NDJSON stream, if streaming was started. Never blocks.
+pub fn emit(record: NdjsonRecord) {
+    if let Some(sender) = STREAM.get() {
+        let _ = sender.send(record);
+    }
+}
+
+/// Returns true when `start_stream` has been called.
+pub fn is_streaming() -> bool {
+    STREAM.get().is_some()
+}
+
+/// Turns streaming on without the stdout writer and receives every record emitted from then on.
+#[cfg(test)]
+pub fn subscribe() -> broadcast::Receiver<NdjsonRecord> {
+    STREAM.get_or_init(|| broadcast::channel(STREAM_CAPACITY).0).subscribe()
+}
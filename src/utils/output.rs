@@ -0,0 +1,50 @@
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static RUN_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Creates this run's output folder, `<base_dir>/run-<unix millis>`, and directs file outputs into it.
+///
+/// On failure the error is returned and file outputs stay disabled; the run itself is unaffected.
+pub fn init(base_dir: &str) -> std::io::Result<PathBuf> {
+    let started_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or_default();
+    let run_dir = Path::new(base_dir).join(format!("run-{}", started_ms));
+
+    fs::create_dir_all(&run_dir)?;
+    Ok(RUN_DIR.get_or_init(|| run_dir).clone())
+}
+
+/// The folder file outputs are written to, if one was created.
+pub fn run_dir() -> Option<&'static Path> {
+    RUN_DIR.get().map(PathBuf::as_path)
+}
+
+/// Places a relative output path inside the run's output folder, if there is one.
+pub fn resolve(path: &str) -> PathBuf {
+    match run_dir() {
+        Some(run_dir) if Path::new(path).is_relative() => run_dir.join(path),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Writes `value` as pretty-printed JSON to `file_name` in the run's output folder, if there is one.
+pub fn write_json<T: Serialize>(file_name: &str, value: &T) {
+    let Some(run_dir) = run_dir() else {
+        return;
+    };
+
+    let path = run_dir.join(file_name);
+    let written = serde_json::to_string_pretty(value)
+        .map_err(std::io::Error::other)
+        .and_then(|json| fs::write(&path, json));
+    match written {
+        Ok(()) => log::info!("Wrote {}", path.display()),
+        Err(e) => log::error!("Failed to write {}: {}", path.display(), e),
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::appstate::ResultsSnapshot;
+use crate::utils::request_metrics::{RequestMetrics, DURATION_BUCKETS};
+
+/// The content type of the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Collects samples by metric, so each metric's HELP and TYPE lines are written once above its samples.
+struct Exposition<'a> {
+    run_labels: Vec<(&'a str, &'a str)>,
+    metrics: BTreeMap<&'static str, Metric>,
+}
+
+struct Metric {
+    help: &'static str,
+    kind: &'static str,
+    samples: Vec<String>,
+}
+
+impl<'a> Exposition<'a> {
+    fn new(snapshot: &'a ResultsSnapshot) -> Self {
+        let mut run_labels: Vec<(&str, &str)> = snapshot.run_labels.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+        run_labels.sort();
+        Exposition { run_labels, metrics: BTreeMap::new() }
+    }
+
+    fn sample(&mut self, name: &'static str, help: &'static str, labels: &[(&str, &str)], value: f64) {
+        self.typed_sample(name, help, "gauge", name, labels, value);
+    }
+
+    /// Adds a sample to the metric `name` of type `kind`; histograms name their samples with a suffix.
+    fn typed_sample(&mut self, name: &'static str, help: &'static str, kind: &'static str, sample_name: &str, labels: &[(&str, &str)], value: f64) {
+        // Run labels are added to every sample, unless a sample label of the same name takes precedence.
+        let run_labels = self.run_labels.iter().filter(|(key, _)| labels.iter().all(|(name, _)| name != key));
+        let labels: Vec<String> = labels.iter().chain(run_labels)
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape_label_value(value)))
+            .collect();
+        let line = format!("{}{{{}}} {}", sample_name, labels.join(","), value);
+        self.metrics.entry(name).or_insert_with(|| Metric { help, kind, samples: Vec::new() }).samples.push(line);
+    }
+
+    fn render(mut self) -> String {
+        let mut output = String::new();
+        for (name, metric) in &mut self.metrics {
+            // Gauges come from hash maps, so they are sorted to keep the output stable; the rest already
+            // arrive in order, and sorting would scatter a histogram's buckets.
+            if metric.kind == "gauge" {
+                metric.samples.sort();
+            }
+            let _ = writeln!(output, "# HELP {} {}", name, metric.help);
+            let _ = writeln!(output, "# TYPE {} {}", name, metric.kind);
+            for sample in metric.samples.iter() {
+                let _ = writeln!(output, "{}", sample);
+            }
+        }
+        output
+    }
+}
+
+/// Renders the latest task and load test results in the Prometheus text exposition format.
+///
+/// The gauges hold the most recent result, labelled with its workflow, API, URL, and method, plus the
+/// run's labels. Status codes are labels, so e.g. 5xx responses can be alerted on. Alongside them, the
+/// `requests_total` and `errors_total` counters and the `request_duration_seconds` histogram cover every
+/// request sent since the process started, labelled by API name, method, and status code.
+pub fn render(snapshot: &ResultsSnapshot, request_metrics: &RequestMetrics) -> String {
+    let mut exposition = Exposition::new(snapshot);
+
+    for (key, series) in request_metrics.series() {
+        let status_code = key.status_code.map(|status_code| status_code.to_string()).unwrap_or_default();
+        let labels = [("api", key.api.as_str()), ("method", key.method), ("status_code", status_code.as_str())];
+        exposition.typed_sample("requests_total", "Requests sent since the tool started.", "counter", "requests_total", &labels, series.requests as f64);
+        exposition.typed_sample("errors_total", "Failed requests since the tool started.", "counter", "errors_total", &labels, series.errors as f64);
+
+        let help = "Duration of the requests sent since the tool started, in seconds.";
+        for (upper_bound, count) in DURATION_BUCKETS.iter().zip(series.bucket_counts) {
+            let upper_bound = upper_bound.to_string();
+            let bucket_labels = [&labels[..], &[("le", upper_bound.as_str())]].concat();
+            exposition.typed_sample("request_duration_seconds", help, "histogram", "request_duration_seconds_bucket", &bucket_labels, count as f64);
+        }
+        let bucket_labels = [&labels[..], &[("le", "+Inf")]].concat();
+        exposition.typed_sample("request_duration_seconds", help, "histogram", "request_duration_seconds_bucket", &bucket_labels, series.requests as f64);
+        exposition.typed_sample("request_duration_seconds", help, "histogram", "request_duration_seconds_sum", &labels, series.duration_sum_seconds);
+        exposition.typed_sample("request_duration_seconds", help, "histogram", "request_duration_seconds_count", &labels, series.requests as f64);
+    }
+
+    for (workflow, apis) in &snapshot.task_results {
+        for (api, data) in apis {
+            let labels = [("workflow", workflow.as_str()), ("api", api.as_str()), ("url", data.api_url.as_str()), ("method", data.method.as_str())];
+            exposition.sample("request_response_time_ms", "Response time of the latest task request, in milliseconds.", &labels, data.response_time as f64);
+
+            let status_code = data.status_code.map(|status_code| status_code.to_string()).unwrap_or_default();
+            let status_labels = [&labels[..], &[("status", data.status.as_str()), ("status_code", status_code.as_str())]].concat();
+            exposition.sample("request_status", "Outcome of the latest task request; always 1, with the outcome in its labels.", &status_labels, 1.0);
+        }
+    }
+
+    for (workflow, apis) in &snapshot.load_test_results {
+        for (api, data) in apis {
+            let labels = [("workflow", workflow.as_str()), ("api", api.as_str()), ("url", data.api_url.as_str()), ("method", data.method.as_str())];
+            exposition.sample("load_test_requests", "Requests sent by the latest load test.", &labels, data.total_requests as f64);
+            exposition.sample("load_test_failures", "Failed requests of the latest load test.", &labels, data.failure_count as f64);
+            exposition.sample("load_test_achieved_rps", "Completed requests per second of the latest load test.", &labels, data.achieved_rps);
+            exposition.sample("load_test_average_response_time_ms", "Mean response time of the latest load test, in milliseconds.", &labels, data.average_response_time_ms as f64);
+
+            let quantiles = [("0.5", Some(data.median_response_time_ms)), ("0.95", data.percentile_95th_response_time_ms), ("1", Some(data.max_response_time_ms))];
+            for (quantile, value) in quantiles {
+                if let Some(value) = value {
+                    let quantile_labels = [&labels[..], &[("quantile", quantile)]].concat();
+                    exposition.sample("load_test_response_time_ms", "Response time quantiles of the latest load test, in milliseconds.", &quantile_labels, value as f64);
+                }
+            }
+
+            for (status_code, count) in &data.status_code_distribution {
+                let status_code = status_code.to_string();
+                let status_labels = [&labels[..], &[("status_code", status_code.as_str())]].concat();
+                exposition.sample("load_test_responses", "Responses of the latest load test by status code.", &status_labels, *count as f64);
+            }
+        }
+    }
+
+    exposition.render()
+}
+
+/// Escapes a label value as the exposition format requires.
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::task_result;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    const URL: &str = "http://localhost/orders?q=\"a\"";
+
+    #[test]
+    fn test_tasks_render_with_status_code_labels() {
+        let snapshot = ResultsSnapshot {
+            run_labels: HashMap::from([("env".to_string(), "staging".to_string())]),
+            task_results: HashMap::from([("Shop".to_string(), HashMap::from([
+                ("Orders".to_string(), task_result(URL, "ERROR", Some(503))),
+                ("Cart".to_string(), task_result(URL, "ERROR", None)),
+            ]))]),
+            ..ResultsSnapshot::default()
+        };
+
+        let output = render(&snapshot, &RequestMetrics::default());
+        let labels = r#"workflow="Shop",api="Orders",url="http://localhost/orders?q=\"a\"",method="GET""#;
+        assert!(output.starts_with("# HELP request_response_time_ms "), "{}", output);
+        assert!(output.contains("# TYPE request_status gauge\n"));
+        assert!(output.contains(&format!("request_response_time_ms{{{},env=\"staging\"}} 42\n", labels)), "{}", output);
+        assert!(output.contains(&format!("request_status{{{},status=\"ERROR\",status_code=\"503\",env=\"staging\"}} 1\n", labels)), "{}", output);
+        assert!(output.contains(r#"api="Cart","#) && output.contains(r#"status_code="",env="staging"} 1"#));
+        assert_eq!(output.matches("# TYPE").count(), 2);
+    }
+
+    #[test]
+    fn test_request_metrics_render_as_counters_and_a_histogram() {
+        let snapshot = ResultsSnapshot::default();
+        let mut request_metrics = RequestMetrics::default();
+        request_metrics.record("Orders", "POST", Some(500), Duration::from_millis(25), true);
+        request_metrics.record("Orders", "POST", Some(500), Duration::from_millis(250), true);
+
+        let output = render(&snapshot, &request_metrics);
+        let labels = r#"api="Orders",method="POST",status_code="500""#;
+        assert!(output.contains("# TYPE requests_total counter\n"), "{}", output);
+        assert!(output.contains("# TYPE request_duration_seconds histogram\n"));
+        assert!(output.contains(&format!("requests_total{{{}}} 2\n", labels)), "{}", output);
+        assert!(output.contains(&format!("errors_total{{{}}} 2\n", labels)));
+        assert!(output.contains(&format!("request_duration_seconds_bucket{{{},le=\"0.025\"}} 1\nrequest_duration_seconds_bucket{{{},le=\"0.05\"}} 1\n", labels, labels)));
+        assert!(output.contains(&format!("request_duration_seconds_bucket{{{},le=\"+Inf\"}} 2\n", labels)));
+        assert!(output.contains(&format!("request_duration_seconds_sum{{{}}} 0.275\n", labels)), "{}", output);
+        assert!(output.contains(&format!("request_duration_seconds_count{{{}}} 2\n", labels)));
+    }
+}
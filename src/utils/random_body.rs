@@ -0,0 +1,119 @@
+use bytes::Bytes;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+use crate::config::ApiConfig;
+
+/// Printable characters random bodies are drawn from, so they are safe in any text protocol.
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+lazy_static! {
+    // Random data per (seed, maximum size), generated once and sliced for each request.
+    static ref BUFFERS: Mutex<HashMap<(u64, usize), Bytes>> = Mutex::new(HashMap::new());
+}
+
+/// A small, fast, seedable generator; quality is ample for filling payloads.
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A value in `min..=max`.
+    pub(crate) fn between(&mut self, min: usize, max: usize) -> usize {
+        min + (self.next_u64() % (max - min + 1) as u64) as usize
+    }
+}
+
+/// The size limits configured for an API's random body, or `None` when it has no random body.
+fn size_limits(api_config: &ApiConfig) -> Option<(usize, usize)> {
+    match (api_config.random_body_size_range, api_config.random_body_size_bytes) {
+        (Some([min, max]), _) => Some((min.min(max), min.max(max))),
+        (None, Some(size)) => Some((size, size)),
+        (None, None) => None,
+    }
+}
+
+/// The generator for one iteration, so each request's draws are reproducible on their own.
+pub(crate) fn iteration_rng(seed: u64, iteration: u64) -> SplitMix64 {
+    SplitMix64(seed ^ iteration.wrapping_mul(0xD1B5_4A32_D192_ED03))
+}
+
+/// The size of the random body sent in `iteration`, if the API has one.
+pub fn body_size(api_config: &ApiConfig, iteration: u64) -> Option<usize> {
+    let (min, max) = size_limits(api_config)?;
+    Some(iteration_rng(api_config.random_body_seed.unwrap_or_default(), iteration).between(min, max))
+}
+
+/// The random body sent in `iteration`, if the API has one.
+///
+/// Bodies are reproducible for a given seed and iteration. Each is a slice of a buffer shared
+/// by all requests with the same seed and maximum size, so large payloads are not re-allocated.
+pub fn random_body(api_config: &ApiConfig, iteration: u64) -> Option<Bytes> {
+    let (min, max) = size_limits(api_config)?;
+    let seed = api_config.random_body_seed.unwrap_or_default();
+    let mut rng = iteration_rng(seed, iteration);
+    let size = rng.between(min, max);
+    let offset = rng.between(0, max);
+
+    let buffer = BUFFERS.lock().unwrap_or_else(PoisonError::into_inner)
+        .entry((seed, max))
+        .or_insert_with(|| {
+            let mut fill = SplitMix64(seed);
+            (0..max * 2).map(|_| ALPHABET[(fill.next_u64() % ALPHABET.len() as u64) as usize]).collect::<Vec<u8>>().into()
+        })
+        .clone();
+    Some(buffer.slice(offset..offset + size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_config(size: Option<usize>, range: Option<[usize; 2]>) -> ApiConfig {
+        let mut api_config: ApiConfig = serde_yaml::from_str(r#"
+name: "Upload"
+url: "http://localhost/upload"
+method: POST
+headers: {}
+expected_field: ""
+response_time_threshold: 1000
+"#).unwrap();
+        api_config.random_body_size_bytes = size;
+        api_config.random_body_size_range = range;
+        api_config
+    }
+
+    #[test]
+    fn test_fixed_size_bodies_are_reproducible() {
+        let api_config = api_config(Some(64), None);
+        let first = random_body(&api_config, 7).unwrap();
+
+        assert_eq!(first.len(), 64);
+        assert_eq!(body_size(&api_config, 7), Some(64));
+        assert_eq!(first, random_body(&api_config, 7).unwrap());
+        assert_ne!(first, random_body(&api_config, 8).unwrap());
+        assert!(first.iter().all(|byte| ALPHABET.contains(byte)));
+    }
+
+    #[test]
+    fn test_range_sizes_stay_within_bounds() {
+        let api_config = api_config(None, Some([10, 20]));
+        for iteration in 0..100 {
+            let body = random_body(&api_config, iteration).unwrap();
+            assert!((10..=20).contains(&body.len()));
+            assert_eq!(body_size(&api_config, iteration), Some(body.len()));
+        }
+    }
+
+    #[test]
+    fn test_no_random_body_by_default() {
+        assert!(random_body(&api_config(None, None), 0).is_none());
+    }
+}
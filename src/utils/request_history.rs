@@ -0,0 +1,57 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One completed request, as plotted over the course of a run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistorySample {
+    /// Completion time as milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub response_time_ms: u64,
+    /// `None` when no response arrived.
+    pub status_code: Option<u16>,
+}
+
+impl HistorySample {
+    /// A sample for a request completing at `completed`.
+    pub fn at(completed: SystemTime, response_time_ms: u64, status_code: Option<u16>) -> Self {
+        let timestamp_ms = completed.duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_millis() as u64).unwrap_or_default();
+        HistorySample { timestamp_ms, response_time_ms, status_code }
+    }
+}
+
+/// The most recent requests to one URL, oldest first; once full, each new sample drops the oldest.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(transparent)]
+pub struct RequestHistory {
+    samples: VecDeque<HistorySample>,
+}
+
+impl RequestHistory {
+    /// Appends `sample`, keeping no more than `capacity` samples.
+    pub fn record(&mut self, sample: HistorySample, capacity: usize) {
+        while self.samples.len() >= capacity.max(1) {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_history_keeps_the_most_recent_samples() {
+        let mut history = RequestHistory::default();
+        for response_time_ms in 1..=5 {
+            history.record(HistorySample::at(UNIX_EPOCH + Duration::from_secs(response_time_ms), response_time_ms, Some(200)), 3);
+        }
+
+        let response_times: Vec<u64> = history.samples.iter().map(|sample| sample.response_time_ms).collect();
+        assert_eq!(response_times, vec![3, 4, 5]);
+        assert_eq!(history.samples[0].timestamp_ms, 3000);
+        assert_eq!(serde_json::to_string(&history.samples[0]).unwrap(), r#"{"timestamp_ms":3000,"response_time_ms":3,"status_code":200}"#);
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::OnceLock;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+static LIMIT: OnceLock<RequestLimit> = OnceLock::new();
+
+/// A bound on the number of requests in flight at once.
+struct RequestLimit(Semaphore);
+
+impl RequestLimit {
+    fn new(max_concurrent_requests: usize) -> Self {
+        RequestLimit(Semaphore::new(max_concurrent_requests))
+    }
+
+    async fn acquire(&self) -> SemaphorePermit<'_> {
+        self.0.acquire().await.expect("the request limit is never closed")
+    }
+}
+
+/// Caps the number of requests in flight across the whole run at `max_concurrent_requests`.
+pub fn start(max_concurrent_requests: usize) {
+    let _ = LIMIT.set(RequestLimit::new(max_concurrent_requests));
+}
+
+/// Waits until another request may be sent; it counts as in flight until the permit is dropped.
+///
+/// Returns `None` straight away when no limit is set.
+pub async fn acquire() -> Option<SemaphorePermit<'static>> {
+    match LIMIT.get() {
+        Some(limit) => Some(limit.acquire().await),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::join_all;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_requests_in_flight_never_exceed_the_limit() {
+        // Answers each request after a delay, noting the most requests it was handling at once.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let (in_flight, most_in_flight) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let (server_in_flight, server_most_in_flight) = (in_flight.clone(), most_in_flight.clone());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let (in_flight, most_in_flight) = (server_in_flight.clone(), server_most_in_flight.clone());
+                tokio::spawn(async move {
+                    let mut request = [0; 1024];
+                    let _ = socket.read(&mut request).await;
+                    most_in_flight.fetch_max(in_flight.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    let _ = socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").await;
+                });
+            }
+        });
+        let limit = RequestLimit::new(3);
+        let client = reqwest::Client::new();
+
+        let responses = join_all((0..10).map(|_| async {
+            let _permit = limit.acquire().await;
+            client.get(&url).send().await
+        })).await;
+
+        assert!(responses.iter().all(|response| response.as_ref().is_ok_and(|response| response.status().is_success())));
+        assert_eq!(most_in_flight.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Upper bounds of the request duration histogram buckets, in seconds.
+pub const DURATION_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// What requests are counted by: the API they went to, their method, and the status code they got.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SeriesKey {
+    pub api: String,
+    pub method: &'static str,
+    /// `None` when no response arrived.
+    pub status_code: Option<u16>,
+}
+
+/// Running totals for the requests of one `SeriesKey`, kept for the lifetime of the process.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestSeries {
+    pub requests: u64,
+    /// Requests that failed, whether or not a response arrived.
+    pub errors: u64,
+    /// How many requests took no longer than each of `DURATION_BUCKETS`; cumulative, as Prometheus expects.
+    pub bucket_counts: [u64; DURATION_BUCKETS.len()],
+    pub duration_sum_seconds: f64,
+}
+
+/// Every request sent by tasks and load tests, counted so they can be scraped as Prometheus counters.
+#[derive(Debug, Default)]
+pub struct RequestMetrics {
+    series: BTreeMap<SeriesKey, RequestSeries>,
+}
+
+impl RequestMetrics {
+    /// Counts one completed request.
+    pub fn record(&mut self, api: &str, method: &'static str, status_code: Option<u16>, duration: Duration, failed: bool) {
+        let key = SeriesKey { api: api.to_string(), method, status_code };
+        let series = self.series.entry(key).or_default();
+        let seconds = duration.as_secs_f64();
+        series.requests += 1;
+        series.errors += u64::from(failed);
+        series.duration_sum_seconds += seconds;
+        for (count, upper_bound) in series.bucket_counts.iter_mut().zip(DURATION_BUCKETS) {
+            if seconds <= upper_bound {
+                *count += 1;
+            }
+        }
+    }
+
+    /// Every series recorded so far, ordered by API, method, and status code.
+    pub fn series(&self) -> impl Iterator<Item = (&SeriesKey, &RequestSeries)> {
+        self.series.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_are_counted_per_status_code() {
+        let mut metrics = RequestMetrics::default();
+        metrics.record("Orders", "GET", Some(200), Duration::from_millis(20), false);
+        metrics.record("Orders", "GET", Some(200), Duration::from_millis(300), false);
+        metrics.record("Orders", "GET", None, Duration::from_secs(30), true);
+
+        let series: Vec<_> = metrics.series().collect();
+        assert_eq!(series.len(), 2);
+        let (key, ok) = series[1];
+        assert_eq!(key.status_code, Some(200));
+        assert_eq!((ok.requests, ok.errors), (2, 0));
+        assert_eq!(ok.bucket_counts, [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2]);
+        assert!((ok.duration_sum_seconds - 0.32).abs() < 1e-9);
+
+        let (key, failed) = series[0];
+        assert_eq!(key.status_code, None);
+        assert_eq!((failed.requests, failed.errors), (1, 1));
+        assert_eq!(failed.bucket_counts, [0; DURATION_BUCKETS.len()]);
+    }
+}
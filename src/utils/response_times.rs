@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Most response times kept per task; older ones are dropped so a long-running server stays bounded.
+const MAX_SAMPLES: usize = 10_000;
+
+/// The response times and status codes recorded for one task across runs, most recent last.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseTimeHistory {
+    samples: VecDeque<(u64, Option<u16>)>,
+}
+
+/// Statistics over a set of response times, in milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseTimeStats {
+    /// How many response times the statistics cover.
+    pub count: usize,
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+    /// Population standard deviation of the response times.
+    pub stddev: f64,
+    /// Percentiles are left out when there are too few samples for them to mean anything.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p50: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p90: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p95: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p99: Option<u64>,
+    /// Whether fewer than the configured minimum samples were recorded, so percentiles are omitted.
+    pub insufficient_samples: bool,
+}
+
+impl ResponseTimeHistory {
+    /// Records one call; `status_code` is `None` when no response arrived.
+    pub fn record(&mut self, response_time_ms: u64, status_code: Option<u16>) {
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((response_time_ms, status_code));
+    }
+
+    /// The status code of every recorded call.
+    pub fn status_codes(&self) -> impl Iterator<Item = Option<u16>> + '_ {
+        self.samples.iter().map(|(_, status_code)| *status_code)
+    }
+
+    /// Statistics over the recorded response times, or `None` before anything was recorded.
+    ///
+    /// Percentiles are only reported once at least `min_samples` response times were recorded.
+    pub fn stats(&self, min_samples: usize) -> Option<ResponseTimeStats> {
+        ResponseTimeStats::from_times(self.samples.iter().map(|(response_time_ms, _)| *response_time_ms).collect(), min_samples)
+    }
+}
+
+impl ResponseTimeStats {
+    /// Statistics over `response_times_ms`, or `None` when there are none.
+    ///
+    /// Percentiles are only reported when there are at least `min_samples` response times.
+    pub fn from_times(mut response_times_ms: Vec<u64>, min_samples: usize) -> Option<ResponseTimeStats> {
+        if response_times_ms.is_empty() {
+            return None;
+        }
+
+        response_times_ms.sort_unstable();
+        let sorted = &response_times_ms;
+        // Nearest-rank percentiles, as for load tests.
+        let insufficient_samples = sorted.len() < min_samples;
+        let percentile = |p: f64| (!insufficient_samples).then(|| sorted[((p * sorted.len() as f64).ceil() as usize).saturating_sub(1)]);
+        let mean = sorted.iter().sum::<u64>() as f64 / sorted.len() as f64;
+        let variance = sorted.iter().map(|time| (*time as f64 - mean).powi(2)).sum::<f64>() / sorted.len() as f64;
+
+        Some(ResponseTimeStats {
+            count: sorted.len(),
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            mean,
+            stddev: variance.sqrt(),
+            p50: percentile(0.50),
+            p90: percentile(0.90),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            insufficient_samples,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_fold_every_recorded_time() {
+        let mut history = ResponseTimeHistory::default();
+        assert_eq!(history.stats(0), None);
+
+        for response_time_ms in (1..=100).rev() {
+            history.record(response_time_ms, Some(200));
+        }
+        let stats = history.stats(0).unwrap();
+
+        assert_eq!(stats.count, 100);
+        assert_eq!((stats.min, stats.max), (1, 100));
+        assert_eq!(stats.mean, 50.5);
+        assert!((stats.stddev - 28.866).abs() < 0.001, "{}", stats.stddev);
+        assert_eq!((stats.p50, stats.p90, stats.p95, stats.p99), (Some(50), Some(90), Some(95), Some(99)));
+        assert!(!stats.insufficient_samples);
+    }
+
+    #[test]
+    fn test_percentiles_need_the_minimum_sample_count() {
+        let mut history = ResponseTimeHistory::default();
+        for response_time_ms in 1..=4 {
+            history.record(response_time_ms, Some(200));
+        }
+
+        let below = history.stats(5).unwrap();
+        assert!(below.insufficient_samples);
+        assert_eq!((below.p50, below.p90, below.p95, below.p99), (None, None, None, None));
+        assert_eq!((below.count, below.min, below.max), (4, 1, 4));
+
+        history.record(5, Some(200));
+        let above = history.stats(5).unwrap();
+        assert!(!above.insufficient_samples);
+        assert_eq!(above.p95, Some(5));
+    }
+
+    #[test]
+    fn test_oldest_times_are_dropped_at_the_limit() {
+        let mut history = ResponseTimeHistory::default();
+        history.record(1_000, None);
+        for _ in 0..MAX_SAMPLES {
+            history.record(5, Some(200));
+        }
+
+        let stats = history.stats(0).unwrap();
+        assert_eq!(stats.count, MAX_SAMPLES);
+        assert_eq!(stats.max, 5);
+        assert!(history.status_codes().all(|status_code| status_code == Some(200)));
+    }
+}
@@ -0,0 +1,105 @@
+use std::fs;
+use std::path::Path;
+
+use crate::appstate::ResultsSnapshot;
+
+/// The format a results file is written in, picked by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultsFormat {
+    /// The full results snapshot, pretty-printed.
+    Json,
+    /// One row per task and load test: url, method, status, response_time, status_code.
+    Csv,
+}
+
+impl ResultsFormat {
+    pub fn from_path(path: &str) -> Result<Self, String> {
+        match Path::new(path).extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => Ok(ResultsFormat::Json),
+            Some(extension) if extension.eq_ignore_ascii_case("csv") => Ok(ResultsFormat::Csv),
+            _ => Err(format!("Results file '{}' must end in .json or .csv", path)),
+        }
+    }
+}
+
+/// Writes the snapshot to `path` in the format its extension names; failures are logged, never fatal.
+pub fn write(path: &Path, snapshot: &ResultsSnapshot) {
+    let contents = match ResultsFormat::from_path(&path.to_string_lossy()) {
+        Ok(ResultsFormat::Json) => serde_json::to_string_pretty(snapshot).map_err(|e| e.to_string()),
+        Ok(ResultsFormat::Csv) => Ok(to_csv(snapshot)),
+        Err(e) => Err(e),
+    };
+    match contents.and_then(|contents| fs::write(path, contents).map_err(|e| e.to_string())) {
+        Ok(()) => log::info!("Wrote results to {}", path.display()),
+        Err(e) => log::error!("Failed to write results to {}: {}", path.display(), e),
+    }
+}
+
+/// Renders tasks and load tests as CSV rows, ordered by workflow and API name.
+///
+/// A load test's row has its average response time, and no status code since it made many requests;
+/// its status is "ERROR" when any of them failed.
+fn to_csv(snapshot: &ResultsSnapshot) -> String {
+    let mut rows: Vec<(&str, &str, [String; 5])> = Vec::new();
+    for (workflow, apis) in &snapshot.task_results {
+        for (api, data) in apis {
+            let status_code = data.status_code.map(|status_code| status_code.to_string()).unwrap_or_default();
+            rows.push((workflow, api, [data.api_url.clone(), data.method.as_str().to_string(), data.status.clone(), data.response_time.to_string(), status_code]));
+        }
+    }
+    for (workflow, apis) in &snapshot.load_test_results {
+        for (api, data) in apis {
+            let status = if data.failure_count == 0 { "OK" } else { "ERROR" };
+            rows.push((workflow, api, [data.api_url.clone(), data.method.as_str().to_string(), status.to_string(), data.average_response_time_ms.to_string(), String::new()]));
+        }
+    }
+    rows.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+
+    let mut csv = String::from("url,method,status,response_time,status_code\n");
+    for (_, _, fields) in rows {
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Quotes a field when it contains a delimiter, quote, or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::task_result;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_format_follows_the_extension() {
+        assert_eq!(ResultsFormat::from_path("out/results.JSON"), Ok(ResultsFormat::Json));
+        assert_eq!(ResultsFormat::from_path("results.csv"), Ok(ResultsFormat::Csv));
+        assert!(ResultsFormat::from_path("results.txt").is_err());
+        assert!(ResultsFormat::from_path("results").is_err());
+    }
+
+    #[test]
+    fn test_csv_has_one_row_per_task_in_order() {
+        let snapshot = ResultsSnapshot {
+            run_labels: HashMap::new(),
+            task_results: HashMap::from([("Shop".to_string(), HashMap::from([
+                ("Search".to_string(), task_result("http://localhost/search?q=a,b", "OK", Some(200))),
+                ("Cart".to_string(), task_result("http://localhost/cart", "ERROR", None)),
+            ]))]),
+            ..ResultsSnapshot::default()
+        };
+
+        assert_eq!(to_csv(&snapshot), "url,method,status,response_time,status_code\n\
+            http://localhost/cart,GET,ERROR,42,\n\
+            \"http://localhost/search?q=a,b\",GET,OK,42,200\n");
+    }
+}
@@ -0,0 +1,141 @@
+use lazy_static::lazy_static;
+use rhai::{Dynamic, Engine, Map, ParseError, Scope, AST};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::config::ApiConfig;
+
+lazy_static! {
+    static ref ENGINE: Engine = sandboxed_engine();
+    // Scripts are compiled once and reused, since load tests run them for every request.
+    static ref COMPILED: Mutex<HashMap<String, Arc<AST>>> = Mutex::new(HashMap::new());
+}
+
+/// The parts of a request a pre-request script may change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptedRequest {
+    pub url: String,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+/// Builds an engine that cannot touch the file system or run away with CPU or memory.
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(100_000)
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(1 << 20)
+        .set_max_array_size(10_000)
+        .set_max_map_size(10_000);
+    engine.on_print(|text| log::info!("[pre_request_script] {}", text));
+    engine.on_debug(|text, _, _| log::debug!("[pre_request_script] {}", text));
+    engine
+}
+
+/// Checks that a script parses, so syntax errors surface when the configuration is loaded.
+pub fn compile(script: &str) -> Result<(), ParseError> {
+    ENGINE.compile(script).map(|_| ())
+}
+
+fn compiled(script: &str) -> Result<Arc<AST>, String> {
+    let mut cache = COMPILED.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(ast) = cache.get(script) {
+        return Ok(ast.clone());
+    }
+
+    let ast = Arc::new(ENGINE.compile(script).map_err(|e| e.to_string())?);
+    cache.insert(script.to_string(), ast.clone());
+    Ok(ast)
+}
+
+/// Runs an API's pre-request script and returns the request as the script left it.
+///
+/// The script sees a `request` map (`url`, `method`, `headers`, `body`) it may modify, the
+/// process environment as a read-only `env` map, and the `iteration` number of the request,
+/// which counts up from zero within a load test.
+pub fn run_pre_request_script(script: &str, api_config: &ApiConfig, body: &str, iteration: u64) -> Result<ScriptedRequest, String> {
+    let fail = |reason: String| format!("Pre-request script for '{}' failed: {}", api_config.name, reason);
+    let ast = compiled(script).map_err(fail)?;
+
+    let headers: Map = api_config.headers.iter()
+        .map(|(key, value)| (key.as_str().into(), Dynamic::from(value.clone())))
+        .collect();
+    let mut request = Map::new();
+    request.insert("url".into(), Dynamic::from(api_config.url.clone()));
+    request.insert("method".into(), Dynamic::from(api_config.method.as_str().to_string()));
+    request.insert("headers".into(), Dynamic::from_map(headers));
+    request.insert("body".into(), Dynamic::from(body.to_string()));
+    let env: Map = std::env::vars()
+        .map(|(key, value)| (key.into(), Dynamic::from(value)))
+        .collect();
+
+    let mut scope = Scope::new();
+    scope.push("request", request);
+    scope.push_constant("env", env);
+    scope.push_constant("iteration", iteration as i64);
+
+    ENGINE.run_ast_with_scope(&mut scope, &ast).map_err(|e| fail(e.to_string()))?;
+
+    let request = scope.get_value::<Map>("request")
+        .ok_or_else(|| fail("`request` is no longer a map".to_string()))?;
+    let field = |name: &str| -> Result<String, String> {
+        request.get(name)
+            .map(|value| value.to_string())
+            .ok_or_else(|| fail(format!("`request.{}` was removed", name)))
+    };
+    let headers = request.get("headers")
+        .and_then(|headers| headers.read_lock::<Map>().map(|map| map.clone()))
+        .ok_or_else(|| fail("`request.headers` is no longer a map".to_string()))?
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+    Ok(ScriptedRequest { url: field("url")?, headers, body: field("body")? })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_config() -> ApiConfig {
+        serde_yaml::from_str(r#"
+name: "Signed"
+url: "http://localhost/orders"
+method: POST
+headers:
+  Content-Type: "application/json"
+expected_field: ""
+response_time_threshold: 1000
+"#).unwrap()
+    }
+
+    #[test]
+    fn test_script_can_change_url_headers_and_body() {
+        let script = r#"
+            request.url += "?page=" + iteration;
+            request.headers["X-Signature"] = "sig-" + request.body.len();
+            request.body = "{\"n\":" + iteration + "}";
+        "#;
+
+        let request = run_pre_request_script(script, &api_config(), "{}", 3).unwrap();
+        assert_eq!(request.url, "http://localhost/orders?page=3");
+        assert_eq!(request.headers["X-Signature"], "sig-2");
+        assert_eq!(request.headers["Content-Type"], "application/json");
+        assert_eq!(request.body, r#"{"n":3}"#);
+    }
+
+    #[test]
+    fn test_script_errors_name_the_api() {
+        let error = run_pre_request_script("throw \"no key\";", &api_config(), "", 0).unwrap_err();
+        assert!(error.starts_with("Pre-request script for 'Signed' failed:"), "{}", error);
+        assert!(error.contains("no key"), "{}", error);
+    }
+
+    #[test]
+    fn test_runaway_scripts_are_stopped() {
+        assert!(run_pre_request_script("loop {}", &api_config(), "", 0).is_err());
+        assert!(compile("request.url = ").is_err());
+    }
+}
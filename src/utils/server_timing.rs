@@ -0,0 +1,74 @@
+/// Parses a server-reported processing time, in milliseconds, from a response header.
+///
+/// The standard `Server-Timing` header (`db;dur=2.5, app;dur=10`) yields its `total` metric
+/// when present, otherwise the sum of all `dur` values. Any other header is read as a single
+/// duration such as `12.5ms`, `0.0125s`, or `12500us`; a bare number is taken as milliseconds.
+pub fn parse_processing_time(header_name: &str, value: &str) -> Option<f64> {
+    if header_name.eq_ignore_ascii_case("server-timing") {
+        parse_server_timing(value)
+    } else {
+        parse_duration_ms(value.trim())
+    }
+}
+
+fn parse_server_timing(value: &str) -> Option<f64> {
+    let metrics: Vec<(&str, f64)> = value.split(',')
+        .filter_map(|metric| {
+            let mut params = metric.split(';').map(str::trim);
+            let name = params.next()?;
+            let duration = params
+                .filter_map(|param| param.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("dur"))
+                .and_then(|(_, duration)| duration.trim().trim_matches('"').parse::<f64>().ok())?;
+            Some((name, duration))
+        })
+        .collect();
+
+    if metrics.is_empty() {
+        return None;
+    }
+
+    metrics.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("total"))
+        .map(|(_, duration)| *duration)
+        .or_else(|| Some(metrics.iter().map(|(_, duration)| duration).sum()))
+}
+
+fn parse_duration_ms(value: &str) -> Option<f64> {
+    let (number, scale) = if let Some(number) = value.strip_suffix("ms") {
+        (number, 1.0)
+    } else if let Some(number) = value.strip_suffix("us") {
+        (number, 0.001)
+    } else if let Some(number) = value.strip_suffix('s') {
+        (number, 1000.0)
+    } else {
+        (value, 1.0)
+    };
+
+    number.trim().parse::<f64>().ok().map(|duration| duration * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_timing_prefers_total() {
+        assert_eq!(parse_processing_time("Server-Timing", "db;dur=2.5, total;dur=7.25"), Some(7.25));
+    }
+
+    #[test]
+    fn test_server_timing_sums_metrics_without_total() {
+        assert_eq!(parse_processing_time("server-timing", r#"db;dur=2.5;desc="Query", app;dur=10"#), Some(12.5));
+        assert_eq!(parse_processing_time("server-timing", "cache;desc=hit"), None);
+    }
+
+    #[test]
+    fn test_plain_duration_headers() {
+        assert_eq!(parse_processing_time("X-Response-Time", "12.5ms"), Some(12.5));
+        assert_eq!(parse_processing_time("X-Response-Time", "0.5s"), Some(500.0));
+        assert_eq!(parse_processing_time("X-Response-Time", "1500us"), Some(1.5));
+        assert_eq!(parse_processing_time("X-Response-Time", " 42 "), Some(42.0));
+        assert_eq!(parse_processing_time("X-Response-Time", "soon"), None);
+    }
+}
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// One of the slowest requests of a load test.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlowRequest {
+    /// When the request completed, in milliseconds from the start of the test.
+    pub completed_at_ms: u64,
+    /// The response time in milliseconds.
+    pub response_time_ms: u64,
+    /// The HTTP status code returned.
+    pub status_code: u16,
+    /// The size of the response body in bytes.
+    pub bytes: usize,
+    /// A truncated, redacted snapshot of the response body, when body capture is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+/// A bounded record of the slowest requests seen so far.
+#[derive(Debug, Clone)]
+pub struct SlowestRequests {
+    capacity: usize,
+    entries: Vec<(Duration, SlowRequest)>,
+}
+
+impl SlowestRequests {
+    pub fn new(capacity: usize) -> Self {
+        SlowestRequests { capacity, entries: Vec::with_capacity(capacity) }
+    }
+
+    /// Whether a request taking `duration` would be kept, so callers can skip expensive work otherwise.
+    pub fn admits(&self, duration: Duration) -> bool {
+        self.entries.len() < self.capacity || self.fastest().is_some_and(|(_, fastest)| duration > fastest)
+    }
+
+    /// Records a request, evicting the fastest kept one when the buffer is full.
+    pub fn insert(&mut self, duration: Duration, request: SlowRequest) {
+        if !self.admits(duration) {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            if let Some((index, _)) = self.fastest() {
+                self.entries.swap_remove(index);
+            }
+        }
+        self.entries.push((duration, request));
+    }
+
+    /// Returns the kept requests, slowest first.
+    pub fn into_sorted(mut self) -> Vec<SlowRequest> {
+        self.entries.sort_by(|(a, _), (b, _)| b.cmp(a));
+        self.entries.into_iter().map(|(_, request)| request).collect()
+    }
+
+    fn fastest(&self) -> Option<(usize, Duration)> {
+        self.entries.iter()
+            .enumerate()
+            .min_by_key(|(_, (duration, _))| *duration)
+            .map(|(index, (duration, _))| (index, *duration))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(response_time_ms: u64) -> SlowRequest {
+        SlowRequest { completed_at_ms: 0, response_time_ms, status_code: 200, bytes: 0, body: None }
+    }
+
+    #[test]
+    fn test_keeps_only_the_slowest() {
+        let mut slowest = SlowestRequests::new(2);
+        for millis in [30, 10, 50, 20, 40] {
+            slowest.insert(Duration::from_millis(millis), request(millis));
+        }
+
+        assert!(!slowest.admits(Duration::from_millis(35)));
+        assert!(slowest.admits(Duration::from_millis(45)));
+        let kept: Vec<u64> = slowest.into_sorted().iter().map(|request| request.response_time_ms).collect();
+        assert_eq!(kept, vec![50, 40]);
+    }
+
+    #[test]
+    fn test_zero_capacity_keeps_nothing() {
+        let mut slowest = SlowestRequests::new(0);
+        slowest.insert(Duration::from_millis(10), request(10));
+        assert!(slowest.into_sorted().is_empty());
+    }
+}
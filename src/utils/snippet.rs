@@ -0,0 +1,79 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // "password": "...", "api_key": 1234, "X-Api-Key": "..." and similar JSON fields, whatever their value's type.
+    static ref SECRET_JSON_FIELD: Regex = Regex::new(
+        r#"(?i)"([a-z_-]*(?:password|passwd|secret|token|api[_-]?key|authorization|cookie)[a-z_-]*)"\s*:\s*(?:"[^"]*"|[^,{}\[\]\s]+)"#
+    ).unwrap();
+    // Authorization: ..., X-Api-Key: ... and similar header lines, as echoed in plain text bodies.
+    static ref SECRET_HEADER_LINE: Regex = Regex::new(
+        r"(?im)^([ \t]*[a-z_-]*(?:password|passwd|secret|token|api[_-]?key|authorization|cookie)[a-z_-]*)[ \t]*:[ \t]*[^\r\n]+"
+    ).unwrap();
+    // <password>...</password> and similar XML elements.
+    static ref SECRET_XML_ELEMENT: Regex = Regex::new(
+        r"(?i)<([a-z_:-]*(?:password|passwd|secret|token|api[_-]?key)[a-z_-]*)>[^<]*"
+    ).unwrap();
+    // password=...&token=... in query strings and form bodies.
+    static ref SECRET_FORM_FIELD: Regex = Regex::new(
+        r"(?i)\b([a-z_]*(?:password|passwd|secret|token|api_?key)[a-z_]*)=[^&\s]+"
+    ).unwrap();
+    static ref BEARER_TOKEN: Regex = Regex::new(r"(?i)\bbearer\s+[a-z0-9._~+/=-]+").unwrap();
+}
+
+/// Returns at most `max_bytes` of `body`, with obvious secrets redacted, for use in log and error messages.
+///
+/// Secrets are recognised by name only: JSON fields, header lines, XML elements and form fields whose
+/// name mentions a password, secret, token, API key, authorization or cookie, plus bearer tokens anywhere.
+/// A secret under any other name, such as a card number, is shown as is.
+///
+/// Redaction happens before truncation so a partially cut secret is never exposed.
+pub fn body_snippet(body: &str, max_bytes: usize) -> String {
+    let redacted = SECRET_JSON_FIELD.replace_all(body, r#""$1":"[REDACTED]""#);
+    let redacted = SECRET_HEADER_LINE.replace_all(&redacted, "$1: [REDACTED]");
+    let redacted = SECRET_XML_ELEMENT.replace_all(&redacted, "<$1>[REDACTED]");
+    let redacted = SECRET_FORM_FIELD.replace_all(&redacted, "$1=[REDACTED]");
+    let redacted = BEARER_TOKEN.replace_all(&redacted, "Bearer [REDACTED]");
+
+    if redacted.len() <= max_bytes {
+        return redacted.into_owned();
+    }
+
+    // Back off to a character boundary so multi-byte characters are never split.
+    let mut end = max_bytes;
+    while !redacted.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &redacted[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snippet_is_truncated() {
+        assert_eq!(body_snippet("hello world", 5), "hello...");
+        assert_eq!(body_snippet("short", 100), "short");
+        assert_eq!(body_snippet("héllo", 2), "h...");
+    }
+
+    #[test]
+    fn test_secrets_are_redacted() {
+        let body = r#"{"user":"ada","access_token":"abc.def","Password": "hunter2"}"#;
+        assert_eq!(body_snippet(body, 200), r#"{"user":"ada","access_token":"[REDACTED]","Password":"[REDACTED]"}"#);
+
+        assert_eq!(body_snippet("user=ada&api_key=s3cr3t", 200), "user=ada&api_key=[REDACTED]");
+        assert_eq!(body_snippet("sent Bearer eyJhbGciOi.x.y", 200), "sent Bearer [REDACTED]");
+    }
+
+    #[test]
+    fn test_secrets_outside_json_strings_are_redacted() {
+        assert_eq!(body_snippet(r#"{"pin_token": 1234, "X-Api-Key": "abc", "id": 7}"#, 200), r#"{"pin_token":"[REDACTED]", "X-Api-Key":"[REDACTED]", "id": 7}"#);
+
+        let echoed = "Host: localhost\r\nAuthorization: Basic YWRhOmh1bnRlcjI=\r\nCookie: session=abc";
+        assert_eq!(body_snippet(echoed, 200), "Host: localhost\r\nAuthorization: [REDACTED]\r\nCookie: [REDACTED]");
+
+        assert_eq!(body_snippet("<login><user>ada</user><password>hunter2</password></login>", 200), "<login><user>ada</user><password>[REDACTED]</password></login>");
+    }
+}
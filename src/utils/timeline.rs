@@ -0,0 +1,136 @@
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use crate::utils::http_client::RedirectHop;
+
+/// Most events kept for one request, so a long redirect chain cannot grow a timeline without bound.
+const MAX_TIMELINE_EVENTS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineEventKind {
+    /// The request was sent.
+    Attempt,
+    /// A redirect response arrived and was followed.
+    Redirect,
+    /// The final response arrived.
+    Response,
+    /// The request failed without a response.
+    Error,
+}
+
+/// One step in the life of a request.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimelineEvent {
+    pub kind: TimelineEventKind,
+    /// When the step ended, in milliseconds from the start of the request.
+    pub offset_ms: f64,
+    /// How long the step took, i.e. the time since the previous event.
+    pub duration_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Collects the events of a single request so a slow or failed result can be explained.
+pub struct RequestTimeline {
+    started: Instant,
+    last: Instant,
+    events: Vec<TimelineEvent>,
+    skipped_redirects: usize,
+}
+
+impl RequestTimeline {
+    /// Starts a timeline for a request sent to `url` at `started`.
+    pub fn start(started: Instant, url: &str) -> Self {
+        let mut timeline = RequestTimeline { started, last: started, events: Vec::new(), skipped_redirects: 0 };
+        timeline.push(TimelineEventKind::Attempt, started, Some(url.to_string()), None, None);
+        timeline
+    }
+
+    /// Records a redirect answered by `hop` at `at`.
+    ///
+    /// Redirects beyond the event limit are only counted, leaving room for the final outcome.
+    pub fn redirect(&mut self, hop: &RedirectHop, at: Instant) {
+        if self.events.len() + 1 >= MAX_TIMELINE_EVENTS {
+            self.skipped_redirects += 1;
+            return;
+        }
+        self.push(TimelineEventKind::Redirect, at, Some(hop.url.clone()), Some(hop.status), None);
+    }
+
+    /// Records the final response from `url`.
+    pub fn response(&mut self, url: &str, status_code: u16, at: Instant) {
+        let detail = self.skipped_summary();
+        self.push(TimelineEventKind::Response, at, Some(url.to_string()), Some(status_code), detail);
+    }
+
+    /// Records that the request failed with `message`.
+    pub fn error(&mut self, message: String, at: Instant) {
+        let detail = match self.skipped_summary() {
+            Some(skipped) => format!("{}; {}", message, skipped),
+            None => message,
+        };
+        self.push(TimelineEventKind::Error, at, None, None, Some(detail));
+    }
+
+    pub fn into_events(self) -> Vec<TimelineEvent> {
+        self.events
+    }
+
+    fn skipped_summary(&self) -> Option<String> {
+        (self.skipped_redirects > 0).then(|| format!("{} further redirects not recorded", self.skipped_redirects))
+    }
+
+    fn push(&mut self, kind: TimelineEventKind, at: Instant, url: Option<String>, status_code: Option<u16>, detail: Option<String>) {
+        let as_ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        self.events.push(TimelineEvent {
+            kind,
+            offset_ms: as_ms(at.saturating_duration_since(self.started)),
+            duration_ms: as_ms(at.saturating_duration_since(self.last)),
+            url,
+            status_code,
+            detail,
+        });
+        self.last = self.last.max(at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_measure_each_hop() {
+        let started = Instant::now();
+        let hop = RedirectHop { url: "http://localhost/old".to_string(), status: 301 };
+        let mut timeline = RequestTimeline::start(started, "http://localhost/old");
+        timeline.redirect(&hop, started + Duration::from_millis(20));
+        timeline.response("http://localhost/new", 200, started + Duration::from_millis(50));
+        let events = timeline.into_events();
+
+        let kinds: Vec<_> = events.iter().map(|event| event.kind).collect();
+        assert_eq!(kinds, [TimelineEventKind::Attempt, TimelineEventKind::Redirect, TimelineEventKind::Response]);
+        assert_eq!((events[1].offset_ms, events[1].duration_ms), (20.0, 20.0));
+        assert_eq!((events[2].offset_ms, events[2].duration_ms), (50.0, 30.0));
+        assert_eq!(events[2].status_code, Some(200));
+    }
+
+    #[test]
+    fn test_redirects_beyond_the_limit_are_counted() {
+        let started = Instant::now();
+        let hop = RedirectHop { url: "http://localhost/loop".to_string(), status: 302 };
+        let mut timeline = RequestTimeline::start(started, "http://localhost/loop");
+        for _ in 0..MAX_TIMELINE_EVENTS + 5 {
+            timeline.redirect(&hop, started);
+        }
+        timeline.error("too many redirects".to_string(), started);
+        let events = timeline.into_events();
+
+        assert_eq!(events.len(), MAX_TIMELINE_EVENTS);
+        assert_eq!(events.last().unwrap().detail.as_deref(), Some("too many redirects; 7 further redirects not recorded"));
+    }
+}
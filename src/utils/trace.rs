@@ -0,0 +1,138 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Most events kept in memory; later requests are counted but not recorded.
+const MAX_TRACE_EVENTS: usize = 100_000;
+
+static TRACE: OnceLock<Mutex<Trace>> = OnceLock::new();
+/// Lanes held by requests in flight, across every task and load test.
+static LANES: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+/// A complete ("X") event in the Chrome trace format, one per request.
+#[derive(Debug, Clone, Serialize)]
+struct TraceEvent {
+    name: String,
+    cat: &'static str,
+    ph: &'static str,
+    /// Start time in microseconds since the trace began.
+    ts: u64,
+    /// Duration in microseconds.
+    dur: u64,
+    pid: u32,
+    /// The lane the request is drawn in, so overlapping requests appear side by side.
+    tid: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<TraceArgs>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TraceArgs {
+    status_code: u16,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceFile<'a> {
+    trace_events: &'a [TraceEvent],
+    display_time_unit: &'static str,
+}
+
+struct Trace {
+    path: PathBuf,
+    started: Instant,
+    events: Vec<TraceEvent>,
+    dropped: usize,
+}
+
+/// Starts collecting request events to be written to `path` by [`write`].
+pub fn start(path: PathBuf) {
+    let _ = TRACE.set(Mutex::new(Trace { path, started: Instant::now(), events: Vec::new(), dropped: 0 }));
+}
+
+/// Whether a trace is being collected, so callers can skip building events otherwise.
+pub fn is_tracing() -> bool {
+    TRACE.get().is_some()
+}
+
+/// The lane a request in flight is drawn in. No other request takes it until this one is dropped, so
+/// overlapping requests never share a lane; the lowest free lane is reused to keep the trace compact.
+pub struct Lane(usize);
+
+impl Lane {
+    pub fn acquire() -> Lane {
+        let mut lanes = LANES.lock().unwrap_or_else(PoisonError::into_inner);
+        let lane = (0..).find(|lane| !lanes.contains(lane)).unwrap_or_default();
+        lanes.insert(lane);
+        Lane(lane)
+    }
+}
+
+impl Drop for Lane {
+    fn drop(&mut self) {
+        LANES.lock().unwrap_or_else(PoisonError::into_inner).remove(&self.0);
+    }
+}
+
+/// Records one request that began at `started` and took `duration`, drawn in `lane`.
+pub fn record(name: &str, category: &'static str, started: Instant, duration: Duration, lane: &Lane, status_code: Option<u16>) {
+    let Some(trace) = TRACE.get() else {
+        return;
+    };
+
+    let mut trace = trace.lock().unwrap_or_else(PoisonError::into_inner);
+    if trace.events.len() >= MAX_TRACE_EVENTS {
+        trace.dropped += 1;
+        return;
+    }
+    let ts = started.saturating_duration_since(trace.started).as_micros() as u64;
+    trace.events.push(TraceEvent {
+        name: name.to_string(),
+        cat: category,
+        ph: "X",
+        ts,
+        dur: duration.as_micros() as u64,
+        pid: std::process::id(),
+        tid: lane.0,
+        args: status_code.map(|status_code| TraceArgs { status_code }),
+    });
+}
+
+/// Writes the events collected so far as a JSON file loadable in `chrome://tracing` or Perfetto.
+pub fn write() {
+    let Some(trace) = TRACE.get() else {
+        return;
+    };
+
+    let trace = trace.lock().unwrap_or_else(PoisonError::into_inner);
+    if trace.dropped > 0 {
+        log::warn!("Trace reached {} events; {} later requests were not recorded", MAX_TRACE_EVENTS, trace.dropped);
+    }
+    let file = TraceFile { trace_events: &trace.events, display_time_unit: "ms" };
+    let written = serde_json::to_vec(&file)
+        .map_err(std::io::Error::other)
+        .and_then(|json| std::fs::write(&trace.path, json));
+    match written {
+        Ok(()) => log::info!("Wrote trace of {} requests to {}", trace.events.len(), trace.path.display()),
+        Err(e) => log::error!("Failed to write trace to {}: {}", trace.path.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlapping_requests_get_distinct_lanes() {
+        let first = Lane::acquire();
+        let second = Lane::acquire();
+        assert_ne!(first.0, second.0);
+
+        // A released lane is reused, while one still held is not.
+        let released = first.0;
+        drop(first);
+        assert_eq!(Lane::acquire().0, released);
+    }
+}
@@ -0,0 +1,309 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use lazy_static::lazy_static;
+use reqwest::Url;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+
+/// Appended to the client's key before hashing, as RFC 6455 prescribes for the `Sec-WebSocket-Accept` header.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The largest handshake response read before giving up on the server.
+const MAX_HANDSHAKE_BYTES: usize = 16 * 1024;
+
+/// The largest message accepted from the server, whether in one frame or reassembled from several.
+const MAX_FRAME_BYTES: u64 = 16 * 1024 * 1024;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+lazy_static! {
+    // TLS settings for `wss://` URLs by the `ca_cert_path` they trust, built once for each.
+    static ref TLS_CONNECTORS: Mutex<HashMap<Option<String>, TlsConnector>> = Mutex::new(HashMap::new());
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// A data message received from the server.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// A client connection to a WebSocket server, over `ws://` or `wss://`.
+///
+/// Only what a load test needs is supported: text messages out, any data message in, answering pings,
+/// and closing. Extensions such as compression are not negotiated.
+///
+/// This is a small implementation of RFC 6455 rather than tokio-tungstenite, which is not yet among the
+/// project's dependencies; it should be swapped for that crate once it can be added, keeping this interface.
+pub struct WsConnection {
+    stream: Box<dyn Stream>,
+}
+
+impl WsConnection {
+    /// Opens a connection to `url` and completes the opening handshake, sending `headers` with it.
+    ///
+    /// `wss://` servers must present a certificate from the web roots the HTTP client trusts, or from
+    /// the PEM CA certificate at `ca_cert_path`.
+    pub async fn connect(url: &str, headers: &[(String, String)], ca_cert_path: Option<&str>) -> Result<Self, String> {
+        let url = Url::parse(url).map_err(|e| format!("Invalid WebSocket URL '{}': {}", url, e))?;
+        let secure = match url.scheme() {
+            "ws" => false,
+            "wss" => true,
+            scheme => return Err(format!("WebSocket URLs must start with ws:// or wss://, not {}://", scheme)),
+        };
+        // IPv6 literals keep their brackets in the URL, but not in a socket address or TLS server name.
+        let host = url.host_str().ok_or_else(|| format!("WebSocket URL '{}' has no host", url))?
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let port = url.port_or_known_default().unwrap_or(if secure { 443 } else { 80 });
+
+        let tcp = TcpStream::connect((host, port)).await.map_err(|e| format!("Error connecting to {}:{}: {}", host, port, e))?;
+        tcp.set_nodelay(true).map_err(|e| e.to_string())?;
+        let stream: Box<dyn Stream> = if secure {
+            let server_name = ServerName::try_from(host).map_err(|e| format!("Invalid TLS server name '{}': {}", host, e))?;
+            Box::new(tls_connector(ca_cert_path)?.connect(server_name, tcp).await.map_err(|e| format!("TLS handshake with {} failed: {}", host, e))?)
+        } else {
+            Box::new(tcp)
+        };
+
+        let mut connection = WsConnection { stream };
+        connection.handshake(&url, headers).await?;
+        Ok(connection)
+    }
+
+    async fn handshake(&mut self, url: &Url, headers: &[(String, String)]) -> Result<(), String> {
+        let key = STANDARD.encode(rand::random::<[u8; 16]>());
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let mut request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n",
+            path, host, key
+        );
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        self.stream.write_all(request.as_bytes()).await.map_err(|e| format!("Error sending the WebSocket handshake: {}", e))?;
+
+        // The response is read a byte at a time, so nothing the server sends after it is consumed here.
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() >= MAX_HANDSHAKE_BYTES {
+                return Err("WebSocket handshake response is too large".to_string());
+            }
+            let byte = self.stream.read_u8().await.map_err(|e| format!("Error reading the WebSocket handshake: {}", e))?;
+            response.push(byte);
+        }
+        let response = String::from_utf8_lossy(&response);
+        let mut lines = response.lines();
+        let status_line = lines.next().unwrap_or_default();
+        if status_line.split_whitespace().nth(1) != Some("101") {
+            return Err(format!("WebSocket upgrade refused: {}", status_line));
+        }
+        let accept = lines
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("sec-websocket-accept"))
+            .map(|(_, value)| value.trim().to_string());
+        if accept.as_deref() != Some(accept_key(&key).as_str()) {
+            return Err("WebSocket handshake response has a missing or wrong Sec-WebSocket-Accept header".to_string());
+        }
+        Ok(())
+    }
+
+    /// Sends a text message.
+    pub async fn send_text(&mut self, text: &str) -> Result<(), String> {
+        self.send_frame(OPCODE_TEXT, text.as_bytes()).await
+    }
+
+    /// Waits for the next data message, answering pings on the way.
+    ///
+    /// Fails once the server closes the connection, with the close code and reason it gave.
+    pub async fn next_message(&mut self) -> Result<Message, String> {
+        let mut fragments: Option<(u8, Vec<u8>)> = None;
+        loop {
+            let (fin, opcode, payload) = self.read_frame().await?;
+            match opcode {
+                OPCODE_PING => self.send_frame(OPCODE_PONG, &payload).await?,
+                OPCODE_PONG => {},
+                OPCODE_CLOSE => {
+                    let _ = self.send_frame(OPCODE_CLOSE, &payload).await;
+                    return Err(close_description(&payload));
+                },
+                OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                    let (message_opcode, data) = match (fragments.take(), opcode) {
+                        (Some((message_opcode, mut data)), OPCODE_CONTINUATION) => {
+                            if (data.len() + payload.len()) as u64 > MAX_FRAME_BYTES {
+                                return Err(format!("WebSocket message is larger than {} bytes", MAX_FRAME_BYTES));
+                            }
+                            data.extend_from_slice(&payload);
+                            (message_opcode, data)
+                        },
+                        (None, OPCODE_CONTINUATION) => return Err("WebSocket continuation frame without a message to continue".to_string()),
+                        (Some(_), _) => return Err("WebSocket message started before the previous one ended".to_string()),
+                        (None, _) => (opcode, payload),
+                    };
+                    if !fin {
+                        fragments = Some((message_opcode, data));
+                        continue;
+                    }
+                    return match message_opcode {
+                        OPCODE_TEXT => String::from_utf8(data).map(Message::Text).map_err(|_| "WebSocket text message is not valid UTF-8".to_string()),
+                        _ => Ok(Message::Binary(data)),
+                    };
+                },
+                opcode => return Err(format!("Unknown WebSocket opcode {:#x}", opcode)),
+            }
+        }
+    }
+
+    /// Starts the closing handshake with a normal closure; the server's reply is not waited for.
+    pub async fn close(mut self) -> Result<(), String> {
+        self.send_frame(OPCODE_CLOSE, &1000u16.to_be_bytes()).await?;
+        self.stream.shutdown().await.map_err(|e| e.to_string())
+    }
+
+    /// Writes one final frame; frames from a client are always masked.
+    async fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<(), String> {
+        let mask: [u8; 4] = rand::random();
+        let mut frame = Vec::with_capacity(payload.len() + 14);
+        frame.push(0x80 | opcode);
+        match payload.len() {
+            len if len < 126 => frame.push(0x80 | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            },
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            },
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(index, byte)| byte ^ mask[index % 4]));
+        self.stream.write_all(&frame).await.map_err(|e| format!("Error sending a WebSocket frame: {}", e))
+    }
+
+    /// Reads one frame, returning whether it is final, its opcode, and its unmasked payload.
+    async fn read_frame(&mut self) -> Result<(bool, u8, Vec<u8>), String> {
+        let disconnected = |e: std::io::Error| format!("WebSocket connection lost: {}", e);
+        let mut header = [0u8; 2];
+        self.stream.read_exact(&mut header).await.map_err(disconnected)?;
+        let fin = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0F;
+        let masked = header[1] & 0x80 != 0;
+        let len = match header[1] & 0x7F {
+            126 => self.stream.read_u16().await.map_err(disconnected)? as u64,
+            127 => self.stream.read_u64().await.map_err(disconnected)?,
+            len => len as u64,
+        };
+        if len > MAX_FRAME_BYTES {
+            return Err(format!("WebSocket frame of {} bytes is larger than {} bytes", len, MAX_FRAME_BYTES));
+        }
+        let mut mask = [0u8; 4];
+        if masked {
+            self.stream.read_exact(&mut mask).await.map_err(disconnected)?;
+        }
+        let mut payload = vec![0u8; len as usize];
+        self.stream.read_exact(&mut payload).await.map_err(disconnected)?;
+        if masked {
+            payload.iter_mut().enumerate().for_each(|(index, byte)| *byte ^= mask[index % 4]);
+        }
+        Ok((fin, opcode, payload))
+    }
+}
+
+/// The TLS connector trusting the web roots and, if given, the PEM CA certificate at `ca_cert_path`.
+fn tls_connector(ca_cert_path: Option<&str>) -> Result<TlsConnector, String> {
+    let key = ca_cert_path.map(str::to_string);
+    if let Some(connector) = TLS_CONNECTORS.lock().unwrap_or_else(PoisonError::into_inner).get(&key) {
+        return Ok(connector.clone());
+    }
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(anchor.subject, anchor.spki, anchor.name_constraints)
+    }));
+    if let Some(path) = ca_cert_path {
+        let pem = std::fs::read(path).map_err(|e| format!("Failed to read CA certificate at {}: {}", path, e))?;
+        let certificates = rustls_pemfile::certs(&mut pem.as_slice()).map_err(|e| format!("Invalid CA certificate in {}: {}", path, e))?;
+        if certificates.is_empty() {
+            return Err(format!("Invalid CA certificate in {}: no certificate found", path));
+        }
+        for certificate in certificates {
+            roots.add(&Certificate(certificate)).map_err(|e| format!("Invalid CA certificate in {}: {}", path, e))?;
+        }
+    }
+    let config = ClientConfig::builder().with_safe_defaults().with_root_certificates(roots).with_no_client_auth();
+    let connector = TlsConnector::from(Arc::new(config));
+    TLS_CONNECTORS.lock().unwrap_or_else(PoisonError::into_inner).insert(key, connector.clone());
+    Ok(connector)
+}
+
+/// The `Sec-WebSocket-Accept` value a server must answer `key` with.
+fn accept_key(key: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key.as_bytes());
+    sha1.update(ACCEPT_GUID.as_bytes());
+    STANDARD.encode(sha1.finalize())
+}
+
+/// Describes a close frame's payload: a status code, then an optional UTF-8 reason.
+fn close_description(payload: &[u8]) -> String {
+    match payload {
+        [high, low, reason @ ..] => {
+            let code = u16::from_be_bytes([*high, *low]);
+            match String::from_utf8_lossy(reason).trim() {
+                "" => format!("WebSocket closed by the server with code {}", code),
+                reason => format!("WebSocket closed by the server with code {}: {}", code, reason),
+            }
+        },
+        _ => "WebSocket closed by the server".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_key_matches_the_rfc_example() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn test_close_payload_is_described() {
+        assert_eq!(close_description(&[0x03, 0xE8]), "WebSocket closed by the server with code 1000");
+        assert_eq!(close_description(b"\x03\xF3going away"), "WebSocket closed by the server with code 1011: going away");
+        assert_eq!(close_description(&[]), "WebSocket closed by the server");
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frames_are_refused_before_reading_them() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut connection = WsConnection { stream: Box::new(client) };
+        let mut header = vec![0x82, 127];
+        header.extend_from_slice(&u64::MAX.to_be_bytes());
+        server.write_all(&header).await.unwrap();
+
+        let error = connection.next_message().await.unwrap_err();
+        assert!(error.contains("larger than 16777216 bytes"), "{}", error);
+    }
+}
@@ -0,0 +1,47 @@
+use crate::utils::random_body::iteration_rng;
+
+/// Picks among options in proportion to their weights.
+#[derive(Debug, Clone)]
+pub struct WeightedPicker {
+    /// The running total of the weights, so a pick is a search for the first total above a random draw.
+    cumulative: Vec<u64>,
+}
+
+impl WeightedPicker {
+    /// A picker over `weights`, or `None` when they sum to 0 and nothing could ever be picked.
+    pub fn new(weights: impl IntoIterator<Item = u32>) -> Option<Self> {
+        let cumulative: Vec<u64> = weights.into_iter()
+            .scan(0, |total, weight| {
+                *total += u64::from(weight);
+                Some(*total)
+            })
+            .collect();
+        (cumulative.last().copied().unwrap_or_default() > 0).then_some(WeightedPicker { cumulative })
+    }
+
+    /// The index of the option picked for `iteration`; the same iteration always picks the same option.
+    pub fn pick(&self, iteration: u64) -> usize {
+        let total = self.cumulative[self.cumulative.len() - 1];
+        let draw = iteration_rng(0, iteration).between(0, total as usize - 1) as u64;
+        self.cumulative.partition_point(|cumulative| *cumulative <= draw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_picks_follow_the_weights() {
+        let picker = WeightedPicker::new([10, 0, 1]).unwrap();
+        let mut counts = [0; 3];
+        for iteration in 0..11_000 {
+            counts[picker.pick(iteration)] += 1;
+        }
+
+        assert_eq!(counts[1], 0);
+        assert!((9_500..=10_500).contains(&counts[0]), "{:?}", counts);
+        assert_eq!(picker.pick(42), picker.pick(42));
+        assert!(WeightedPicker::new([0, 0]).is_none());
+    }
+}
@@ -0,0 +1,355 @@
+use std::sync::Arc;
+use std::time::Duration;
+use futures::future::join_all;
+use log::{info, error};
+use reqwest::Client;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tokio::time::{timeout, Instant};
+use tokio_util::sync::CancellationToken;
+
+use crate::{appstate::AppState, config::{ApiConfig, Settings, WebSocketConfig}, factory::ApiMonitor, utils::{interpolate::resolve_variables, response_times::ResponseTimeStats, websocket::WsConnection}};
+
+/// How long to wait for a reply to a message when `reply_timeout_ms` is not set.
+const DEFAULT_REPLY_TIMEOUT_MS: u64 = 5000;
+
+/// How long opening a connection may take when the API sets no `timeout_seconds`.
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 30;
+
+/// Represents the data collected by one WebSocket load test.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebSocketMonitoringData {
+    pub api_url: String,
+    /// "OK" when every connection opened and every message was answered, otherwise "ERROR".
+    pub status: String,
+    /// Connections attempted.
+    pub connections: usize,
+    /// Connections that completed the opening handshake.
+    pub connected: usize,
+    /// Time to open a connection, handshake included, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_time: Option<ResponseTimeStats>,
+    pub messages_sent: usize,
+    /// Messages answered before `reply_timeout_ms`.
+    pub replies_received: usize,
+    /// Time from sending a message to receiving its reply, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub round_trip_time: Option<ResponseTimeStats>,
+    /// Messages that went unanswered for `reply_timeout_ms`.
+    pub reply_timeouts: usize,
+    /// Connections that failed to open.
+    pub connect_errors: usize,
+    /// Open connections the server closed, or that broke, before their messages were done.
+    pub disconnect_errors: usize,
+}
+
+/// What happened on one connection.
+#[derive(Default)]
+struct ConnectionOutcome {
+    connect_time: Option<Duration>,
+    round_trips: Vec<Duration>,
+    messages_sent: usize,
+    reply_timeouts: usize,
+    connect_error: Option<String>,
+    disconnect_error: Option<String>,
+}
+
+/// Opens `connections` WebSocket connections to an API at once and measures the messages exchanged over them.
+pub struct WsLoadTest {
+    /// Configuration of the WebSocket endpoint, whose headers are sent with each handshake.
+    pub api_config: Arc<ApiConfig>,
+    /// A reference to the shared application state where results are recorded.
+    pub app_state: Arc<Mutex<AppState>>,
+    pub websocket_config: WebSocketConfig,
+    /// Global settings, consulted for the minimum samples behind percentiles and the CA certificate to trust.
+    pub settings: Arc<Settings>,
+}
+
+impl WsLoadTest {
+    /// Runs one connection: opens it, sends each message in turn and waits for its reply, then closes it.
+    async fn run_connection(&self, headers: &[(String, String)], messages: &[String], cancel: &CancellationToken) -> ConnectionOutcome {
+        let mut outcome = ConnectionOutcome::default();
+        let connect_timeout = Duration::from_secs(self.api_config.timeout_seconds.unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS));
+        let reply_timeout = Duration::from_millis(self.websocket_config.reply_timeout_ms.unwrap_or(DEFAULT_REPLY_TIMEOUT_MS));
+
+        let start = Instant::now();
+        let connect = timeout(connect_timeout, WsConnection::connect(&self.api_config.url, headers, self.settings.ca_cert_path.as_deref()));
+        // A cancelled run abandons a connection still opening; it counts as neither opened nor failed.
+        let connected = tokio::select! {
+            connected = connect => connected,
+            _ = cancel.cancelled() => return outcome,
+        };
+        let mut connection = match connected {
+            Ok(Ok(connection)) => connection,
+            Ok(Err(e)) => {
+                outcome.connect_error = Some(e);
+                return outcome;
+            },
+            Err(_) => {
+                outcome.connect_error = Some(format!("WebSocket connection timed out after {:?}", connect_timeout));
+                return outcome;
+            },
+        };
+        outcome.connect_time = Some(start.elapsed());
+
+        'rounds: for _ in 0..self.websocket_config.rounds.unwrap_or(1) {
+            for message in messages {
+                // A cancelled run stops between messages; the connection is still closed cleanly.
+                if cancel.is_cancelled() {
+                    break 'rounds;
+                }
+                let sent = Instant::now();
+                if let Err(e) = connection.send_text(message).await {
+                    outcome.disconnect_error = Some(e);
+                    return outcome;
+                }
+                outcome.messages_sent += 1;
+                let reply = tokio::select! {
+                    reply = timeout(reply_timeout, connection.next_message()) => reply,
+                    // The message went unanswered only because the run stopped, so it is not a timeout.
+                    _ = cancel.cancelled() => {
+                        outcome.messages_sent -= 1;
+                        break 'rounds;
+                    },
+                };
+                match reply {
+                    Ok(Ok(_)) => outcome.round_trips.push(sent.elapsed()),
+                    Ok(Err(e)) => {
+                        outcome.disconnect_error = Some(e);
+                        return outcome;
+                    },
+                    Err(_) => outcome.reply_timeouts += 1,
+                }
+            }
+        }
+
+        if let Err(e) = connection.close().await {
+            outcome.disconnect_error = Some(e);
+        }
+        outcome
+    }
+}
+
+#[async_trait::async_trait]
+impl ApiMonitor for WsLoadTest {
+
+    async fn execute(&self, _client: &Client, workflow_name: &str, cancel: &CancellationToken) -> Result<(), String> {
+        let variables = self.app_state.lock().await.variables(workflow_name).await;
+        let headers: Vec<(String, String)> = self.api_config.headers.iter()
+            .map(|(name, value)| (name.clone(), resolve_variables(value, &variables).into_owned()))
+            .collect();
+        let messages: Vec<String> = self.websocket_config.messages.iter().flatten()
+            .map(|message| resolve_variables(message, &variables).into_owned())
+            .collect();
+        let connections = self.websocket_config.connections.unwrap_or(1);
+
+        let outcomes = join_all((0..connections).map(|_| self.run_connection(&headers, &messages, cancel))).await;
+
+        let errors: Vec<&str> = outcomes.iter()
+            .filter_map(|outcome| outcome.connect_error.as_deref().or(outcome.disconnect_error.as_deref()))
+            .collect();
+        if let Some(e) = errors.first() {
+            error!("WebSocket load test '{}' had {} failed connections, e.g.: {}", self.api_config.name, errors.len(), e);
+        }
+        self.app_state.lock().await.record_errors(&self.api_config.url, errors.iter().copied()).await;
+
+        let min_samples = self.settings.min_samples_for_percentiles.unwrap_or(0);
+        let millis = |durations: Vec<Duration>| durations.into_iter().map(|duration| duration.as_millis() as u64).collect();
+        let reply_timeouts: usize = outcomes.iter().map(|outcome| outcome.reply_timeouts).sum();
+        let data = WebSocketMonitoringData {
+            api_url: self.api_config.url.clone(),
+            status: if errors.is_empty() && reply_timeouts == 0 { "OK" } else { "ERROR" }.to_string(),
+            connections,
+            connected: outcomes.iter().filter(|outcome| outcome.connect_time.is_some()).count(),
+            connect_time: ResponseTimeStats::from_times(millis(outcomes.iter().filter_map(|outcome| outcome.connect_time).collect()), min_samples),
+            messages_sent: outcomes.iter().map(|outcome| outcome.messages_sent).sum(),
+            replies_received: outcomes.iter().map(|outcome| outcome.round_trips.len()).sum(),
+            round_trip_time: ResponseTimeStats::from_times(millis(outcomes.iter().flat_map(|outcome| outcome.round_trips.iter().copied()).collect()), min_samples),
+            reply_timeouts,
+            connect_errors: outcomes.iter().filter(|outcome| outcome.connect_error.is_some()).count(),
+            disconnect_errors: outcomes.iter().filter(|outcome| outcome.disconnect_error.is_some()).count(),
+        };
+
+        info!(
+            "WebSocket load test '{}' opened {} of {} connections and received {} of {} replies",
+            self.api_config.name, data.connected, data.connections, data.replies_received, data.messages_sent
+        );
+        let status = data.status.clone();
+        update_websocket_app_state(&self.app_state, workflow_name, &self.api_config.name, data).await;
+
+        if status == "OK" {
+            Ok(())
+        } else {
+            Err(format!("WebSocket load test '{}' had {} failed connections and {} unanswered messages", self.api_config.name, errors.len(), reply_timeouts))
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("WebSocket load test for {}", self.api_config.name)
+    }
+
+    fn response_time_threshold(&self) -> Option<u64> {
+        None
+    }
+
+    fn get_task_order(&self) -> usize {
+        self.api_config.task_order.unwrap_or(usize::MAX)
+    }
+}
+
+/// Updates the shared application state with the results of a WebSocket load test.
+async fn update_websocket_app_state(
+    app_state: &Arc<Mutex<AppState>>,
+    workflow_name: &str,
+    task_name: &str,
+    websocket_data: WebSocketMonitoringData
+) {
+    let state = app_state.lock().await;
+    let websocket_monitoring_data = &mut *state.websocket_monitoring_data.lock().await;
+
+    websocket_monitoring_data
+        .entry(workflow_name.to_string())
+        .or_default()
+        .insert(task_name.to_string(), websocket_data);
+
+    log::info!("Updated WebSocket data for {} in workflow {}", task_name, workflow_name);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::settings;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use sha1::{Digest, Sha1};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use crate::utils::websocket::Message;
+
+    /// Accepts WebSocket connections on `address` and echoes each text message back in an unmasked frame.
+    async fn serve_echo(address: &str) -> String {
+        let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+        let url = format!("ws://{}/echo", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    while !request.ends_with(b"\r\n\r\n") {
+                        request.push(socket.read_u8().await.unwrap());
+                    }
+                    let request = String::from_utf8(request).unwrap();
+                    let key = request.lines()
+                        .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+                        .unwrap();
+                    let accept = STANDARD.encode(Sha1::digest(format!("{}258EAFA5-E914-47DA-95CA-C5AB0DC85B11", key)));
+                    let response = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n", accept);
+                    socket.write_all(response.as_bytes()).await.unwrap();
+
+                    loop {
+                        let mut header = [0u8; 2];
+                        if socket.read_exact(&mut header).await.is_err() {
+                            return;
+                        }
+                        let len = (header[1] & 0x7F) as usize;
+                        let mut mask = [0u8; 4];
+                        socket.read_exact(&mut mask).await.unwrap();
+                        let mut payload = vec![0u8; len];
+                        socket.read_exact(&mut payload).await.unwrap();
+                        payload.iter_mut().enumerate().for_each(|(index, byte)| *byte ^= mask[index % 4]);
+                        if header[0] & 0x0F == 0x8 {
+                            return;
+                        }
+                        let mut frame = vec![0x81, len as u8];
+                        frame.extend_from_slice(&payload);
+                        socket.write_all(&frame).await.unwrap();
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    fn api_config(url: &str) -> ApiConfig {
+        serde_yaml::from_str(&format!(r#"
+name: "Chat"
+url: "{}"
+method: GET
+headers: {{}}
+expected_field: ""
+response_time_threshold: 1000
+protocol: websocket
+"#, url)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_connections_exchange_messages_and_record_round_trips() {
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let websocket_config = WebSocketConfig { connections: Some(3), messages: Some(vec!["ping".to_string(), "hello".to_string()]), rounds: Some(2), reply_timeout_ms: None };
+        let load_test = WsLoadTest { api_config: Arc::new(api_config(&serve_echo("127.0.0.1:0").await)), app_state: app_state.clone(), websocket_config, settings: Arc::new(settings()) };
+
+        load_test.execute(&Client::new(), "Live", &CancellationToken::new()).await.unwrap();
+
+        let results = app_state.lock().await.websocket_monitoring_data.lock().await.clone();
+        let data = &results["Live"]["Chat"];
+        assert_eq!((data.status.as_str(), data.connections, data.connected), ("OK", 3, 3));
+        assert_eq!((data.messages_sent, data.replies_received, data.reply_timeouts), (12, 12, 0));
+        assert_eq!(data.round_trip_time.as_ref().unwrap().count, 12);
+        assert_eq!(data.connect_time.as_ref().unwrap().count, 3);
+    }
+
+    #[tokio::test]
+    async fn test_refused_connections_are_connect_errors() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let websocket_config = WebSocketConfig { connections: Some(2), ..WebSocketConfig::default() };
+        let load_test = WsLoadTest { api_config: Arc::new(api_config(&url)), app_state: app_state.clone(), websocket_config, settings: Arc::new(settings()) };
+
+        assert!(load_test.execute(&Client::new(), "Live", &CancellationToken::new()).await.is_err());
+
+        let results = app_state.lock().await.websocket_monitoring_data.lock().await.clone();
+        let data = &results["Live"]["Chat"];
+        assert_eq!((data.status.as_str(), data.connected, data.connect_errors), ("ERROR", 0, 2));
+        assert!(data.connect_time.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancelling_stops_connections_still_opening() {
+        // Accepts connections but never completes their handshake.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/", listener.local_addr().unwrap());
+        let app_state = Arc::new(Mutex::new(AppState::default()));
+        let load_test = WsLoadTest { api_config: Arc::new(api_config(&url)), app_state: app_state.clone(), websocket_config: WebSocketConfig::default(), settings: Arc::new(settings()) };
+        let cancel = CancellationToken::new();
+        let cancel_soon = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cancel_soon.cancel();
+        });
+
+        let started = Instant::now();
+        load_test.execute(&Client::new(), "Live", &cancel).await.unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(2), "took {:?}", started.elapsed());
+        let results = app_state.lock().await.websocket_monitoring_data.lock().await.clone();
+        assert_eq!((results["Live"]["Chat"].connected, results["Live"]["Chat"].connect_errors), (0, 0));
+        drop(listener);
+    }
+
+    #[tokio::test]
+    async fn test_ipv6_literal_urls_connect() {
+        let url = serve_echo("[::1]:0").await;
+        assert!(url.starts_with("ws://[::1]:"), "{}", url);
+
+        let mut connection = WsConnection::connect(&url, &[], None).await.unwrap();
+        connection.send_text("hello").await.unwrap();
+        assert_eq!(connection.next_message().await.unwrap(), Message::Text("hello".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_secure_connections_report_an_unreadable_ca_certificate() {
+        let url = serve_echo("127.0.0.1:0").await.replacen("ws://", "wss://", 1);
+
+        let error = WsConnection::connect(&url, &[], Some("/nonexistent/ca.pem")).await.err().unwrap();
+        assert!(error.contains("Failed to read CA certificate at /nonexistent/ca.pem"), "{}", error);
+    }
+}